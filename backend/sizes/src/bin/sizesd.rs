@@ -0,0 +1,72 @@
+use std::env;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+use getopts::Options;
+
+use sizes::config;
+use sizes::server;
+
+const USAGE: &str = "Usage: sizesd [options]
+
+Serves the sizes HTTP API under /sizes without the desktop app, and the
+built frontend under / when --static-dir is given.";
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let mut opts = Options::new();
+    opts.optopt("", "config", "config file, defaults to ~/.config/sizes/config.toml", "FILE");
+    opts.optopt("", "address", "address to listen on, defaults to 127.0.0.1", "IP");
+    opts.optopt("", "port", "port to listen on, defaults to 8000", "PORT");
+    opts.optopt("", "db", "database path, defaults to ~/.config/sizes/app.db", "PATH");
+    opts.optopt("", "static-dir", "directory of the built frontend to serve at /", "DIR");
+    opts.optflag("h", "help", "print this help");
+
+    let matches = match opts.parse(&args) {
+        Ok(m) => m,
+        Err(e) => fail(e.to_string()),
+    };
+    if matches.opt_present("h") {
+        println!("{}", opts.usage(USAGE));
+        return;
+    }
+
+    let config = match config::load(matches.opt_str("config").as_deref().map(Path::new)) {
+        Ok(config) => config,
+        Err(e) => fail(e.to_string()),
+    };
+    let mut options = config.server.clone();
+    if let Some(address) = matches.opt_str("address") {
+        match address.parse::<IpAddr>() {
+            Ok(address) => options.address = Some(address),
+            Err(_) => fail(format!("invalid address {:?}", address)),
+        }
+    }
+    if let Some(port) = matches.opt_str("port") {
+        match port.parse::<u16>() {
+            Ok(port) => options.port = Some(port),
+            Err(_) => fail(format!("invalid port {:?}", port)),
+        }
+    }
+    if let Some(dir) = matches.opt_str("static-dir") {
+        let dir = PathBuf::from(dir);
+        if !dir.is_dir() {
+            fail(format!("static dir {} is not a directory", dir.display()));
+        }
+        options.static_dir = Some(dir);
+    }
+
+    let db_path = matches.opt_str("db").map(PathBuf::from).unwrap_or(config.db.path.clone());
+    let client = sizes::init_with(&db_path).await;
+    if let Err(e) = server::build(client, &options).launch().await {
+        fail(e.to_string());
+    }
+}
+
+fn fail(msg: String) -> ! {
+    eprintln!("{}", msg);
+    exit(1)
+}
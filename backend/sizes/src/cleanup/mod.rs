@@ -0,0 +1,386 @@
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::conf;
+use crate::db::dirstat::{self, DirStatBatch};
+use crate::db::key::{key_path, split_scan_result_key};
+use crate::db::{TABLE_AUDIT_LOG, TABLE_CONF, TABLE_DIR_SCAN_RESULT};
+use crate::kvstore::{Batch, KvStore};
+use crate::scan::DirScanOverview;
+use crate::{home_dir, random_token, unix, Error};
+
+// a ticket has to be confirmed within this many seconds
+const TICKET_TTL: u64 = 300;
+
+// nothing inside these is cleaned up, whatever root was scanned. every
+// other top level dir is refused only itself
+const SYSTEM_DIRS: [&str; 13] = [
+    "/bin", "/boot", "/dev", "/etc", "/lib", "/lib32", "/lib64", "/proc", "/sbin", "/sys", "/usr", "/System",
+    "/private/etc",
+];
+
+static CONF_KEY_CLEANUP_PREFIX: &str = "cleanup,";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum CleanupAction {
+    Delete,
+    Trash,
+}
+
+impl FromStr for CleanupAction {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> crate::Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "delete" => Ok(CleanupAction::Delete),
+            "trash" => Ok(CleanupAction::Trash),
+            _ => Err(format!("unknown cleanup action {:?}, expect delete or trash", s).into()),
+        }
+    }
+}
+
+impl Display for CleanupAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CleanupAction::Delete => write!(f, "delete"),
+            CleanupAction::Trash => write!(f, "trash"),
+        }
+    }
+}
+
+// what the user is asked to confirm, the action is only run after
+// the token is handed back through `confirm`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CleanupTicket {
+    pub token: String,
+    pub path: PathBuf,
+    pub action: CleanupAction,
+    pub overview: DirScanOverview,
+    pub expires: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AuditRecord {
+    pub ts: u64,
+    pub token: String,
+    pub path: PathBuf,
+    pub action: CleanupAction,
+    pub overview: DirScanOverview,
+    pub trashed_to: Option<PathBuf>,
+    pub error: Option<String>,
+}
+
+pub fn prepare(
+    db: &impl KvStore,
+    path: &Path,
+    action: CleanupAction,
+) -> crate::Result<CleanupTicket> {
+    check_target(db, path)?;
+    let meta = path.symlink_metadata()?;
+
+    let overview = if meta.is_dir() {
+        dirstat::get_subtree_overview(db, path)
+    } else {
+        let mut overview = DirScanOverview::new();
+        overview.files = 1;
+        overview.blocks = meta.blocks();
+        overview
+    };
+
+    drop_expired_tickets(db)?;
+    let ticket = CleanupTicket {
+        token: random_token()?,
+        path: PathBuf::from(path),
+        action,
+        overview,
+        expires: unix() + TICKET_TTL,
+    };
+    db.set_json(TABLE_CONF, ticket_key(&ticket.token), &ticket)?;
+    Ok(ticket)
+}
+
+// the ticket of a token not yet confirmed, e.g. to learn the path first
+pub fn find_ticket(db: &impl KvStore, token: &str) -> crate::Result<CleanupTicket> {
+    let Some(ticket) = db.get_as::<CleanupTicket>(TABLE_CONF, ticket_key(token))? else {
+        return Err(Error::NotFound(format!("unknown cleanup token {}", token)));
+    };
+    if ticket.expires < unix() {
+        return Err(Error::NotFound(format!("cleanup token {} expired", token)));
+    }
+    Ok(ticket)
+}
+
+// runs the action of the ticket. it may take long on a large tree, the
+// server runs it through `TaskManager::run_on_path`
+pub fn confirm(db: &impl KvStore, token: &str) -> crate::Result<AuditRecord> {
+    let ticket = find_ticket(db, token);
+    db.delete(TABLE_CONF, ticket_key(token))?;
+    let ticket = ticket?;
+
+    let mut record = AuditRecord {
+        ts: unix(),
+        token: ticket.token.clone(),
+        path: ticket.path.clone(),
+        action: ticket.action,
+        overview: ticket.overview.clone(),
+        trashed_to: None,
+        error: None,
+    };
+
+    let is_dir = ticket.path.symlink_metadata().map(|m| m.is_dir()).unwrap_or(false);
+    let res = match ticket.action {
+        CleanupAction::Delete => remove_path(&ticket.path, is_dir),
+        CleanupAction::Trash => move_to_trash(&ticket.path).map(|to| {
+            record.trashed_to = Some(to);
+        }),
+    };
+
+    match res {
+        Ok(_) => {
+            if let Err(e) = forget_path(db, &ticket.path, is_dir, &ticket.overview) {
+                eprintln!("remove {:?} from db failed, {}", ticket.path, e);
+            }
+            save_audit_record(db, &record)?;
+            Ok(record)
+        }
+        Err(e) => {
+            record.error = Some(e.to_string());
+            save_audit_record(db, &record)?;
+            Err(Error::Io(format!("{} {} failed, {}", ticket.action, ticket.path.display(), e)))
+        }
+    }
+}
+
+pub fn list_audit(db: &impl KvStore, limit: u32) -> Vec<AuditRecord> {
+    let mut records: Vec<AuditRecord> = Vec::new();
+    // keys start with the timestamp, show the latest actions first
+    db.foreach_rev(TABLE_AUDIT_LOG, "", limit, |_, v| {
+        if let Ok(record) = serde_json::from_slice(v) {
+            records.push(record);
+        }
+    });
+    records
+}
+
+fn save_audit_record(db: &impl KvStore, record: &AuditRecord) -> crate::Result<()> {
+    let key = format!("{},{}", record.ts, record.token);
+    db.set_json(TABLE_AUDIT_LOG, key, record)
+}
+
+fn ticket_key(token: &str) -> String {
+    format!("{}{}", CONF_KEY_CLEANUP_PREFIX, token)
+}
+
+// tickets never confirmed stay behind, each new one clears the expired ones
+fn drop_expired_tickets(db: &impl KvStore) -> crate::Result<()> {
+    let now = unix();
+    let mut batch = Batch::new();
+    db.foreach(TABLE_CONF, CONF_KEY_CLEANUP_PREFIX, 0, |k, v| {
+        match serde_json::from_slice::<CleanupTicket>(v) {
+            Ok(ticket) if ticket.expires >= now => {}
+            _ => batch.delete(TABLE_CONF, k),
+        }
+    });
+    if batch.is_empty() {
+        return Ok(());
+    }
+    db.write(batch)
+}
+
+// the dirs with a scan result and the watched ones
+fn roots(db: &impl KvStore) -> HashSet<PathBuf> {
+    let mut roots: HashSet<PathBuf> = conf::list_watch(db).into_iter().map(|w| PathBuf::from(w.path)).collect();
+    let mut last = String::new();
+    db.foreach(TABLE_DIR_SCAN_RESULT, "", 0, |k, _| {
+        if let Some((dir, _)) = split_scan_result_key(k) {
+            if dir != last {
+                last = dir.to_string();
+                roots.insert(key_path(dir));
+            }
+        }
+    });
+    roots
+}
+
+// the path with its parent resolved, so a link on the way can not lead out
+// of a root
+fn real_path(path: &Path) -> PathBuf {
+    match (path.parent().map(fs::canonicalize), path.file_name()) {
+        (Some(Ok(parent)), Some(name)) => parent.join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+fn is_protected(path: &Path) -> bool {
+    let home = home_dir();
+    path.parent() == Some(Path::new("/"))
+        || home.starts_with(path)
+        || fs::canonicalize(&home).is_ok_and(|home| home.starts_with(path))
+        || SYSTEM_DIRS.iter().any(|dir| path.starts_with(dir))
+}
+
+// only paths strictly inside a scanned or watched root are cleaned up, and
+// never $HOME, its ancestors or the system dirs
+pub fn check_target(db: &impl KvStore, path: &Path) -> crate::Result<()> {
+    if !path.is_absolute() {
+        return Err(Error::InvalidPath(format!("{} is not an absolute path", path.display())));
+    }
+    if path.components().any(|c| matches!(c, Component::CurDir | Component::ParentDir)) {
+        return Err(Error::InvalidPath(format!("{} is not a normalized path", path.display())));
+    }
+    let real = real_path(path);
+    if is_protected(path) || is_protected(&real) {
+        return Err(Error::InvalidPath(format!("refuse to clean up {}", path.display())));
+    }
+    let inside = roots(db).iter().any(|root| {
+        let root = fs::canonicalize(root).unwrap_or_else(|_| root.clone());
+        real.starts_with(&root) && real != root
+    });
+    if !inside {
+        return Err(Error::InvalidPath(format!("{} is not inside a scanned or watched dir", path.display())));
+    }
+    Ok(())
+}
+
+fn remove_path(path: &Path, is_dir: bool) -> io::Result<()> {
+    if is_dir {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+// drop the stats of the removed subtree and take it out of its parent,
+// the recursive totals of all ancestors are derived from the parent.
+// written as one batch so the parent never counts a subtree that is gone
+fn forget_path(
+    db: &impl KvStore,
+    path: &Path,
+    is_dir: bool,
+    overview: &DirScanOverview,
+) -> crate::Result<()> {
+    let mut batch = DirStatBatch::new(db);
+    if is_dir {
+        let mut paths = Vec::new();
+        dirstat::foreach_subtree(db, path, |path, _| paths.push(path.to_path_buf()));
+        for path in paths {
+            batch.delete(&path);
+        }
+    }
+
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        return batch.write();
+    };
+    let Some(mut parent_stat) = dirstat::get_dir_stat(db, parent)? else {
+        return batch.write();
+    };
+    if is_dir {
+        let orig_size = parent_stat.sub_dirs.len();
+        parent_stat.sub_dirs.retain(|elem| elem.as_os_str() != name);
+        if orig_size != parent_stat.sub_dirs.len() {
+            parent_stat.subdir_num = parent_stat.subdir_num.saturating_sub(1);
+        }
+    } else {
+        parent_stat.file_num = parent_stat.file_num.saturating_sub(1);
+        parent_stat.blocks = parent_stat.blocks.saturating_sub(overview.blocks);
+    }
+    batch.save(parent, &parent_stat)?;
+    batch.write()
+}
+
+// $XDG_DATA_HOME/Trash, see https://specifications.freedesktop.org/trash-spec/latest/
+pub fn trash_dir() -> PathBuf {
+    match std::env::var_os("XDG_DATA_HOME") {
+        Some(v) if !v.is_empty() => PathBuf::from(v).join("Trash"),
+        _ => home_dir().join(".local/share/Trash"),
+    }
+}
+
+// move `path` into the home trash and return where it ended up
+pub fn move_to_trash(path: &Path) -> io::Result<PathBuf> {
+    let trash = trash_dir();
+    let files_dir = trash.join("files");
+    let info_dir = trash.join("info");
+    fs::create_dir_all(&files_dir)?;
+    fs::create_dir_all(&info_dir)?;
+
+    let Some(name) = path.file_name() else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"));
+    };
+    let info = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        percent_encode(path.as_os_str().as_bytes()),
+        format_deletion_date(unix())
+    );
+
+    for i in 1..1000 {
+        let mut trash_name = name.to_os_string();
+        if i > 1 {
+            trash_name.push(format!(".{}", i));
+        }
+        let mut info_name = trash_name.clone();
+        info_name.push(".trashinfo");
+
+        // creating the info file exclusively reserves the name in files/
+        let info_path = info_dir.join(&info_name);
+        match fs::OpenOptions::new().write(true).create_new(true).open(&info_path) {
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+        let target = files_dir.join(&trash_name);
+        if let Err(e) = fs::write(&info_path, &info).and_then(|_| fs::rename(path, &target)) {
+            let _ = fs::remove_file(&info_path);
+            return Err(e);
+        }
+        return Ok(target);
+    }
+    Err(io::Error::new(io::ErrorKind::AlreadyExists, "too many trashed entries with the same name"))
+}
+
+fn percent_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len());
+    for &b in bytes {
+        if b.is_ascii_alphanumeric() || b"/-_.~".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+// YYYY-MM-DDThh:mm:ss in UTC
+pub fn format_deletion_date(ts: u64) -> String {
+    let days = (ts / 86400) as i64;
+    let secs = ts % 86400;
+
+    // civil from days, http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
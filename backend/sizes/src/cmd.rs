@@ -2,51 +2,129 @@ use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::Display;
 use std::iter::Map;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use kanal::{AsyncReceiver, AsyncSender, SendError};
-use tokio::sync::RwLock;
+use serde::Serialize;
+use tokio::sync::{broadcast, Notify, RwLock};
+use tokio::task;
 
-use crate::db::scanresult;
+use crate::db::{maintenance, retention, scanresult};
+use crate::db::DbHandle;
+use crate::kvstore::store::Store;
+use crate::kvstore::KvStore;
 use crate::scan::DirScanResult;
-use crate::{db, scandir, StaticBox};
-use crate::conf::app_db_path;
+use crate::{config, scandir, unix, StaticBox};
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub enum Command {
     ScanDir(PathBuf),
+    // one table, all of them for none
+    Compact(Option<String>),
+    // into a new path
+    Backup(PathBuf),
+    // from a backup, replacing all data
+    Restore(PathBuf),
+    // drops the data beyond the `[retention]` limits
+    Retention,
 }
 
+impl Command {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::ScanDir(_) => "scan",
+            Command::Compact(_) => "compact",
+            Command::Backup(_) => "backup",
+            Command::Restore(_) => "restore",
+            Command::Retention => "retention",
+        }
+    }
+
+    // the dir scanned or the path of the backup, empty for the others
+    pub fn path(&self) -> PathBuf {
+        match self {
+            Command::ScanDir(path) | Command::Backup(path) | Command::Restore(path) => path.clone(),
+            Command::Compact(_) | Command::Retention => PathBuf::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub enum ProgressStatus {
     PENDING,
     STARTED,
+    // a periodic tick while the task runs
+    RUNNING,
     COMPLETED,
     FAILED,
     ABORTED,
 }
 
+impl ProgressStatus {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProgressStatus::PENDING => "pending",
+            ProgressStatus::STARTED => "started",
+            ProgressStatus::RUNNING => "running",
+            ProgressStatus::COMPLETED => "completed",
+            ProgressStatus::FAILED => "failed",
+            ProgressStatus::ABORTED => "aborted",
+        }
+    }
+}
+
+// what the task manager broadcasts about a task, `task` is `Command::name`.
+// for a scan `result` is the progress so far, or the final result once the
+// status is COMPLETED, FAILED or ABORTED, for the others it stays empty
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskEvent {
+    pub task: &'static str,
+    pub status: ProgressStatus,
+    pub path: PathBuf,
+    pub result: DirScanResult,
+    pub error: Option<String>,
+}
+
 pub trait Progress: Display {
     fn new() -> Self;
     fn status() -> Result<ProgressStatus, String>;
     fn progress() -> Result<(i8, i8), String>;
 }
 
-type TASKS = Arc<RwLock<HashMap<Command, &'static DirScanResult>>>;
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+const EVENTS_CAPACITY: usize = 256;
+
+#[derive(Debug)]
+struct OngoingTask {
+    progress: &'static DirScanResult,
+    abort: Arc<Notify>,
+}
+
+type TASKS = Arc<RwLock<HashMap<Command, OngoingTask>>>;
 
 #[derive(Clone, Debug)]
 pub struct TaskManager {
     ongoing_tasks: TASKS,
     tx: AsyncSender<Command>,
+    events: broadcast::Sender<TaskEvent>,
+    db: DbHandle,
+    // scans share it, maintenance holds it alone, so a backup or restore
+    // waits for the running scans and the scans queued later wait for it
+    exclusive: Arc<RwLock<()>>,
 }
 
 impl TaskManager {
-    pub fn new() -> Self {
+    pub fn new(db: DbHandle) -> Self {
         let (tx, rx) = kanal::bounded_async(8);
+        let (events, _) = broadcast::channel(EVENTS_CAPACITY);
         let task_manager = TaskManager {
             ongoing_tasks: TASKS::new(RwLock::new(HashMap::new())),
             tx,
+            events,
+            db,
+            exclusive: Arc::new(RwLock::new(())),
         };
 
         let ret = task_manager.clone();
@@ -60,14 +138,79 @@ impl TaskManager {
         self.tx.send(cmd).await
     }
 
-    pub async fn scan_progress(&self) -> HashMap<PathBuf, DirScanResult> {
-        let cmd = Command::ScanDir(PathBuf::from("/"));
+    // queues a retention run every `period`, the first one a period from now
+    pub fn schedule_retention(&self, period: Duration) {
+        let tx = self.tx.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(period);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if let Err(e) = tx.send(Command::Retention).await {
+                    eprintln!("{}, stop scheduling retention", e);
+                    return;
+                }
+            }
+        });
+    }
 
+    // events of the tasks started after subscribing
+    pub fn subscribe(&self) -> broadcast::Receiver<TaskEvent> {
+        self.events.subscribe()
+    }
+
+    pub async fn abort(&self, path: &Path) -> crate::Result<()> {
+        let cmd = Command::ScanDir(PathBuf::from(path));
+        let r = self.ongoing_tasks.read().await;
+        let Some(task) = r.get(&cmd) else {
+            return Err(crate::Error::NotFound(format!("{} is not being scanned", path.display())));
+        };
+        task.abort.notify_one();
+        Ok(())
+    }
+
+    // runs `job` on a blocking thread, next to the scans of other trees but
+    // not during maintenance. refused while a scan covers `path`, the tasks
+    // queued meanwhile start once it is done
+    pub async fn run_on_path<T, F>(&self, path: &Path, job: F) -> crate::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Store) -> crate::Result<T> + Send + 'static,
+    {
+        let _shared = self.exclusive.read().await;
+        let r = self.ongoing_tasks.read().await;
+        for cmd in r.keys() {
+            if let Command::ScanDir(scanned) = cmd {
+                if scanned.starts_with(path) || path.starts_with(scanned) {
+                    return Err(crate::Error::Busy(format!("{} is being scanned", scanned.display())));
+                }
+            }
+        }
+        let db = self.db.clone();
+        let res = task::spawn_blocking(move || job(&db)).await;
+        drop(r);
+        res.map_err(|e| crate::Error::Other(e.to_string()))?
+    }
+
+    // runs `job` on a blocking thread, not during maintenance, for writes no
+    // scan can interfere with, e.g. an import or a change of the config
+    pub async fn run_shared<T, F>(&self, job: F) -> crate::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Store) -> crate::Result<T> + Send + 'static,
+    {
+        let _shared = self.exclusive.read().await;
+        let db = self.db.clone();
+        let res = task::spawn_blocking(move || job(&db)).await;
+        res.map_err(|e| crate::Error::Other(e.to_string()))?
+    }
+
+    pub async fn scan_progress(&self) -> HashMap<PathBuf, DirScanResult> {
         let mut tasks = HashMap::new();
         let r = self.ongoing_tasks.read().await;
         r.iter().for_each(|(k_ref, v_ref)| {
             if let Command::ScanDir(path) = k_ref {
-                let mut elem = (*v_ref).clone();
+                let mut elem = v_ref.progress.clone();
                 elem.ongoing = true;
                 tasks.insert(path.clone(), elem);
             }
@@ -100,18 +243,67 @@ impl TaskManager {
         drop(r);
 
         let t1 = self.ongoing_tasks.clone();
+        let db = self.db.clone();
+        let events = self.events.clone();
+        let exclusive = self.exclusive.clone();
 
         tokio::spawn(async move {
             let progress = StaticBox::new(DirScanResult::new());
+            let abort = Arc::new(Notify::new());
 
             let mut w = t1.write().await;
-            w.insert(cmd.clone(), progress.get());
+            w.insert(cmd.clone(), OngoingTask { progress: progress.get(), abort: abort.clone() });
             drop(w);
             println!("insert cmd into ongoing task queue");
 
-            match cmd {
+            let last = match cmd {
                 Command::ScanDir(ref path) => {
-                    scan_dir(&path, progress.get_mut()).await;
+                    let _shared = exclusive.read().await;
+                    // sending fails only when nobody listens, which is fine
+                    let current = progress.get();
+                    let event = |status, error| TaskEvent {
+                        task: cmd.name(),
+                        status,
+                        path: path.clone(),
+                        result: current.clone(),
+                        error,
+                    };
+                    let _ = events.send(event(ProgressStatus::STARTED, None));
+
+                    let scan = scan_dir(db, path, progress.get_mut());
+                    tokio::pin!(scan);
+                    let mut ticker = tokio::time::interval(PROGRESS_INTERVAL);
+                    ticker.tick().await;
+                    loop {
+                        tokio::select! {
+                            res = &mut scan => match res {
+                                Ok(_) => break event(ProgressStatus::COMPLETED, None),
+                                Err(e) => break event(ProgressStatus::FAILED, Some(e.to_string())),
+                            },
+                            // dropping the scan future stops its workers
+                            _ = abort.notified() => break event(ProgressStatus::ABORTED, None),
+                            _ = ticker.tick() => {
+                                let _ = events.send(event(ProgressStatus::RUNNING, None));
+                            }
+                        }
+                    }
+                }
+                ref maintenance => {
+                    let _alone = exclusive.write().await;
+                    let event = |status, error| TaskEvent {
+                        task: maintenance.name(),
+                        status,
+                        path: maintenance.path(),
+                        result: DirScanResult::new(),
+                        error,
+                    };
+                    let _ = events.send(event(ProgressStatus::STARTED, None));
+                    let job = maintenance.clone();
+                    match task::spawn_blocking(move || run_maintenance(&db, &job)).await {
+                        Ok(Ok(())) => event(ProgressStatus::COMPLETED, None),
+                        Ok(Err(e)) => event(ProgressStatus::FAILED, Some(e.to_string())),
+                        Err(e) => event(ProgressStatus::FAILED, Some(e.to_string())),
+                    }
                 }
             };
 
@@ -120,32 +312,53 @@ impl TaskManager {
             drop(w);
 
             progress.drop();
+            // sent once the task is gone, so whoever reacts to it sees the task finished
+            let _ = events.send(last);
 
             println!("finished cmd {:?} remove from ongoing task queue", cmd);
         });
     }
 }
 
-async fn scan_dir(path: &PathBuf, progress: &'static mut DirScanResult) {
-    println!("start scanning directory {:?}", path);
+fn run_maintenance(db: &Store, cmd: &Command) -> crate::Result<()> {
+    match cmd {
+        Command::Compact(table) => maintenance::compact(db, table.as_deref()),
+        Command::Backup(path) => maintenance::backup(db, path).map(|_| ()),
+        Command::Restore(path) => maintenance::restore(db, path).map(|_| ()),
+        Command::Retention => {
+            let report = retention::apply_retention(db, &config::get().retention, unix())?;
+            eprintln!("retention removed {} scan results and {} dirs", report.scan_results, report.dirs);
+            Ok(())
+        }
+        Command::ScanDir(path) => Err(crate::Error::Other(format!("{} is a scan, not maintenance", path.display()))),
+    }
+}
 
-    let db = db::get_db(app_db_path(), false);
+// scans into any store, e.g. a `MemoryStore` for a scan that is not kept
+pub async fn scan_dir<S: KvStore + Send + Sync + 'static>(
+    db: Arc<S>,
+    path: &PathBuf,
+    progress: &mut DirScanResult,
+) -> crate::Result<()> {
+    eprintln!("start scanning directory {:?}", path);
 
     let t1 = Instant::now();
-    scandir::compute_dir_stats_loop_parallel(db, path, progress)
-        .await
-        .unwrap();
+    progress.root = path.clone();
+    progress.started_at = unix();
+    scandir::compute_dir_stats_loop_parallel(db.clone(), path, config::get().scan.concurrency, progress).await?;
     let elapsed = t1.elapsed().as_secs();
     progress.spent = elapsed;
     progress.ongoing = false;
+    progress.finished_at = unix();
 
-    if let Err(e) = scanresult::save_dir_scan_result(db, path, progress) {
+    if let Err(e) = scanresult::save_dir_scan_result(db.as_ref(), path, progress) {
         eprintln!("save dir scan result failed: {e}");
     }
-    println!(
+    eprintln!(
         "spent {} seconds, finished scanning directory {}, stat: {:?}",
         elapsed,
         path.display(),
         progress
     );
+    Ok(())
 }
@@ -1,13 +1,14 @@
-use std::{path::PathBuf, sync::OnceLock};
+use std::path::PathBuf;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
 use crate::kvstore::KvStore;
-use crate::{
-    db::TABLE_CONF,
-    home_dir,
-};
+use crate::reclaim::ReclaimRule;
+use crate::config;
+use crate::db::TABLE_CONF;
 
 static CONF_KEY_WATCHES: &str = "watches";
+static CONF_KEY_RECLAIM_RULES: &str = "reclaim_rules";
 
 #[derive(Debug, Serialize, Deserialize, Clone, Hash, Eq, PartialEq)]
 pub struct WatchDirectoryConfiguration {
@@ -17,23 +18,32 @@ pub struct WatchDirectoryConfiguration {
 }
 
 pub fn app_db_path() -> &'static PathBuf {
-    static GLOBAL_CONF_PATH: OnceLock<PathBuf> = OnceLock::new();
-    GLOBAL_CONF_PATH.get_or_init(|| {
-        let mut path = home_dir();
-        path.push(".config/sizes/app.db");
-        path
+    &config::get().db.path
+}
+
+// a list kept under one key of the conf table. changes go through this so a
+// list that fails to read is left as it is instead of being replaced
+pub(crate) fn load_list<T: DeserializeOwned>(db: &impl KvStore, key: &str) -> crate::Result<Vec<T>> {
+    Ok(db.get_as(TABLE_CONF, key)?.unwrap_or_default())
+}
+
+// for readers an unreadable list is reported and shows as empty
+pub(crate) fn list_or_report<T: DeserializeOwned>(db: &impl KvStore, key: &str) -> Vec<T> {
+    load_list(db, key).unwrap_or_else(|e| {
+        eprintln!("read {} failed, {}", key, e);
+        vec![]
     })
 }
 
 pub fn list_watch(db: &impl KvStore) -> Vec<WatchDirectoryConfiguration> {
-    db.get_as(TABLE_CONF, CONF_KEY_WATCHES).unwrap_or(vec![])
+    list_or_report(db, CONF_KEY_WATCHES)
 }
 
 pub fn add_watch(
     db: &impl KvStore,
     to_add: &WatchDirectoryConfiguration,
 ) -> crate::Result<()> {
-    let mut watches = list_watch(db);
+    let mut watches: Vec<WatchDirectoryConfiguration> = load_list(db, CONF_KEY_WATCHES)?;
     if watches.contains(to_add) {
         return Ok(());
     }
@@ -57,7 +67,7 @@ pub fn remove_watch(
     db: &impl KvStore,
     to_remove: &WatchDirectoryConfiguration,
 ) -> crate::Result<()> {
-    let mut watches = list_watch(db);
+    let mut watches: Vec<WatchDirectoryConfiguration> = load_list(db, CONF_KEY_WATCHES)?;
     let orig_size = watches.len();
     watches.retain(|elem| elem.path != to_remove.path);
     if orig_size != watches.len() {
@@ -66,3 +76,29 @@ pub fn remove_watch(
         Ok(())
     }
 }
+
+// user defined rules for the reclaimable space analyzer, on top of `reclaim::builtin_rules`
+pub fn list_reclaim_rules(db: &impl KvStore) -> Vec<ReclaimRule> {
+    list_or_report(db, CONF_KEY_RECLAIM_RULES)
+}
+
+pub fn add_reclaim_rule(db: &impl KvStore, to_add: &ReclaimRule) -> crate::Result<()> {
+    if to_add.dir_name.is_none() && to_add.paths.is_empty() {
+        return Err(format!("rule {} matches neither a directory name nor a path", to_add.name).into());
+    }
+    let mut rules: Vec<ReclaimRule> = load_list(db, CONF_KEY_RECLAIM_RULES)?;
+    rules.retain(|elem| elem.name != to_add.name);
+    rules.push(to_add.clone());
+    db.set_json(TABLE_CONF, CONF_KEY_RECLAIM_RULES, &rules)
+}
+
+pub fn remove_reclaim_rule(db: &impl KvStore, name: &str) -> crate::Result<()> {
+    let mut rules: Vec<ReclaimRule> = load_list(db, CONF_KEY_RECLAIM_RULES)?;
+    let orig_size = rules.len();
+    rules.retain(|elem| elem.name != name);
+    if orig_size != rules.len() {
+        db.set_json(TABLE_CONF, CONF_KEY_RECLAIM_RULES, &rules)
+    } else {
+        Ok(())
+    }
+}
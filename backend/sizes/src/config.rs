@@ -0,0 +1,138 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+
+use crate::db::Backend;
+use crate::home_dir;
+use crate::server::ServerOptions;
+
+pub static CONFIG_ENV: &str = "SIZES_CONFIG";
+
+// the settings read from `config.toml`, every section and field is optional
+// and falls back to the value the app used before there was a config file
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct AppConfig {
+    // the file this was loaded from, none when it did not exist
+    #[serde(skip_deserializing)]
+    pub file: Option<PathBuf>,
+    pub db: DbConfig,
+    pub server: ServerOptions,
+    pub scan: ScanConfig,
+    pub retention: RetentionConfig,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct DbConfig {
+    pub backend: Backend,
+    pub path: PathBuf,
+    // sizes of a regular column family, tiny ones get a quarter and huge ones four times as much
+    pub write_buffer_mb: usize,
+    pub block_cache_mb: usize,
+}
+
+impl Default for DbConfig {
+    fn default() -> Self {
+        DbConfig {
+            backend: Backend::default(),
+            path: home_dir().join(".config/sizes/app.db"),
+            write_buffer_mb: 32,
+            block_cache_mb: 64,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ScanConfig {
+    // number of directories read at the same time
+    pub concurrency: usize,
+}
+
+impl Default for ScanConfig {
+    fn default() -> Self {
+        ScanConfig { concurrency: 8 }
+    }
+}
+
+// 0 means no limit for the first three
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct RetentionConfig {
+    // scan results kept for each directory
+    pub scan_results: usize,
+    pub max_age_days: u64,
+    // stats of directories no scan reached for this long
+    pub stale_dir_days: u64,
+    // how often the limits are applied, 0 leaves it to `db prune`
+    pub interval_hours: u64,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        RetentionConfig { scan_results: 0, max_age_days: 0, stale_dir_days: 0, interval_hours: 24 }
+    }
+}
+
+static CONFIG: OnceLock<AppConfig> = OnceLock::new();
+
+// `--config` first, then `$SIZES_CONFIG`, then `$XDG_CONFIG_HOME/sizes/config.toml`
+pub fn config_file(cli: Option<&Path>) -> PathBuf {
+    if let Some(path) = cli {
+        return path.to_path_buf();
+    }
+    if let Some(path) = env::var_os(CONFIG_ENV).filter(|v| !v.is_empty()) {
+        return PathBuf::from(path);
+    }
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or(home_dir().join(".config"));
+    config_home.join("sizes/config.toml")
+}
+
+// a missing file gives the default configuration, a broken one is an error
+pub fn read_config(path: &Path) -> crate::Result<AppConfig> {
+    if !path.exists() {
+        return Ok(AppConfig::default());
+    }
+    let content = fs::read_to_string(path)?;
+    let mut config: AppConfig = toml::from_str(&content)
+        .map_err(|e| format!("invalid config file {}: {}", path.display(), e))?;
+    config.file = Some(path.to_path_buf());
+    config.db.path = expand_home(&config.db.path);
+    if let Some(dir) = &config.server.static_dir {
+        config.server.static_dir = Some(expand_home(dir));
+    }
+    config.scan.concurrency = config.scan.concurrency.max(1);
+    Ok(config)
+}
+
+// loads the configuration the process runs with, only the first call reads the file
+pub fn load(cli: Option<&Path>) -> crate::Result<&'static AppConfig> {
+    if let Some(config) = CONFIG.get() {
+        return Ok(config);
+    }
+    let config = read_config(&config_file(cli))?;
+    Ok(CONFIG.get_or_init(|| config))
+}
+
+pub fn get() -> &'static AppConfig {
+    CONFIG.get_or_init(|| {
+        read_config(&config_file(None)).unwrap_or_else(|e| {
+            eprintln!("{}, using the default configuration", e);
+            AppConfig::default()
+        })
+    })
+}
+
+fn expand_home(path: &Path) -> PathBuf {
+    match path.strip_prefix("~") {
+        Ok(rest) => home_dir().join(rest),
+        Err(_) => path.to_path_buf(),
+    }
+}
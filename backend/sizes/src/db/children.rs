@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::dirorder::{Direction, Orderby, Page};
+use super::dirstat::{self, DirStat};
+use super::scanresult;
+use crate::kvstore::KvStore;
+use crate::scan::DirScanOverview;
+use crate::Error;
+
+// name of the synthetic entry holding the files directly in the listed dir
+pub const OWN_FILES_NAME: &str = "<files>";
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChildKind {
+    Dir,
+    // the files of the listed dir itself, `path` is the listed dir
+    Files,
+}
+
+// one row of a drill-down listing, sizes are recursive for dirs. `name` is
+// for display only, `path` is exact also for names that are not utf-8
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChildEntry {
+    pub name: String,
+    pub path: PathBuf,
+    pub kind: ChildKind,
+    pub blocks: u64,
+    pub files: u64,
+    pub dirs: u64,
+    // when a scan last reached the dir, 0 if it never was
+    pub ts: u64,
+}
+
+impl ChildEntry {
+    fn value(&self, orderby: Orderby) -> u64 {
+        match orderby {
+            Orderby::Block => self.blocks,
+            Orderby::FileNum => self.files,
+            Orderby::SubDirNum => self.dirs,
+        }
+    }
+}
+
+// when a scan last reached `path`, by the same rule as the stale dirs of
+// retention: an unchanged dir is not saved again, so it is the newest of its
+// own time, its last scan as a root and the last visit of its parent while
+// the parent lists it. followed down from the top most ancestor
+fn last_visit(db: &impl KvStore, path: &Path, stat: &DirStat) -> u64 {
+    let mut chain: Vec<&Path> = path.ancestors().skip(1).collect();
+    chain.reverse();
+    // the last visit of the dir before, while it lists the next one
+    let mut parent_seen = None;
+    for (i, dir) in chain.iter().enumerate() {
+        let Ok(Some(ancestor)) = dirstat::get_dir_stat(db, dir) else {
+            parent_seen = None;
+            continue;
+        };
+        let seen = visit_time(db, dir, ancestor.ts, parent_seen);
+        let next = chain.get(i + 1).copied().unwrap_or(path);
+        let lists_next = next.file_name().is_some_and(|name| ancestor.sub_dirs.iter().any(|d| d.as_os_str() == name));
+        parent_seen = lists_next.then_some(seen);
+    }
+    visit_time(db, path, stat.ts, parent_seen)
+}
+
+fn visit_time(db: &impl KvStore, path: &Path, ts: u64, parent_seen: Option<u64>) -> u64 {
+    ts.max(scanresult::last_scan_time(db, path)).max(parent_seen.unwrap_or(0))
+}
+
+// the immediate children of a scanned dir with their recursive totals, plus
+// the own files entry, read in a single pass over the subtree
+pub fn list_children(
+    db: &impl KvStore,
+    path: &Path,
+    orderby: Orderby,
+    direction: Direction,
+    offset: u32,
+    limit: u32,
+) -> crate::Result<Page<ChildEntry>> {
+    let Some(stat) = dirstat::get_dir_stat(db, path)? else {
+        return Err(Error::NotFound(format!("{} has not been scanned", path.display())));
+    };
+
+    let seen = last_visit(db, path, &stat);
+    // the time of a child stays none until its stat is found
+    let mut totals: HashMap<OsString, (DirScanOverview, Option<u64>)> = stat
+        .sub_dirs
+        .iter()
+        .map(|name| (name.clone().into_os_string(), (DirScanOverview::new(), None)))
+        .collect();
+    dirstat::foreach_subtree(db, path, |descendant_path, descendant: DirStat| {
+        let Ok(rest) = descendant_path.strip_prefix(path) else {
+            return;
+        };
+        let mut components = rest.components();
+        let Some(name) = components.next() else {
+            return;
+        };
+        let is_child = components.next().is_none();
+        // dirs that disappeared since the parent was read are left out
+        let Some((overview, ts)) = totals.get_mut(name.as_os_str()) else {
+            return;
+        };
+        overview.blocks += descendant.blocks;
+        overview.files += descendant.file_num;
+        overview.dirs += descendant.subdir_num;
+        if is_child {
+            *ts = Some(descendant.ts);
+        }
+    });
+
+    let mut items: Vec<ChildEntry> = totals
+        .into_iter()
+        .map(|(name, (overview, ts))| {
+            let path = path.join(&name);
+            // the listed dir still lists every child here
+            let ts = ts.map_or(0, |ts| visit_time(db, &path, ts, Some(seen)));
+            ChildEntry {
+                path,
+                name: name.to_string_lossy().to_string(),
+                kind: ChildKind::Dir,
+                blocks: overview.blocks,
+                files: overview.files,
+                dirs: overview.dirs,
+                ts,
+            }
+        })
+        .collect();
+    items.push(ChildEntry {
+        name: OWN_FILES_NAME.to_string(),
+        path: path.to_path_buf(),
+        kind: ChildKind::Files,
+        blocks: stat.blocks,
+        files: stat.file_num,
+        dirs: 0,
+        ts: seen,
+    });
+
+    items.sort_by(|a, b| {
+        let order = a.value(orderby).cmp(&b.value(orderby));
+        let order = match direction {
+            Direction::Asc => order,
+            Direction::Desc => order.reverse(),
+        };
+        order.then_with(|| a.name.cmp(&b.name))
+    });
+    Ok(Page::of(items, offset, limit))
+}
@@ -0,0 +1,248 @@
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use super::dirstat::{self, DirStat};
+use super::key::key_path;
+use crate::db::{TABLE_DIR_ORDER, TABLE_DIR_STAT};
+use crate::kvstore::{Batch, KvStore};
+use crate::Error;
+
+// the order index keeps one key per dir and sortable field,
+// `{field},{value as 20 digits},{key of the dir}` with an empty value, so
+// walking a field prefix forward or backward gives the dirs sorted by that
+// field without reading any stat. the number of dirs is kept next to them
+// under `count`, which sorts apart from every field prefix
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum Orderby {
+    #[default]
+    Block,
+    FileNum,
+    SubDirNum,
+}
+
+impl Orderby {
+    pub const ALL: [Orderby; 3] = [Orderby::Block, Orderby::FileNum, Orderby::SubDirNum];
+
+    fn field(&self) -> &'static str {
+        match self {
+            Orderby::Block => "blocks",
+            Orderby::FileNum => "files",
+            Orderby::SubDirNum => "dirs",
+        }
+    }
+
+    pub(crate) fn value(&self, stat: &DirStat) -> u64 {
+        match self {
+            Orderby::Block => stat.blocks,
+            Orderby::FileNum => stat.file_num,
+            Orderby::SubDirNum => stat.subdir_num,
+        }
+    }
+}
+
+// the field names, and the variant names in any case
+impl FromStr for Orderby {
+    type Err = Error;
+
+    fn from_str(s: &str) -> crate::Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "blocks" | "block" => Ok(Orderby::Block),
+            "files" | "filenum" => Ok(Orderby::FileNum),
+            "dirs" | "subdirnum" => Ok(Orderby::SubDirNum),
+            _ => Err(Error::Other(format!("invalid orderby {:?}, expect blocks, files or dirs", s))),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum Direction {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl FromStr for Direction {
+    type Err = Error;
+
+    fn from_str(s: &str) -> crate::Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "asc" => Ok(Direction::Asc),
+            "desc" => Ok(Direction::Desc),
+            _ => Err(Error::Other(format!("invalid direction {:?}, expect asc or desc", s))),
+        }
+    }
+}
+
+// one page of a sorted listing. `total` counts every item matching the
+// query when it is known without walking all of them, `has_more` tells
+// whether there is a next page either way
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Page<T> {
+    pub total: Option<u64>,
+    pub has_more: bool,
+    pub offset: u32,
+    pub limit: u32,
+    pub items: Vec<T>,
+}
+
+impl<T> Page<T> {
+    pub fn empty(offset: u32, limit: u32) -> Page<T> {
+        Page { total: Some(0), has_more: false, offset, limit, items: Vec::new() }
+    }
+
+    // pages a list that is already sorted in memory
+    pub fn of(all: Vec<T>, offset: u32, limit: u32) -> Page<T> {
+        let total = all.len() as u64;
+        let items: Vec<T> = all.into_iter().skip(offset as usize).take(limit as usize).collect();
+        let has_more = (offset as u64).saturating_add(items.len() as u64) < total;
+        Page { total: Some(total), has_more, offset, limit, items }
+    }
+}
+
+const COUNT_KEY: &str = "count";
+
+// count updates read the count and write it back, writers of one process
+// take turns so none of them starts from a count another is changing
+static COUNT_LOCK: Mutex<()> = Mutex::new(());
+
+fn index_prefix(orderby: Orderby) -> String {
+    format!("{},", orderby.field())
+}
+
+fn index_key(orderby: Orderby, value: u64, key: &str) -> String {
+    format!("{}{:020},{}", index_prefix(orderby), value, key)
+}
+
+// moves the index entries of a dir from its `old` stat to the `new` one,
+// either of them can be none when the dir is added or removed. `key` is the
+// key of the dir in the stat table
+pub(crate) fn update_index(batch: &mut Batch, key: &str, old: Option<&DirStat>, new: Option<&DirStat>) {
+    for orderby in Orderby::ALL {
+        let old_value = old.map(|stat| orderby.value(stat));
+        let new_value = new.map(|stat| orderby.value(stat));
+        if old_value == new_value {
+            continue;
+        }
+        if let Some(value) = old_value {
+            batch.delete(TABLE_DIR_ORDER, index_key(orderby, value, key));
+        }
+        if let Some(value) = new_value {
+            batch.set(TABLE_DIR_ORDER, index_key(orderby, value, key), "");
+        }
+    }
+}
+
+// the number of dirs, counted from the index when a database written before
+// the count was kept has none
+pub fn dir_count(db: &impl KvStore) -> u64 {
+    if let Some(count) = db.get_bytes(TABLE_DIR_ORDER, COUNT_KEY) {
+        if let Some(count) = std::str::from_utf8(count.as_ref()).ok().and_then(|c| c.parse().ok()) {
+            return count;
+        }
+    }
+    let mut count = 0;
+    db.foreach(TABLE_DIR_ORDER, index_prefix(Orderby::Block), 0, |_, _| count += 1);
+    count
+}
+
+// writes `batch` together with the count moved by `added` dirs, which is
+// negative for dirs removed
+pub(crate) fn write_counted(db: &impl KvStore, mut batch: Batch, added: i64) -> crate::Result<()> {
+    if added == 0 {
+        return db.write(batch);
+    }
+    let _turn = COUNT_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let count = dir_count(db).saturating_add_signed(added);
+    batch.set(TABLE_DIR_ORDER, COUNT_KEY, count.to_string());
+    db.write(batch)
+}
+
+// databases written before the index existed have stats but no index entries
+pub fn ensure_index(db: &impl KvStore) -> crate::Result<()> {
+    let mut has_stats = false;
+    db.foreach(TABLE_DIR_STAT, "", 1, |_, _| has_stats = true);
+    let mut has_index = false;
+    db.foreach(TABLE_DIR_ORDER, index_prefix(Orderby::Block), 1, |_, _| has_index = true);
+    if !has_stats || has_index {
+        return Ok(());
+    }
+
+    let mut stats = Vec::new();
+    db.foreach(TABLE_DIR_STAT, "", 0, |k, v| {
+        if let Some(stat) = dirstat::decode_or_report(&key_path(k), v) {
+            stats.push((k.to_string(), stat));
+        }
+    });
+    eprintln!("building the order index of {} dirs", stats.len());
+    let mut batch = Batch::new();
+    for (key, stat) in &stats {
+        update_index(&mut batch, key, None, Some(stat));
+    }
+    batch.set(TABLE_DIR_ORDER, COUNT_KEY, stats.len().to_string());
+    db.write(batch)
+}
+
+// the dirs sorted by `orderby`, skipping those whose value is below `min`.
+// the walk stops one key after the page, so a page costs its offset and
+// size whatever the number of dirs. the total is the kept count, with a
+// `min` the keys from it on are counted
+pub fn list_dirs(
+    db: &impl KvStore,
+    orderby: Orderby,
+    direction: Direction,
+    min: u64,
+    offset: u32,
+    limit: u32,
+) -> crate::Result<Page<DirStat>> {
+    let prefix = index_prefix(orderby);
+    let start = offset as usize;
+    let walk = u32::try_from(start + limit as usize + 1).unwrap_or(u32::MAX);
+    let mut matched = 0;
+    let mut has_more = false;
+    let mut keys: Vec<String> = Vec::with_capacity(limit as usize);
+
+    // only the keys are parsed, the stats of the page are read afterwards
+    let mut visit = |k: &str, _: &[u8]| {
+        let Some((value, key)) = k[prefix.len()..].split_once(',') else {
+            return;
+        };
+        if !value.parse::<u64>().is_ok_and(|value| value >= min) {
+            return;
+        }
+        matched += 1;
+        if matched <= start {
+            return;
+        }
+        if keys.len() < limit as usize {
+            keys.push(key.to_string());
+        } else {
+            has_more = true;
+        }
+    };
+    match direction {
+        // from `min` on, every key walked is a match
+        Direction::Asc => {
+            let from = format!("{}{:020}", prefix, min);
+            db.foreach_range(TABLE_DIR_ORDER, from, format!("{}~", prefix), walk, &mut visit)
+        }
+        // the values below `min` come last
+        Direction::Desc => db.foreach_rev(TABLE_DIR_ORDER, &prefix, walk, &mut visit),
+    }
+
+    let total = if min == 0 {
+        dir_count(db)
+    } else {
+        let mut total = 0;
+        let from = format!("{}{:020}", prefix, min);
+        db.foreach_range(TABLE_DIR_ORDER, from, format!("{}~", prefix), 0, |_, _| total += 1);
+        total
+    };
+    let items = keys
+        .iter()
+        .filter_map(|key| dirstat::get_dir_stat_by_key(db, key).transpose())
+        .collect::<crate::Result<_>>()?;
+    Ok(Page { total: Some(total), has_more, offset, limit, items })
+}
@@ -1,10 +1,20 @@
-use crate::db::TABLE_DIR_STAT;
-use crate::kvstore::KvStore;
+use crate::db::codec::{put_bytes, put_varint, put_varint_signed, Reader};
+use crate::db::key::{key_path, path_key, subtree_prefix};
+use crate::db::{dirorder, search, TABLE_DIR_STAT};
+use crate::kvstore::{Batch, KvStore};
 use crate::scan::DirScanOverview;
-use crate::unix;
+use crate::{unix, Error};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
 
+// the first byte of a stored stat tells how the rest is encoded, the stats
+// written before the binary one are json and start with `{`
+const ENCODING_JSON: u8 = b'{';
+const ENCODING_V1: u8 = 1;
+
 #[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct DirStat {
     pub ts: u64,
@@ -28,6 +38,147 @@ impl DirStat {
             blocks: 0,
         }
     }
+
+    // `ENCODING_V1` and the fields as varints, the names of the sub dirs
+    // byte for byte. the path is left out, the key of the stat holds it
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(16 + self.sub_dirs.iter().map(|d| d.as_os_str().len() + 1).sum::<usize>());
+        out.push(ENCODING_V1);
+        put_varint(&mut out, self.ts);
+        put_varint_signed(&mut out, self.mtime);
+        put_varint(&mut out, self.subdir_num);
+        put_varint(&mut out, self.file_num);
+        put_varint(&mut out, self.blocks);
+        put_varint(&mut out, self.sub_dirs.len() as u64);
+        for dir in &self.sub_dirs {
+            put_bytes(&mut out, dir.as_os_str().as_bytes());
+        }
+        out
+    }
+
+    // the stat of the dir at `path` from either encoding, an error for
+    // anything else, e.g. a value cut short or of a later version
+    pub fn decode(path: &Path, bytes: &[u8]) -> crate::Result<DirStat> {
+        let fail = |why: String| Error::Decode(format!("dir stat of {}: {}", path.display(), why));
+        match bytes.first() {
+            Some(&ENCODING_JSON) => {
+                let mut stat: DirStat = serde_json::from_slice(bytes).map_err(|e| fail(e.to_string()))?;
+                if stat.path.as_os_str().is_empty() {
+                    stat.path = path.to_path_buf();
+                }
+                Ok(stat)
+            }
+            Some(&ENCODING_V1) => Self::decode_v1(path, &bytes[1..])
+                .ok_or_else(|| fail(format!("{} bytes cut short", bytes.len()))),
+            Some(other) => Err(fail(format!("unknown encoding {}", other))),
+            None => Err(fail("empty value".to_string())),
+        }
+    }
+
+    fn decode_v1(path: &Path, bytes: &[u8]) -> Option<DirStat> {
+        let mut reader = Reader::new(bytes);
+        let mut stat = DirStat {
+            ts: reader.varint()?,
+            mtime: reader.varint_signed()?,
+            subdir_num: reader.varint()?,
+            file_num: reader.varint()?,
+            blocks: reader.varint()?,
+            sub_dirs: Vec::new(),
+            path: path.to_path_buf(),
+        };
+        let dirs = reader.varint()?;
+        for _ in 0..dirs {
+            stat.sub_dirs.push(PathBuf::from(OsStr::from_bytes(reader.bytes()?)));
+        }
+        Some(stat)
+    }
+}
+
+// for walks that go on without the stat, the failure is logged. a lookup of
+// one dir returns it instead, see `get_dir_stat`
+pub(crate) fn decode_or_report(path: &Path, bytes: &[u8]) -> Option<DirStat> {
+    DirStat::decode(path, bytes).map_err(|e| eprintln!("{}", e)).ok()
+}
+
+// whether a stored stat is in an encoding older than the one written now
+pub fn is_outdated(bytes: &[u8]) -> bool {
+    bytes.first() != Some(&ENCODING_V1)
+}
+
+// stat writes together with their index entries, to be written as one batch.
+// the stats staged here are the old ones for later writes of the same path,
+// the store only has them once the batch is written
+pub struct DirStatBatch<'a, S: KvStore> {
+    db: &'a S,
+    batch: Batch,
+    pending: HashMap<String, Option<DirStat>>,
+    // dirs saved that were not there before less the dirs deleted
+    added: i64,
+}
+
+impl<'a, S: KvStore> DirStatBatch<'a, S> {
+    pub fn new(db: &'a S) -> Self {
+        Self { db, batch: Batch::new(), pending: HashMap::new(), added: 0 }
+    }
+
+    fn old(&self, key: &str) -> Option<DirStat> {
+        match self.pending.get(key) {
+            Some(stat) => stat.clone(),
+            // a stat that does not decode is written over, its index
+            // entries can not be known
+            None => get_dir_stat_by_key(self.db, key).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                None
+            }),
+        }
+    }
+
+    pub fn save(&mut self, path: &Path, stat: &DirStat) -> crate::Result<()> {
+        let key = path_key(path);
+        let old = self.old(&key);
+        self.batch.set(TABLE_DIR_STAT, &key, stat.encode());
+        search::update_name_index(&mut self.batch, &key, old.is_some(), true);
+        dirorder::update_index(&mut self.batch, &key, old.as_ref(), Some(stat));
+        if old.is_none() {
+            self.added += 1;
+        }
+        self.pending.insert(key, Some(stat.clone()));
+        Ok(())
+    }
+
+    pub fn delete(&mut self, path: &Path) {
+        let key = path_key(path);
+        let Some(old) = self.old(&key) else {
+            return;
+        };
+        self.batch.delete(TABLE_DIR_STAT, &key);
+        search::update_name_index(&mut self.batch, &key, true, false);
+        dirorder::update_index(&mut self.batch, &key, Some(&old), None);
+        self.added -= 1;
+        self.pending.insert(key, None);
+    }
+
+    // the number of dirs staged
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub fn write(self) -> crate::Result<()> {
+        self.write_with(Batch::new())
+    }
+
+    // for callers adding writes of their own, `extra` goes in the same batch
+    pub fn write_with(mut self, extra: Batch) -> crate::Result<()> {
+        self.batch.append(extra);
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        dirorder::write_counted(self.db, self.batch, self.added)
+    }
 }
 
 pub fn save_dir_stat(
@@ -35,26 +186,72 @@ pub fn save_dir_stat(
     path: &Path,
     stat: &DirStat,
 ) -> crate::Result<()> {
-    db.set_json(TABLE_DIR_STAT, path.to_string_lossy(), stat)
+    let mut batch = DirStatBatch::new(db);
+    batch.save(path, stat)?;
+    batch.write()
+}
+
+pub fn delete_dir_stat(db: &impl KvStore, path: &Path) -> crate::Result<()> {
+    let mut batch = DirStatBatch::new(db);
+    batch.delete(path);
+    batch.write()
+}
+
+// none when the dir was not scanned, an error when its stat does not decode
+pub fn get_dir_stat(db: &impl KvStore, path: &Path) -> crate::Result<Option<DirStat>> {
+    get_dir_stat_by_key(db, &path_key(path))
+}
+
+// for keys read from the indexes, see `key::path_key`
+pub(crate) fn get_dir_stat_by_key(db: &impl KvStore, key: &str) -> crate::Result<Option<DirStat>> {
+    let Some(bytes) = db.get_bytes(TABLE_DIR_STAT, key) else {
+        return Ok(None);
+    };
+    DirStat::decode(&key_path(key), bytes.as_ref()).map(Some)
 }
 
-pub fn get_dir_stat(db: &impl KvStore, path: &Path) -> Option<DirStat> {
-    db.get_as(TABLE_DIR_STAT, path.to_string_lossy())
+// the stat and whether it is still in an older encoding, which the scanner
+// then saves again even when the dir is unchanged
+pub fn load_dir_stat(db: &impl KvStore, path: &Path) -> Option<(DirStat, bool)> {
+    let bytes = db.get_bytes(TABLE_DIR_STAT, path_key(path))?;
+    let stat = decode_or_report(path, bytes.as_ref())?;
+    Some((stat, is_outdated(bytes.as_ref())))
 }
 
 // sum(blocks) on the dir and all sub-dirs recursively
 pub fn get_dir_stat_recursive(db: &impl KvStore, path: &Path) -> Option<DirScanOverview> {
-    let mut overview = DirScanOverview::new();
+    let mut overview = get_subtree_overview(db, path);
+    overview.is_cached = false;
+    Some(overview)
+}
 
-    db.foreach(TABLE_DIR_STAT, path.to_string_lossy(), 0, |_,v| {
-        let res: serde_json::Result<DirStat> = serde_json::from_str(v);
-        if let Ok(stat) = res {
-            overview.blocks += stat.blocks;
-            overview.dirs += stat.subdir_num;
-            overview.files += stat.file_num;
-            overview.is_cached = false;
+// visit the stats of `path` and all of its descendants, siblings sharing
+// the name prefix, e.g. /data/ab for /data/a, sort elsewhere
+pub fn foreach_subtree<F>(db: &impl KvStore, path: &Path, mut callback: F)
+where F: FnMut(&Path, DirStat) {
+    let mut visit = |k: &str, v: &[u8]| {
+        let path = key_path(k);
+        if let Some(stat) = decode_or_report(&path, v) {
+            callback(&path, stat);
         }
-    });
+    };
+    let key = path_key(path);
+    let prefix = subtree_prefix(path);
+    // the root is the only dir whose key starts with its own prefix
+    if !key.starts_with(&prefix) {
+        if let Some(v) = db.get_bytes(TABLE_DIR_STAT, &key) {
+            visit(&key, v.as_ref());
+        }
+    }
+    db.foreach(TABLE_DIR_STAT, &prefix, 0, visit);
+}
 
-    Some(overview)
+pub fn get_subtree_overview(db: &impl KvStore, path: &Path) -> DirScanOverview {
+    let mut overview = DirScanOverview::new();
+    foreach_subtree(db, path, |_, stat| {
+        overview.blocks += stat.blocks;
+        overview.dirs += stat.subdir_num;
+        overview.files += stat.file_num;
+    });
+    overview
 }
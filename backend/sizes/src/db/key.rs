@@ -0,0 +1,200 @@
+use std::ffi::OsString;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+
+use crate::db::{TABLE_DIR_NAME, TABLE_DIR_ORDER, TABLE_DIR_SCAN_RESULT, TABLE_DIR_STAT};
+use crate::kvstore::{Batch, KvStore};
+use crate::scan::DirScanResult;
+
+// keys of dirs are their paths with every `/` turned into `SEP`, which sorts
+// below every other byte of a key. a dir is then followed by its descendants
+// and nothing else, e.g. /data/a, /data/a/x, /data/a-old, and `{key}{SEP}`
+// bounds a prefix walk at the separator. bytes that are not utf-8 and the
+// control bytes used here are written as `ESC` and two hex digits, so every
+// path, utf-8 or not, maps to its own key and back
+pub const SEP: char = '\u{1}';
+const ESC: char = '\u{2}';
+// ends the dir part of a scan result key, `{key}{RESULT_SEP}{u64::MAX - unix}`,
+// the time counts down in 20 digits so the newest result of a dir comes first
+const RESULT_SEP: char = '\u{0}';
+
+const MIGRATE_BATCH_SIZE: usize = 10_000;
+
+fn escape(out: &mut String, byte: u8) {
+    out.push(ESC);
+    out.push_str(&format!("{:02x}", byte));
+}
+
+pub fn path_key(path: &Path) -> String {
+    let bytes = path.as_os_str().as_bytes();
+    let mut key = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '/' => key.push(SEP),
+                RESULT_SEP | SEP | ESC => escape(&mut key, c as u8),
+                c => key.push(c),
+            }
+        }
+        for byte in chunk.invalid() {
+            escape(&mut key, *byte);
+        }
+    }
+    key
+}
+
+pub fn key_path(key: &str) -> PathBuf {
+    let mut bytes = Vec::with_capacity(key.len());
+    let mut chars = key.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            SEP => bytes.push(b'/'),
+            ESC => {
+                let hex: String = chars.by_ref().take(2).collect();
+                bytes.push(u8::from_str_radix(&hex, 16).unwrap_or(b'?'));
+            }
+            c => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    PathBuf::from(OsString::from_vec(bytes))
+}
+
+// the prefix of the keys of all descendants, for the root it is the root key
+// itself, which then comes along in a walk of the prefix
+pub fn subtree_prefix(path: &Path) -> String {
+    let mut prefix = path_key(path);
+    if !prefix.ends_with(SEP) {
+        prefix.push(SEP);
+    }
+    prefix
+}
+
+fn result_ts(ts: u64) -> String {
+    format!("{:020}", u64::MAX - ts)
+}
+
+pub fn scan_result_key(path: &Path, ts: u64) -> String {
+    format!("{}{}", scan_result_prefix(path), result_ts(ts))
+}
+
+// the prefix of the scan results of the dir alone
+pub fn scan_result_prefix(path: &Path) -> String {
+    format!("{}{}", path_key(path), RESULT_SEP)
+}
+
+// the keys of the results of a dir finished between `from` and `to`, both
+// inclusive, as the start and end of a range walk, newest first
+pub fn scan_result_range(path: &Path, from: u64, to: u64) -> (String, String) {
+    let prefix = scan_result_prefix(path);
+    // any longer key sorts after the one of `from` and before the next
+    (format!("{}{}", prefix, result_ts(to)), format!("{}{}~", prefix, result_ts(from)))
+}
+
+// the dir key and the time of a scan result key, none for any other key
+pub fn split_scan_result_key(key: &str) -> Option<(&str, u64)> {
+    let (dir, ts) = key.split_once(RESULT_SEP)?;
+    let countdown: u64 = ts.parse().ok()?;
+    Some((dir, u64::MAX - countdown))
+}
+
+// moves a scan result to its current key, filling in what results saved
+// before `DirScanResult` had them lack
+fn rekey_result(db: &impl KvStore, batch: &mut Batch, old: &str, path: &Path, ts: u64) -> crate::Result<()> {
+    let new = scan_result_key(path, ts);
+    match db.get_as::<DirScanResult>(TABLE_DIR_SCAN_RESULT, old) {
+        Ok(Some(mut result)) => {
+            if result.root.as_os_str().is_empty() {
+                result.root = path.to_path_buf();
+            }
+            if result.finished_at == 0 {
+                result.finished_at = ts;
+                result.started_at = ts.saturating_sub(result.spent);
+            }
+            batch.set_json(TABLE_DIR_SCAN_RESULT, new, &result)?;
+        }
+        Ok(None) => {}
+        // moved as it is, it reads no better under the old key
+        Err(e) => {
+            eprintln!("{}", e);
+            if let Some(value) = db.get_bytes(TABLE_DIR_SCAN_RESULT, old) {
+                batch.set(TABLE_DIR_SCAN_RESULT, new, value);
+            }
+        }
+    }
+    batch.delete(TABLE_DIR_SCAN_RESULT, old);
+    Ok(())
+}
+
+// from keys of plain paths. the old keys are the ones holding a `/`, the new
+// ones never do, so a migration cut short carries on where it stopped on the
+// next open. the indexes hold keys of dirs as well, they are dropped and built
+// again by `ensure_index` and `ensure_name_index`
+pub(crate) fn migrate_plain_paths(db: &impl KvStore) -> crate::Result<()> {
+    let mut stats = Vec::new();
+    db.foreach(TABLE_DIR_STAT, "", 0, |k, _| {
+        if k.contains('/') {
+            stats.push(k.to_string());
+        }
+    });
+    let mut results = Vec::new();
+    db.foreach(TABLE_DIR_SCAN_RESULT, "", 0, |k, _| {
+        if k.contains('/') {
+            results.push(k.to_string());
+        }
+    });
+    if stats.is_empty() && results.is_empty() {
+        return Ok(());
+    }
+    eprintln!("migrating the keys of {} dirs and {} scan results", stats.len(), results.len());
+    let mut batch = Batch::new();
+    batch.delete_prefix(TABLE_DIR_ORDER, "");
+    batch.delete_prefix(TABLE_DIR_NAME, "");
+    db.write(batch)?;
+
+    for chunk in stats.chunks(MIGRATE_BATCH_SIZE) {
+        let mut batch = Batch::new();
+        for old in chunk {
+            if let Some(value) = db.get_bytes(TABLE_DIR_STAT, old) {
+                batch.set(TABLE_DIR_STAT, path_key(Path::new(old)), value);
+            }
+            batch.delete(TABLE_DIR_STAT, old);
+        }
+        db.write(batch)?;
+    }
+    for chunk in results.chunks(MIGRATE_BATCH_SIZE) {
+        let mut batch = Batch::new();
+        for old in chunk {
+            // `{path},{unix}`, the path itself may hold a `,`
+            match old.rsplit_once(',').and_then(|(path, ts)| Some((path, ts.parse().ok()?))) {
+                Some((path, ts)) => rekey_result(db, &mut batch, old, Path::new(path), ts)?,
+                None => batch.delete(TABLE_DIR_SCAN_RESULT, old),
+            }
+        }
+        db.write(batch)?;
+    }
+    Ok(())
+}
+
+// from results keyed by the time itself, oldest first. those are shorter
+// than the 20 digits of the current keys, which tells them apart after an
+// interruption
+pub(crate) fn migrate_result_order(db: &impl KvStore) -> crate::Result<()> {
+    let mut results = Vec::new();
+    db.foreach(TABLE_DIR_SCAN_RESULT, "", 0, |k, _| {
+        if let Some((key, ts)) = k.rsplit_once(RESULT_SEP) {
+            if ts.len() != 20 {
+                if let Ok(ts) = ts.parse::<u64>() {
+                    results.push((k.to_string(), key_path(key), ts));
+                }
+            }
+        }
+    });
+    for chunk in results.chunks(MIGRATE_BATCH_SIZE) {
+        let mut batch = Batch::new();
+        for (old, path, ts) in chunk {
+            rekey_result(db, &mut batch, old, path, *ts)?;
+        }
+        db.write(batch)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,268 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::migrate::copy_store;
+use super::{all_tables, dirorder, open_raw_store, schema, search, stats, Backend, TABLE_CONF};
+use crate::conf;
+use crate::kvstore::memory::MemoryStore;
+use crate::kvstore::store::Store;
+use crate::kvstore::{Batch, KvStore};
+#[cfg(feature = "sqlite")]
+use crate::sqlite::SqliteStore;
+use crate::{unix, Error};
+
+static CONF_KEY_BACKUPS: &str = "backups";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackupRecord {
+    pub path: PathBuf,
+    pub backend: Backend,
+    pub created_at: u64,
+    pub schema_version: u32,
+    pub disk_size: u64,
+    // whether the files are still there, checked when listing
+    #[serde(default)]
+    pub exists: bool,
+}
+
+// the tables to compact, all of them for none
+pub fn check_table(table: Option<&str>) -> crate::Result<Vec<&'static str>> {
+    match table {
+        None => Ok(all_tables().to_vec()),
+        Some(table) => match all_tables().into_iter().find(|t| *t == table) {
+            Some(t) => Ok(vec![t]),
+            None => Err(Error::NotFound(format!("no table {}, expect one of {}", table, all_tables().join(", ")))),
+        },
+    }
+}
+
+pub fn check_backup_target(path: &Path) -> crate::Result<()> {
+    if !path.is_absolute() {
+        return Err(Error::InvalidPath(format!("{} is not an absolute path", path.display())));
+    }
+    if path.exists() {
+        return Err(Error::InvalidPath(format!("{} exists already, back up into a new path", path.display())));
+    }
+    Ok(())
+}
+
+pub fn check_backup_source(path: &Path) -> crate::Result<Backend> {
+    Backend::detect(path).ok_or_else(|| Error::NotFound(format!("no backup at {}", path.display())))
+}
+
+// drops what deletes and overwrites left behind, e.g. after a purge. sqlite
+// rebuilds the whole file whatever the table
+pub fn compact(store: &Store, table: Option<&str>) -> crate::Result<()> {
+    let tables = check_table(table)?;
+    match store {
+        Store::RocksDB(db) => {
+            for table in tables {
+                db.compact_cf(table)?;
+            }
+        }
+        #[cfg(feature = "sqlite")]
+        Store::Sqlite(db) => db.vacuum()?,
+        Store::Memory(_) => {}
+    }
+    Ok(())
+}
+
+// a copy of the database as it is now at `path`, a rocksdb checkpoint or a
+// sqlite file of the same backend, and remembers it for `list_backups`
+pub fn backup(store: &Store, path: &Path) -> crate::Result<BackupRecord> {
+    check_backup_target(path)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let backend = match store {
+        Store::RocksDB(db) => {
+            db.checkpoint(path)?;
+            Backend::RocksDB
+        }
+        #[cfg(feature = "sqlite")]
+        Store::Sqlite(db) => {
+            db.vacuum_into(path)?;
+            Backend::Sqlite
+        }
+        Store::Memory(_) => return Err(Error::Other("a database in memory has no files to back up".to_string())),
+    };
+    let record = BackupRecord {
+        path: path.to_path_buf(),
+        backend,
+        created_at: unix(),
+        schema_version: schema::schema_version(store)?,
+        disk_size: stats::disk_size(path),
+        exists: true,
+    };
+    let mut backups: Vec<BackupRecord> = conf::load_list(store, CONF_KEY_BACKUPS)?;
+    backups.retain(|elem| elem.path != record.path);
+    backups.push(record.clone());
+    store.set_json(TABLE_CONF, CONF_KEY_BACKUPS, &backups)?;
+    Ok(record)
+}
+
+// oldest first
+pub fn list_backups(db: &impl KvStore) -> Vec<BackupRecord> {
+    let mut backups: Vec<BackupRecord> = conf::list_or_report(db, CONF_KEY_BACKUPS);
+    for backup in &mut backups {
+        backup.exists = backup.path.exists();
+    }
+    backups
+}
+
+// the data of a store as it was before a restore, a checkpoint next to the
+// database or a copy for one in memory
+struct Rollback {
+    copy: Store,
+    files: Vec<PathBuf>,
+}
+
+impl Rollback {
+    fn take(store: &Store) -> crate::Result<Rollback> {
+        let (copy, files) = match store {
+            Store::RocksDB(db) => {
+                let path = rollback_path(db.path());
+                let _ = fs::remove_dir_all(&path);
+                db.checkpoint(&path)?;
+                (open_raw_store(Backend::RocksDB, &path, false)?, vec![path])
+            }
+            #[cfg(feature = "sqlite")]
+            Store::Sqlite(db) => {
+                let path = rollback_path(db.path());
+                let _ = fs::remove_file(&path);
+                db.vacuum_into(&path)?;
+                let copy = SqliteStore::open(&path, &all_tables(), false)?;
+                let files = copy.files();
+                (Store::Sqlite(copy), files)
+            }
+            Store::Memory(_) => {
+                let copy = Store::Memory(MemoryStore::new());
+                copy_store(store, &copy)?;
+                (copy, Vec::new())
+            }
+        };
+        Ok(Rollback { copy, files })
+    }
+
+    fn put_back(&self, store: &Store) -> crate::Result<()> {
+        clear(store)?;
+        copy_store(&self.copy, store).map(|_| ())
+    }
+
+    fn remove(self) {
+        drop(self.copy);
+        remove_paths(&self.files);
+    }
+}
+
+fn remove_paths(paths: &[PathBuf]) {
+    for path in paths {
+        let res = if path.is_dir() { fs::remove_dir_all(path) } else { fs::remove_file(path) };
+        if let Err(e) = res {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                eprintln!("remove {} failed, {}", path.display(), e);
+            }
+        }
+    }
+}
+
+// opening a store writes to it, rocksdb its manifest and log and the column
+// families an older backup lacks, sqlite its wal. a restore reads a copy in
+// `dir` so the backup stays as it was taken
+fn copy_backup(path: &Path, dir: &Path) -> crate::Result<PathBuf> {
+    let _ = fs::remove_dir_all(dir);
+    fs::create_dir_all(dir)?;
+    let name = path.file_name().unwrap_or_default();
+    let copy = dir.join(name);
+    if path.is_dir() {
+        copy_dir(path, &copy)?;
+        return Ok(copy);
+    }
+    fs::copy(path, &copy)?;
+    // a wal left by a crash still holds committed data
+    let mut wal = name.to_os_string();
+    wal.push("-wal");
+    if path.with_file_name(&wal).is_file() {
+        fs::copy(path.with_file_name(&wal), dir.join(&wal))?;
+    }
+    Ok(copy)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+fn rollback_path(db_path: &Path) -> PathBuf {
+    let mut name = db_path.file_name().unwrap_or_default().to_os_string();
+    name.push(".rollback");
+    db_path.with_file_name(name)
+}
+
+fn clear(store: &Store) -> crate::Result<()> {
+    let mut batch = Batch::new();
+    for table in all_tables() {
+        batch.delete_prefix(table, "");
+    }
+    store.write(batch)
+}
+
+fn replace_data(store: &Store, backup: &Store, backups: Vec<BackupRecord>) -> crate::Result<Vec<(String, u64)>> {
+    clear(store)?;
+    let counts = copy_store(backup, store)?;
+    store.set_json(TABLE_CONF, CONF_KEY_BACKUPS, &backups)?;
+
+    schema::ensure_schema(store)?;
+    dirorder::ensure_index(store)?;
+    search::ensure_name_index(store)?;
+    Ok(counts)
+}
+
+// replaces every table with the one of the backup at `path`, then migrates
+// what an older backup holds. the records of the backups are kept, they
+// are about the files and not about the data. the data as it was is saved
+// first and put back when any step fails, so a restore is done in full or
+// not at all. returns the entries restored per table
+pub fn restore(store: &Store, path: &Path) -> crate::Result<Vec<(String, u64)>> {
+    let backend = check_backup_source(path)?;
+    let dir = std::env::temp_dir().join(format!("sizes-restore-{}", std::process::id()));
+    let res = copy_backup(path, &dir)
+        .and_then(|copy| open_raw_store(backend, &copy, false))
+        .and_then(|backup| restore_from(store, &backup, path));
+    remove_paths(&[dir]);
+    res
+}
+
+fn restore_from(store: &Store, backup: &Store, path: &Path) -> crate::Result<Vec<(String, u64)>> {
+    let version = schema::schema_version(backup)?;
+    if version > schema::latest_version() {
+        return Err(Error::Storage(format!(
+            "backup schema version {} is newer than {}, the latest this build knows", version, schema::latest_version())));
+    }
+    let backups: Vec<BackupRecord> = conf::load_list(store, CONF_KEY_BACKUPS)?;
+
+    let rollback = Rollback::take(store)?;
+    eprintln!("restoring the database from {}", path.display());
+    let res = replace_data(store, backup, backups);
+    if let Err(e) = &res {
+        eprintln!("restore from {} failed, {}, putting the data back", path.display(), e);
+        if let Err(back) = rollback.put_back(store) {
+            // the checkpoint stays for a restore by hand
+            return Err(Error::Storage(format!(
+                "restore failed, {}, and putting the data back failed too, {}, it is kept at {:?}", e, back, rollback.files)));
+        }
+    }
+    rollback.remove();
+    res
+}
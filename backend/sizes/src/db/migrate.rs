@@ -0,0 +1,48 @@
+use std::path::Path;
+
+use super::{all_tables, open_store, Backend};
+use crate::kvstore::{Batch, KvStore};
+use crate::Error;
+
+const COPY_BATCH_SIZE: usize = 10_000;
+
+// copies every table of `src` into `dst`, keys and values byte for byte,
+// returns the number of entries copied per table
+pub fn copy_store(src: &impl KvStore, dst: &impl KvStore) -> crate::Result<Vec<(String, u64)>> {
+    let mut counts = Vec::new();
+    for table in all_tables() {
+        let mut count = 0;
+        let mut batch = Batch::new();
+        let mut res = Ok(());
+        src.foreach(table, "", 0, |k, v| {
+            if res.is_err() {
+                return;
+            }
+            batch.set(table, k, v);
+            count += 1;
+            if batch.len() >= COPY_BATCH_SIZE {
+                res = dst.write(std::mem::take(&mut batch));
+            }
+        });
+        res?;
+        if !batch.is_empty() {
+            dst.write(batch)?;
+        }
+        counts.push((table.to_string(), count));
+    }
+    Ok(counts)
+}
+
+// copies the database at `src` into a new one of the `to` backend at `dst`
+pub fn migrate(src: &Path, dst: &Path, to: Backend) -> crate::Result<Vec<(String, u64)>> {
+    let Some(from) = Backend::detect(src) else {
+        return Err(Error::NotFound(format!("no database at {}", src.display())));
+    };
+    if dst.exists() {
+        return Err(Error::InvalidPath(format!("{} exists already, migrate into a new path", dst.display())));
+    }
+    let src_store = open_store(from, src, false)?;
+    let dst_store = open_store(to, dst, false)?;
+    eprintln!("copying {:?} {} into {:?} {}", from, src.display(), to, dst.display());
+    copy_store(&src_store, &dst_store)
+}
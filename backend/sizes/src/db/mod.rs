@@ -1,23 +1,169 @@
-use crate::rocksdb::{RocksDB, RocksDBBuilder, StdColumnFamilyConfig};
-use std::path::Path;
-use std::sync::OnceLock;
+use crate::{config, Error};
+use crate::kvstore::store::Store;
+use crate::rocksdb::{is_valid_db, RocksDBBuilder, StdColumnFamilyConfig};
+#[cfg(feature = "sqlite")]
+use crate::sqlite::SqliteStore;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, OnceLock};
 
+pub mod children;
+pub mod codec;
+pub mod dirorder;
 pub mod dirstat;
+pub mod key;
+pub mod maintenance;
+pub mod migrate;
+pub mod purge;
+pub mod retention;
 pub mod scanresult;
+pub mod schema;
+pub mod search;
+pub mod stats;
 
 pub static TABLE_CONF: &str = "confs";
 pub static TABLE_DIR_STAT: &str = "dirs";
 pub static TABLE_DIR_SCAN_RESULT: &str = "dirscanres";
+pub static TABLE_AUDIT_LOG: &str = "auditlog";
+pub static TABLE_DIR_ORDER: &str = "dirorder";
+pub static TABLE_DIR_NAME: &str = "dirname";
 
-static DB: OnceLock<RocksDB> = OnceLock::new();
-pub fn get_db(path: &Path, truncate: bool) -> &'static RocksDB {
-    DB.get_or_init(|| {
-        let db = RocksDBBuilder::new(path.to_string_lossy())
-            .with_column_family(TABLE_CONF, StdColumnFamilyConfig::TINY)
-            .with_column_family(TABLE_DIR_STAT, StdColumnFamilyConfig::HUGE)
-            .with_column_family(TABLE_DIR_SCAN_RESULT, StdColumnFamilyConfig::DEFAULT)
-            .truncate(truncate)
-            .build();
-       db
+// every table of the app, in the order they are created
+pub fn all_tables() -> [&'static str; 6] {
+    [TABLE_CONF, TABLE_DIR_STAT, TABLE_DIR_SCAN_RESULT, TABLE_AUDIT_LOG, TABLE_DIR_ORDER, TABLE_DIR_NAME]
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    // a directory of rocksdb files
+    #[default]
+    RocksDB,
+    // a single sqlite file, needs the `sqlite` feature
+    Sqlite,
+}
+
+impl FromStr for Backend {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rocksdb" => Ok(Backend::RocksDB),
+            "sqlite" => Ok(Backend::Sqlite),
+            _ => Err(Error::Other(format!("unknown backend {:?}, expect rocksdb or sqlite", s))),
+        }
+    }
+}
+
+impl Backend {
+    // what is at `path` already, none when there is nothing
+    pub fn detect(path: &Path) -> Option<Backend> {
+        if is_valid_db(path) {
+            Some(Backend::RocksDB)
+        } else if path.is_file() {
+            Some(Backend::Sqlite)
+        } else {
+            None
+        }
+    }
+}
+
+// a shared handle of an open database, it is closed when the last clone is dropped
+pub type DbHandle = Arc<Store>;
+
+// opens a store of the given backend with all the tables of the app, as it
+// is, for reading e.g. a backup without migrating it
+pub fn open_raw_store(backend: Backend, path: &Path, truncate: bool) -> crate::Result<Store> {
+    let conf = &config::get().db;
+    Ok(match backend {
+        Backend::RocksDB => Store::RocksDB(
+            RocksDBBuilder::new(path.to_string_lossy())
+                .with_cache_sizes(conf.write_buffer_mb << 20, conf.block_cache_mb << 20)
+                .with_column_family(TABLE_CONF, StdColumnFamilyConfig::TINY)
+                .with_column_family(TABLE_DIR_STAT, StdColumnFamilyConfig::HUGE)
+                .with_column_family(TABLE_DIR_SCAN_RESULT, StdColumnFamilyConfig::DEFAULT)
+                .with_column_family(TABLE_AUDIT_LOG, StdColumnFamilyConfig::TINY)
+                .with_column_family(TABLE_DIR_ORDER, StdColumnFamilyConfig::DEFAULT)
+                .with_column_family(TABLE_DIR_NAME, StdColumnFamilyConfig::DEFAULT)
+                .truncate(truncate)
+                .build()?,
+        ),
+        #[cfg(feature = "sqlite")]
+        Backend::Sqlite => Store::Sqlite(SqliteStore::open(path, &all_tables(), truncate)?),
+        #[cfg(not(feature = "sqlite"))]
+        Backend::Sqlite => return Err(Error::Other("built without the sqlite feature".to_string())),
     })
 }
+
+// opens a store of the given backend, brought up to the current schema and
+// with its indexes built
+pub fn open_store(backend: Backend, path: &Path, truncate: bool) -> crate::Result<Store> {
+    let store = open_raw_store(backend, path, truncate)?;
+    schema::ensure_schema(&store)?;
+    if let Err(e) = dirorder::ensure_index(&store) {
+        eprintln!("build the order index of {} failed, {}", path.display(), e);
+    }
+    if let Err(e) = search::ensure_name_index(&store) {
+        eprintln!("build the name index of {} failed, {}", path.display(), e);
+    }
+    Ok(store)
+}
+
+// opens a database with the configured backend, independent of any other handle
+pub fn open_db(path: &Path, truncate: bool) -> crate::Result<DbHandle> {
+    Ok(Arc::new(open_store(config::get().db.backend, path, truncate)?))
+}
+
+// the databases open in this process by path, a path is opened only once
+// and every caller asking for it shares the same handle
+#[derive(Default)]
+pub struct DbRegistry {
+    dbs: Mutex<HashMap<PathBuf, DbHandle>>,
+}
+
+impl DbRegistry {
+    pub fn new() -> Self {
+        DbRegistry::default()
+    }
+
+    pub fn open(&self, path: &Path, truncate: bool) -> crate::Result<DbHandle> {
+        let mut dbs = self.dbs.lock().unwrap();
+        if let Some(db) = dbs.get(path) {
+            if truncate {
+                return Err(Error::Busy(format!("database {} is open, close it before truncating", path.display())));
+            }
+            return Ok(db.clone());
+        }
+        let db = open_db(path, truncate)?;
+        dbs.insert(path.to_path_buf(), db.clone());
+        Ok(db)
+    }
+
+    pub fn get(&self, path: &Path) -> Option<DbHandle> {
+        self.dbs.lock().unwrap().get(path).cloned()
+    }
+
+    // forgets the database, it stays usable through the handles already given out
+    pub fn close(&self, path: &Path) -> Option<DbHandle> {
+        self.dbs.lock().unwrap().remove(path)
+    }
+
+    pub fn list(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self.dbs.lock().unwrap().keys().cloned().collect();
+        paths.sort();
+        paths
+    }
+}
+
+pub fn registry() -> &'static DbRegistry {
+    static REGISTRY: OnceLock<DbRegistry> = OnceLock::new();
+    REGISTRY.get_or_init(DbRegistry::new)
+}
+
+pub fn get_db(path: &Path, truncate: bool) -> DbHandle {
+    registry()
+        .open(path, truncate)
+        .unwrap_or_else(|e| panic!("open database failed, {}", e))
+}
@@ -0,0 +1,68 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::key::{path_key, scan_result_prefix, subtree_prefix};
+use super::{dirorder, dirstat, search};
+use crate::conf::{self, WatchDirectoryConfiguration};
+use crate::db::{TABLE_DIR_SCAN_RESULT, TABLE_DIR_STAT};
+use crate::kvstore::{Batch, KvStore};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PurgeStats {
+    pub dirs: u64,
+    pub scan_results: u64,
+}
+
+fn count(db: &impl KvStore, table: &str, prefix: &str) -> u64 {
+    let mut n = 0;
+    db.foreach(table, prefix, 0, |_, _| n += 1);
+    n
+}
+
+// drops the stats, index entries and scan results of `path` and everything
+// below it, all in one batch. the parent keeps listing the dir until it is
+// scanned again
+pub fn purge_subtree(db: &impl KvStore, path: &Path) -> crate::Result<PurgeStats> {
+    let key = path_key(path);
+    let children = subtree_prefix(path);
+    let mut batch = Batch::new();
+    let mut stats = PurgeStats::default();
+
+    // the index keys do not start with the key of the dir, they go one by one
+    dirstat::foreach_subtree(db, path, |path, stat| {
+        let key = path_key(path);
+        search::update_name_index(&mut batch, &key, true, false);
+        dirorder::update_index(&mut batch, &key, Some(&stat), None);
+        stats.dirs += 1;
+    });
+    batch.delete(TABLE_DIR_STAT, &key);
+    batch.delete_prefix(TABLE_DIR_STAT, &children);
+
+    let own_results = scan_result_prefix(path);
+    stats.scan_results = count(db, TABLE_DIR_SCAN_RESULT, &children);
+    batch.delete_prefix(TABLE_DIR_SCAN_RESULT, &children);
+    // for the root the subtree prefix covers them already
+    if !own_results.starts_with(&children) {
+        stats.scan_results += count(db, TABLE_DIR_SCAN_RESULT, &own_results);
+        batch.delete_prefix(TABLE_DIR_SCAN_RESULT, &own_results);
+    }
+
+    dirorder::write_counted(db, batch, -(stats.dirs as i64))?;
+    Ok(stats)
+}
+
+fn overlaps(a: &str, b: &str) -> bool {
+    let (a, b) = (Path::new(a), Path::new(b));
+    a.starts_with(b) || b.starts_with(a)
+}
+
+// stops watching a dir and drops its data, unless another watch still
+// covers part of it, i.e. is the same dir, an ancestor or a descendant
+pub fn remove_watch_and_data(db: &impl KvStore, watch: &WatchDirectoryConfiguration) -> crate::Result<PurgeStats> {
+    conf::remove_watch(db, watch)?;
+    if conf::list_watch(db).iter().any(|other| overlaps(&other.path, &watch.path)) {
+        return Ok(PurgeStats::default());
+    }
+    purge_subtree(db, Path::new(&watch.path))
+}
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use super::dirstat::{self, DirStatBatch};
+use super::key::{key_path, split_scan_result_key};
+use super::{TABLE_CONF, TABLE_DIR_SCAN_RESULT, TABLE_DIR_STAT};
+use crate::config::RetentionConfig;
+use crate::kvstore::{Batch, KvStore};
+
+static CONF_KEY_RETENTION: &str = "retention";
+
+const DAY: u64 = 24 * 3600;
+// deletes written per batch
+const BATCH_SIZE: usize = 10_000;
+
+// what the last run removed
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionReport {
+    pub ran_at: u64,
+    pub scan_results: u64,
+    pub dirs: u64,
+}
+
+pub fn is_enabled(policy: &RetentionConfig) -> bool {
+    policy.scan_results > 0 || policy.max_age_days > 0 || policy.stale_dir_days > 0
+}
+
+fn cutoff(days: u64, now: u64) -> Option<u64> {
+    (days > 0).then(|| now.saturating_sub(days * DAY))
+}
+
+// the keys of the results beyond the limits, and the time of the newest
+// result per dir key. the newest result of a dir is always kept, it is what
+// the dir looks like now
+fn expired_scan_results(db: &impl KvStore, policy: &RetentionConfig, now: u64) -> (Vec<String>, HashMap<String, u64>) {
+    let oldest = cutoff(policy.max_age_days, now);
+    let mut expired = Vec::new();
+    let mut newest: HashMap<String, u64> = HashMap::new();
+    let mut current = String::new();
+    let mut kept = 0;
+    // newest first within each dir
+    db.foreach(TABLE_DIR_SCAN_RESULT, "", 0, |k, _| {
+        let Some((dir, ts)) = split_scan_result_key(k) else {
+            return;
+        };
+        if dir != current {
+            current = dir.to_string();
+            kept = 0;
+            newest.insert(current.clone(), ts);
+        }
+        let too_many = policy.scan_results > 0 && kept >= policy.scan_results;
+        let too_old = kept > 0 && oldest.is_some_and(|oldest| ts < oldest);
+        if too_many || too_old {
+            expired.push(k.to_string());
+        } else {
+            kept += 1;
+        }
+    });
+    (expired, newest)
+}
+
+struct Visited {
+    path: PathBuf,
+    seen: u64,
+    sub_dirs: Vec<PathBuf>,
+}
+
+// the dirs no scan reached since `oldest`. an unchanged dir is not saved
+// again, so the last visit is the newest of its own time, the last scan of
+// it as a root and the last visit of its parent while the parent still lists
+// it. a dir is followed by its descendants in key order, the stack holds the
+// ancestors of the current one
+fn stale_dirs(db: &impl KvStore, oldest: u64, roots: &HashMap<String, u64>) -> Vec<PathBuf> {
+    let mut stale = Vec::new();
+    let mut stack: Vec<Visited> = Vec::new();
+    db.foreach(TABLE_DIR_STAT, "", 0, |k, v| {
+        let path = key_path(k);
+        let Some(stat) = dirstat::decode_or_report(&path, v) else {
+            return;
+        };
+        while stack.last().is_some_and(|top| !path.starts_with(&top.path)) {
+            stack.pop();
+        }
+        let mut seen = stat.ts.max(roots.get(k).copied().unwrap_or(0));
+        if let (Some(parent), Some(name)) = (stack.last(), path.file_name()) {
+            if path.parent() == Some(parent.path.as_path()) && parent.sub_dirs.iter().any(|d| d.as_os_str() == name) {
+                seen = seen.max(parent.seen);
+            }
+        }
+        if seen < oldest {
+            stale.push(path.clone());
+        }
+        stack.push(Visited { path, seen, sub_dirs: stat.sub_dirs });
+    });
+    stale
+}
+
+// drops the scan results and dir stats beyond the limits of `policy`, as of
+// `now` in unix seconds, and keeps the report for `last_report`
+pub fn apply_retention(db: &impl KvStore, policy: &RetentionConfig, now: u64) -> crate::Result<RetentionReport> {
+    let mut report = RetentionReport { ran_at: now, ..Default::default() };
+    let (expired, newest) = expired_scan_results(db, policy, now);
+    for keys in expired.chunks(BATCH_SIZE) {
+        let mut batch = Batch::new();
+        for key in keys {
+            batch.delete(TABLE_DIR_SCAN_RESULT, key);
+        }
+        db.write(batch)?;
+        report.scan_results += keys.len() as u64;
+    }
+
+    if let Some(oldest) = cutoff(policy.stale_dir_days, now) {
+        // through `DirStatBatch`, which drops their index entries too
+        for paths in stale_dirs(db, oldest, &newest).chunks(BATCH_SIZE) {
+            let mut batch = DirStatBatch::new(db);
+            for path in paths {
+                batch.delete(path);
+            }
+            batch.write()?;
+            report.dirs += paths.len() as u64;
+        }
+    }
+
+    db.set_json(TABLE_CONF, CONF_KEY_RETENTION, &report)?;
+    Ok(report)
+}
+
+pub fn last_report(db: &impl KvStore) -> Option<RetentionReport> {
+    db.get_as(TABLE_CONF, CONF_KEY_RETENTION).unwrap_or_else(|e| {
+        eprintln!("read {} failed, {}", CONF_KEY_RETENTION, e);
+        None
+    })
+}
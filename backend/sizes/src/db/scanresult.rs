@@ -1,34 +1,84 @@
 use std::path::Path;
 
+use super::dirorder::{self, Direction, Orderby};
 use super::dirstat::DirStat;
+use crate::db::key::{scan_result_key, scan_result_prefix, scan_result_range, split_scan_result_key};
 use crate::db::TABLE_DIR_SCAN_RESULT;
-use crate::kvstore::KvStore;
+use crate::kvstore::{Batch, KvStore};
 use crate::scan::DirScanResult;
 use crate::unix;
 
 pub fn save_dir_scan_result(
     db: &impl KvStore,
     path: &Path,
-    result: &'static DirScanResult,
+    result: &DirScanResult,
 ) -> crate::Result<()> {
-    let key = format!("{},{}", path.to_string_lossy(), unix());
-    db.set_json(TABLE_DIR_SCAN_RESULT, key, result)
+    let mut batch = Batch::new();
+    add_dir_scan_result(&mut batch, path, result)?;
+    db.write(batch)
 }
 
+// stages the result in a batch, to be saved along with other writes
+pub fn add_dir_scan_result(batch: &mut Batch, path: &Path, result: &DirScanResult) -> crate::Result<()> {
+    let ts = match result.finished_at {
+        0 => unix(),
+        ts => ts,
+    };
+    batch.set_json(TABLE_DIR_SCAN_RESULT, scan_result_key(path, ts), result)
+}
+
+fn collect_results(results: &mut Vec<DirScanResult>) -> impl FnMut(&str, &[u8]) + '_ {
+    |k, v| {
+        match serde_json::from_slice(v) {
+            Ok(result) => results.push(result),
+            Err(e) => eprintln!("decode scan result {:?} failed, {}", k, e),
+        }
+    }
+}
+
+// the results of the dir, newest first
 pub fn get_dir_scan_result(
     db: &impl KvStore,
     path: &Path,
     limit: u32,
 ) -> crate::Result<Vec<DirScanResult>> {
     let mut results: Vec<DirScanResult> = Vec::new();
+    db.foreach(TABLE_DIR_SCAN_RESULT, scan_result_prefix(path), limit, collect_results(&mut results));
+    Ok(results)
+}
 
-    db.foreach(TABLE_DIR_SCAN_RESULT, path.to_string_lossy(), limit, |_,v| {
-        let elem: serde_json::Result<DirScanResult> = serde_json::from_str(v);
-        if let Ok(result) = elem {
-            results.push(result);
+// the results finished between `from` and `to`, in unix seconds and both
+// inclusive, newest first
+pub fn get_dir_scan_results_between(
+    db: &impl KvStore,
+    path: &Path,
+    from: u64,
+    to: u64,
+    limit: u32,
+) -> crate::Result<Vec<DirScanResult>> {
+    let mut results: Vec<DirScanResult> = Vec::new();
+    if from > to {
+        return Ok(results);
+    }
+    let (start, end) = scan_result_range(path, from, to);
+    db.foreach_range(TABLE_DIR_SCAN_RESULT, start, end, limit, collect_results(&mut results));
+    Ok(results)
+}
+
+// when the newest scan of the dir as a root finished, 0 if there was none
+pub fn last_scan_time(db: &impl KvStore, path: &Path) -> u64 {
+    let mut ts = 0;
+    db.foreach(TABLE_DIR_SCAN_RESULT, scan_result_prefix(path), 1, |k, _| {
+        if let Some((_, finished)) = split_scan_result_key(k) {
+            ts = finished;
         }
     });
-    Ok(results)
+    ts
+}
+
+// the last result finished at or before `ts`, what the dir looked like then
+pub fn get_dir_scan_result_at(db: &impl KvStore, path: &Path, ts: u64) -> Option<DirScanResult> {
+    get_dir_scan_results_between(db, path, 0, ts, 1).ok()?.into_iter().next()
 }
 
 pub fn get_largest_dirs(
@@ -37,9 +87,8 @@ pub fn get_largest_dirs(
     limit: u32,
     offset: u32
 ) -> crate::Result<Vec<DirStat>> {
-    let mut ret: Vec<DirStat> = Vec::new();
-    // TODO implement this endpoint
-    Ok(ret)
+    let page = dirorder::list_dirs(db, Orderby::Block, Direction::Desc, min, offset, limit)?;
+    Ok(page.items)
 }
 
 pub fn get_last_dir_scan_result(db: &impl KvStore, path: &Path) -> Option<DirScanResult> {
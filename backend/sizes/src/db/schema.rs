@@ -0,0 +1,62 @@
+use crate::db::{key, TABLE_CONF};
+use crate::kvstore::store::Store;
+use crate::kvstore::KvStore;
+use crate::Error;
+
+static CONF_SCHEMA_VERSION: &str = "schema_version";
+
+// a change of the stored data, `run` takes a database of the version before
+// up to `version`. it may be cut short and run again, so it has to tell the
+// data it moved already from the data still to move, and leave a database it
+// finds moved as it is
+pub struct Migration<S> {
+    pub version: u32,
+    pub name: &'static str,
+    pub run: fn(&S) -> crate::Result<()>,
+}
+
+// all migrations in the order they run, a new one goes last with the next
+// version. a change the readers cope with on their own needs none, e.g. the
+// binary dir stats replace the json ones as the dirs are scanned again
+pub fn migrations<S: KvStore>() -> Vec<Migration<S>> {
+    vec![
+        Migration { version: 1, name: "keys of dirs with separators", run: key::migrate_plain_paths },
+        Migration { version: 2, name: "scan results newest first", run: key::migrate_result_order },
+    ]
+}
+
+// the version `ensure_schema` brings a database to
+pub fn latest_version() -> u32 {
+    migrations::<Store>().last().map_or(0, |m| m.version)
+}
+
+// the version the data of the database is at, 0 for one older than any
+// migration and for a new one
+pub fn schema_version(db: &impl KvStore) -> crate::Result<u32> {
+    let version = db.get_string(TABLE_CONF, CONF_SCHEMA_VERSION);
+    if version.is_empty() {
+        return Ok(0);
+    }
+    version.parse().map_err(|_| Error::Decode(format!("schema version {:?}", version)))
+}
+
+// runs the migrations the database has not had yet, one after the other,
+// and returns the version it is at then. a database written by a later build
+// is refused, this one would misread what it does not know of
+pub fn ensure_schema<S: KvStore>(db: &S) -> crate::Result<u32> {
+    let from = schema_version(db)?;
+    let mut version = from;
+    let migrations = migrations::<S>();
+    let latest = latest_version();
+    if from > latest {
+        return Err(Error::Storage(format!(
+            "database schema version {} is newer than {}, the latest this build knows", from, latest)));
+    }
+    for migration in migrations.iter().filter(|m| m.version > from) {
+        eprintln!("migrating the database to schema version {}, {}", migration.version, migration.name);
+        (migration.run)(db)?;
+        db.set(TABLE_CONF, CONF_SCHEMA_VERSION, migration.version.to_string())?;
+        version = migration.version;
+    }
+    Ok(version)
+}
@@ -0,0 +1,271 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+use super::dirorder::{Direction, Orderby, Page};
+use super::dirstat::{self, DirStat};
+use crate::db::key::key_path;
+use crate::db::{TABLE_DIR_NAME, TABLE_DIR_STAT};
+use crate::kvstore::{Batch, KvStore};
+use crate::Error;
+
+// the name index keeps one key per dir, `{lowercase name}/{key of the dir}`
+// with an empty value, neither a name nor a key contains `/` so it ends the
+// name, the root has an empty name. matching walks these keys only, the stats
+// are read for the matches alone
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchMode {
+    #[default]
+    Substring,
+    // `*`, `?` and `[...]` against the whole name or path, `**` also crosses `/`
+    Glob,
+    Regex,
+}
+
+impl FromStr for MatchMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> crate::Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "substring" => Ok(MatchMode::Substring),
+            "glob" => Ok(MatchMode::Glob),
+            "regex" => Ok(MatchMode::Regex),
+            _ => Err(Error::Other(format!("invalid mode {:?}, expect substring, glob or regex", s))),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchTarget {
+    // the last component of the path
+    #[default]
+    Name,
+    Path,
+}
+
+impl FromStr for MatchTarget {
+    type Err = Error;
+
+    fn from_str(s: &str) -> crate::Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "name" => Ok(MatchTarget::Name),
+            "path" => Ok(MatchTarget::Path),
+            _ => Err(Error::Other(format!("invalid target {:?}, expect name or path", s))),
+        }
+    }
+}
+
+pub const DEFAULT_SEARCH_LIMIT: u32 = 100;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchQuery {
+    pub pattern: String,
+    pub mode: MatchMode,
+    pub target: MatchTarget,
+    pub case_sensitive: bool,
+    // bounds on the blocks of the dir and everything below it, inclusive
+    pub min_blocks: Option<u64>,
+    pub max_blocks: Option<u64>,
+    // bounds on the mtime of the dir, in unix seconds
+    pub modified_after: Option<i64>,
+    pub modified_before: Option<i64>,
+    pub orderby: Orderby,
+    pub direction: Direction,
+    pub offset: u32,
+    // 0 means `DEFAULT_SEARCH_LIMIT`
+    pub limit: u32,
+}
+
+enum Matcher {
+    Substring(String),
+    Regex(Regex),
+}
+
+impl Matcher {
+    fn new(query: &SearchQuery) -> crate::Result<Matcher> {
+        let source = match query.mode {
+            MatchMode::Substring if query.case_sensitive => return Ok(Matcher::Substring(query.pattern.clone())),
+            MatchMode::Substring => return Ok(Matcher::Substring(query.pattern.to_lowercase())),
+            MatchMode::Glob => glob_to_regex(&query.pattern),
+            MatchMode::Regex => query.pattern.clone(),
+        };
+        RegexBuilder::new(&source)
+            .case_insensitive(!query.case_sensitive)
+            .build()
+            .map(Matcher::Regex)
+            .map_err(|e| Error::Other(format!("invalid pattern {:?}, {}", query.pattern, e)))
+    }
+
+    fn is_match(&self, text: &str, case_sensitive: bool) -> bool {
+        match self {
+            Matcher::Substring(pattern) if case_sensitive => text.contains(pattern.as_str()),
+            Matcher::Substring(pattern) => text.to_lowercase().contains(pattern.as_str()),
+            Matcher::Regex(regex) => regex.is_match(text),
+        }
+    }
+}
+
+// a glob is anchored at both ends, like a shell matching file names
+pub fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                regex.push_str(".*");
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                let mut class = String::new();
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    if c == ']' && !class.is_empty() {
+                        closed = true;
+                        break;
+                    }
+                    class.push(c);
+                }
+                if !closed {
+                    regex.push_str(&regex::escape(&format!("[{}", class)));
+                    continue;
+                }
+                let class = match class.strip_prefix('!') {
+                    Some(rest) => format!("^{}", rest),
+                    None => class,
+                };
+                regex.push('[');
+                regex.push_str(&class.replace('\\', "\\\\").replace('[', "\\["));
+                regex.push(']');
+            }
+            _ => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+// the part of a glob before its first wildcard, every matching name starts with it
+fn glob_literal_prefix(glob: &str) -> &str {
+    let end = glob.find(['*', '?', '[']).unwrap_or(glob.len());
+    &glob[..end]
+}
+
+fn dir_name(path: &str) -> &str {
+    Path::new(path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(path)
+}
+
+fn index_key(key: &str) -> String {
+    let path = key_path(key);
+    let name = path.file_name().map(|name| name.to_string_lossy().to_lowercase());
+    format!("{}/{}", name.unwrap_or_default(), key)
+}
+
+// adds or removes the name index entry of a dir, names never change for a path
+pub(crate) fn update_name_index(batch: &mut Batch, key: &str, existed: bool, exists: bool) {
+    match (existed, exists) {
+        (false, true) => batch.set(TABLE_DIR_NAME, index_key(key), ""),
+        (true, false) => batch.delete(TABLE_DIR_NAME, index_key(key)),
+        _ => (),
+    }
+}
+
+// databases written before the index existed have stats but no index entries
+pub fn ensure_name_index(db: &impl KvStore) -> crate::Result<()> {
+    let mut has_stats = false;
+    db.foreach(TABLE_DIR_STAT, "", 1, |_, _| has_stats = true);
+    let mut has_index = false;
+    db.foreach(TABLE_DIR_NAME, "", 1, |_, _| has_index = true);
+    if !has_stats || has_index {
+        return Ok(());
+    }
+
+    let mut keys = Vec::new();
+    db.foreach(TABLE_DIR_STAT, "", 0, |k, _| keys.push(k.to_string()));
+    eprintln!("building the name index of {} dirs", keys.len());
+    let mut batch = Batch::new();
+    for key in &keys {
+        update_name_index(&mut batch, key, false, true);
+    }
+    db.write(batch)
+}
+
+// the subtree is only walked when the blocks of the dir alone do not decide,
+// the total is never below them
+fn in_size_bounds(db: &impl KvStore, stat: &DirStat, query: &SearchQuery) -> bool {
+    if query.max_blocks.is_some_and(|max| stat.blocks > max) {
+        return false;
+    }
+    if query.max_blocks.is_none() && query.min_blocks.is_none_or(|min| stat.blocks >= min) {
+        return true;
+    }
+    let total = dirstat::get_subtree_overview(db, &stat.path).blocks;
+    query.min_blocks.is_none_or(|min| total >= min) && query.max_blocks.is_none_or(|max| total <= max)
+}
+
+// the scanned dirs matching the query, sorted like `dirorder::list_dirs`
+// and dirs with the same value by name. every match is read for the total
+// and the sort, the page is taken from them
+pub fn search(db: &impl KvStore, query: &SearchQuery) -> crate::Result<Page<DirStat>> {
+    let matcher = Matcher::new(query)?;
+    let limit = match query.limit {
+        0 => DEFAULT_SEARCH_LIMIT,
+        limit => limit,
+    };
+    // names starting with a literal can be looked up instead of walking every key
+    let prefix = match (query.mode, query.target) {
+        (MatchMode::Glob, MatchTarget::Name) => glob_literal_prefix(&query.pattern).to_lowercase(),
+        _ => String::new(),
+    };
+
+    let mut found = Vec::new();
+    let mut failed = None;
+    db.foreach_while(TABLE_DIR_NAME, &prefix, |k, _| {
+        let Some((_, key)) = k.split_once('/') else {
+            return true;
+        };
+        let path = key_path(key);
+        let path = path.to_string_lossy();
+        let text = match query.target {
+            MatchTarget::Name => dir_name(&path),
+            MatchTarget::Path => &path,
+        };
+        if !matcher.is_match(text, query.case_sensitive) {
+            return true;
+        }
+        let stat = match dirstat::get_dir_stat_by_key(db, key) {
+            Ok(Some(stat)) => stat,
+            Ok(None) => return true,
+            Err(e) => {
+                failed = Some(e);
+                return false;
+            }
+        };
+        if query.modified_after.is_some_and(|after| stat.mtime < after)
+            || query.modified_before.is_some_and(|before| stat.mtime > before)
+            || !in_size_bounds(db, &stat, query)
+        {
+            return true;
+        }
+        found.push(stat);
+        true
+    });
+    if let Some(e) = failed {
+        return Err(e);
+    }
+    match query.direction {
+        Direction::Asc => found.sort_by_key(|stat| query.orderby.value(stat)),
+        Direction::Desc => found.sort_by_key(|stat| std::cmp::Reverse(query.orderby.value(stat))),
+    }
+    Ok(Page::of(found, query.offset, limit))
+}
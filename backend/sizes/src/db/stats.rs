@@ -0,0 +1,136 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::{all_tables, schema};
+use crate::kvstore::store::Store;
+use crate::kvstore::KvStore;
+use crate::rocksdb::property::{
+    KBackgroundErrors, KCompressionRatioAtLevelPrefix, KEstimateLiveDataSize, KEstimateNumKeys,
+    KNumFilesAtLevelPrefix, KTotalSstFilesSize,
+};
+use crate::rocksdb::{RocksDB, NUM_LEVELS};
+#[cfg(feature = "sqlite")]
+use crate::sqlite::SqliteStore;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LevelStats {
+    pub level: i32,
+    pub files: u64,
+    // uncompressed over compressed size of the files at the level
+    pub compression_ratio: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TableStats {
+    pub name: String,
+    // an estimate for rocksdb, counted for the other backends
+    pub keys: u64,
+    // the bytes of the keys and values, before compression
+    pub live_data_size: u64,
+    pub sst_files_size: u64,
+    // the levels holding any files, rocksdb only
+    pub levels: Vec<LevelStats>,
+}
+
+// what the database itself costs, next to the dirs it describes
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DbStats {
+    pub backend: String,
+    pub path: PathBuf,
+    pub schema_version: u32,
+    // every file of the database on disk, logs included
+    pub disk_size: u64,
+    pub background_errors: u64,
+    pub tables: Vec<TableStats>,
+}
+
+fn parse<T: std::str::FromStr + Default>(value: &str) -> T {
+    value.trim().parse().unwrap_or_default()
+}
+
+pub(crate) fn disk_size(path: &Path) -> u64 {
+    let Ok(meta) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !meta.is_dir() {
+        return meta.len();
+    }
+    let Ok(entries) = fs::read_dir(path) else {
+        return 0;
+    };
+    entries.flatten().map(|entry| disk_size(&entry.path())).sum()
+}
+
+fn rocksdb_table_stats(db: &RocksDB, table: &str) -> crate::Result<TableStats> {
+    let mut levels = Vec::new();
+    for level in 0..NUM_LEVELS {
+        let files: u64 = parse(&db.get_property_cf_with_prefix(table, KNumFilesAtLevelPrefix, level)?);
+        if files == 0 {
+            continue;
+        }
+        let compression_ratio = parse(&db.get_property_cf_with_prefix(table, KCompressionRatioAtLevelPrefix, level)?);
+        levels.push(LevelStats { level, files, compression_ratio });
+    }
+    Ok(TableStats {
+        name: table.to_string(),
+        keys: db.get_int_property_cf(table, KEstimateNumKeys)?,
+        live_data_size: db.get_int_property_cf(table, KEstimateLiveDataSize)?,
+        sst_files_size: db.get_int_property_cf(table, KTotalSstFilesSize)?,
+        levels,
+    })
+}
+
+fn rocksdb_stats(db: &RocksDB) -> crate::Result<DbStats> {
+    Ok(DbStats {
+        backend: "rocksdb".to_string(),
+        path: db.path().to_path_buf(),
+        disk_size: disk_size(db.path()),
+        background_errors: parse(&db.get_property(KBackgroundErrors)),
+        tables: all_tables().iter().map(|table| rocksdb_table_stats(db, table)).collect::<crate::Result<_>>()?,
+        ..Default::default()
+    })
+}
+
+#[cfg(feature = "sqlite")]
+fn sqlite_stats(db: &SqliteStore) -> crate::Result<DbStats> {
+    let mut tables = Vec::new();
+    for table in all_tables() {
+        let (keys, live_data_size) = db.table_size(table)?;
+        tables.push(TableStats { name: table.to_string(), keys, live_data_size, ..Default::default() });
+    }
+    Ok(DbStats {
+        backend: "sqlite".to_string(),
+        path: db.path().to_path_buf(),
+        disk_size: db.files().iter().map(|file| disk_size(file)).sum(),
+        tables,
+        ..Default::default()
+    })
+}
+
+fn memory_stats(db: &impl KvStore) -> DbStats {
+    let tables = all_tables()
+        .iter()
+        .map(|table| {
+            let mut stats = TableStats { name: table.to_string(), ..Default::default() };
+            db.foreach(table, "", 0, |k, v| {
+                stats.keys += 1;
+                stats.live_data_size += (k.len() + v.len()) as u64;
+            });
+            stats
+        })
+        .collect();
+    DbStats { backend: "memory".to_string(), tables, ..Default::default() }
+}
+
+pub fn db_stats(store: &Store) -> crate::Result<DbStats> {
+    let mut stats = match store {
+        Store::RocksDB(db) => rocksdb_stats(db)?,
+        #[cfg(feature = "sqlite")]
+        Store::Sqlite(db) => sqlite_stats(db)?,
+        Store::Memory(db) => memory_stats(db),
+    };
+    stats.schema_version = schema::schema_version(store)?;
+    Ok(stats)
+}
@@ -0,0 +1,222 @@
+use std::cmp::Reverse;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::db::dirstat::{self, DirStat};
+use crate::kvstore::KvStore;
+use crate::scan::BLOCK_SIZE;
+use crate::{unix, Error};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    // https://dev.yorhel.nl/ncdu/jsonfmt
+    Ncdu,
+    // path, blocks, files, dirs, mtime, one line per directory
+    Csv,
+    // nested `DirTreeNode`s
+    Json,
+}
+
+impl FromStr for ExportFormat {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> crate::Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "ncdu" => Ok(ExportFormat::Ncdu),
+            "csv" => Ok(ExportFormat::Csv),
+            "json" => Ok(ExportFormat::Json),
+            _ => Err(format!("unknown export format {:?}, expect ncdu, csv or json", s).into()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DirTreeNode {
+    pub name: String,
+    pub path: PathBuf,
+    pub mtime: i64,
+    // totals of the directory and everything below it
+    pub blocks: u64,
+    pub files: u64,
+    pub dirs: u64,
+    // the files directly in this directory
+    pub own_blocks: u64,
+    pub own_files: u64,
+    // false when the depth limit cut off the children
+    pub expanded: bool,
+    pub children: Vec<DirTreeNode>,
+}
+
+pub fn export(
+    db: &impl KvStore,
+    root: &Path,
+    format: ExportFormat,
+    max_depth: Option<u32>,
+    out: &mut impl Write,
+) -> crate::Result<()> {
+    match format {
+        ExportFormat::Ncdu => export_ncdu(db, root, out),
+        ExportFormat::Csv => export_csv(db, root, out),
+        ExportFormat::Json => export_json_tree(db, root, max_depth, out),
+    }
+}
+
+// the ncdu format has no place for per directory file counts, the files of a
+// directory are accounted to the directory entry itself so the totals match
+pub fn export_ncdu(db: &impl KvStore, root: &Path, out: &mut impl Write) -> crate::Result<()> {
+    let Some(stat) = dirstat::get_dir_stat(db, root)? else {
+        return Err(Error::NotFound(format!("{} has not been scanned", root.display())));
+    };
+    writeln!(
+        out,
+        "[1,2,{{\"progname\":\"sizes\",\"progver\":{},\"timestamp\":{}}},",
+        serde_json::to_string(env!("CARGO_PKG_VERSION")).unwrap(),
+        unix()
+    )?;
+    write_ncdu_dir(db, &root.to_string_lossy(), root, &stat, out)?;
+    writeln!(out, "]")?;
+    Ok(())
+}
+
+fn write_ncdu_dir(
+    db: &impl KvStore,
+    name: &str,
+    path: &Path,
+    stat: &DirStat,
+    out: &mut impl Write,
+) -> crate::Result<()> {
+    let size = stat.blocks * BLOCK_SIZE;
+    write!(
+        out,
+        "[{{\"name\":{},\"asize\":{},\"dsize\":{}",
+        serde_json::to_string(name).unwrap(),
+        size,
+        size
+    )?;
+    if stat.mtime >= 0 {
+        write!(out, ",\"mtime\":{}", stat.mtime)?;
+    }
+    write!(out, "}}")?;
+
+    for elem in &stat.sub_dirs {
+        let child = path.join(elem);
+        let Some(child_stat) = dirstat::get_dir_stat(db, &child)? else {
+            continue;
+        };
+        writeln!(out, ",")?;
+        write_ncdu_dir(db, &elem.to_string_lossy(), &child, &child_stat, out)?;
+    }
+    write!(out, "]")?;
+    Ok(())
+}
+
+pub fn export_csv(db: &impl KvStore, root: &Path, out: &mut impl Write) -> crate::Result<()> {
+    if dirstat::get_dir_stat(db, root)?.is_none() {
+        return Err(Error::NotFound(format!("{} has not been scanned", root.display())));
+    }
+    writeln!(out, "path,blocks,files,dirs,mtime")?;
+
+    let mut pendings = vec![PathBuf::from(root)];
+    while let Some(path) = pendings.pop() {
+        let Some(stat) = dirstat::get_dir_stat(db, &path)? else {
+            continue;
+        };
+        writeln!(
+            out,
+            "{},{},{},{},{}",
+            csv_field(&path.to_string_lossy()),
+            stat.blocks,
+            stat.file_num,
+            stat.subdir_num,
+            stat.mtime
+        )?;
+        // reversed so that the children come out in their stored order
+        for elem in stat.sub_dirs.iter().rev() {
+            pendings.push(path.join(elem));
+        }
+    }
+    Ok(())
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+pub fn export_json_tree(
+    db: &impl KvStore,
+    root: &Path,
+    max_depth: Option<u32>,
+    out: &mut impl Write,
+) -> crate::Result<()> {
+    let Some(tree) = build_tree(db, root, max_depth)? else {
+        return Err(Error::NotFound(format!("{} has not been scanned", root.display())));
+    };
+    serde_json::to_writer(&mut *out, &tree).map_err(|e| e.to_string())?;
+    writeln!(out)?;
+    Ok(())
+}
+
+// the totals are always computed over the whole subtree, `max_depth` only
+// limits how many levels of children are included, 0 means the root alone
+pub fn build_tree(db: &impl KvStore, root: &Path, max_depth: Option<u32>) -> crate::Result<Option<DirTreeNode>> {
+    let Some(stat) = dirstat::get_dir_stat(db, root)? else {
+        return Ok(None);
+    };
+    let name = root
+        .file_name()
+        .map_or(root.to_string_lossy(), |n| n.to_string_lossy())
+        .to_string();
+    build_node(db, name, root, stat, 0, max_depth).map(Some)
+}
+
+fn build_node(
+    db: &impl KvStore,
+    name: String,
+    path: &Path,
+    stat: DirStat,
+    depth: u32,
+    max_depth: Option<u32>,
+) -> crate::Result<DirTreeNode> {
+    let mut node = DirTreeNode {
+        name,
+        path: PathBuf::from(path),
+        mtime: stat.mtime,
+        blocks: stat.blocks,
+        files: stat.file_num,
+        dirs: stat.subdir_num,
+        own_blocks: stat.blocks,
+        own_files: stat.file_num,
+        expanded: max_depth.is_none_or(|max| depth < max),
+        children: vec![],
+    };
+
+    for elem in &stat.sub_dirs {
+        let child_path = path.join(elem);
+        let Some(child_stat) = dirstat::get_dir_stat(db, &child_path)? else {
+            continue;
+        };
+        let child = build_node(
+            db,
+            elem.to_string_lossy().to_string(),
+            &child_path,
+            child_stat,
+            depth + 1,
+            max_depth,
+        )?;
+        node.blocks += child.blocks;
+        node.files += child.files;
+        node.dirs += child.dirs;
+        if node.expanded {
+            node.children.push(child);
+        }
+    }
+    node.children.sort_by_key(|child| Reverse(child.blocks));
+    Ok(node)
+}
@@ -0,0 +1,394 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::fmt;
+use std::io::{BufRead, Read};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::thread;
+
+use serde::de::value::MapAccessDeserializer;
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize};
+
+use crate::conf;
+use crate::db::dirstat::{self, DirStat, DirStatBatch};
+use crate::db::scanresult;
+use crate::db::TABLE_CONF;
+use crate::kvstore::{Batch, KvStore};
+use crate::scan::{DirScanOverview, DirScanResult, BLOCK_SIZE};
+use crate::unix;
+
+static CONF_KEY_IMPORTS: &str = "imports";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ImportSource {
+    // the output of `ncdu -o`
+    Ncdu,
+    // the output of `du -ak`, sizes in KiB
+    Du,
+}
+
+impl FromStr for ImportSource {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> crate::Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "ncdu" => Ok(ImportSource::Ncdu),
+            "du" => Ok(ImportSource::Du),
+            _ => Err(format!("unknown import format {:?}, expect ncdu or du", s).into()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImportRecord {
+    pub host: String,
+    pub source: ImportSource,
+    pub imported_at: u64,
+    // where the tree is kept in the db, see `namespaced_path`
+    pub root: PathBuf,
+    pub original_root: PathBuf,
+    pub overview: DirScanOverview,
+}
+
+// imported trees live next to the local ones as `{host}:{path}`, the same
+// way scp names remote paths, so they never collide with an absolute path
+pub fn namespaced_path(host: &str, path: &Path) -> PathBuf {
+    let mut s = OsString::from(format!("{}:", host));
+    s.push(path.as_os_str());
+    PathBuf::from(s)
+}
+
+pub fn list_imports(db: &impl KvStore) -> Vec<ImportRecord> {
+    conf::list_or_report(db, CONF_KEY_IMPORTS)
+}
+
+pub fn import(
+    db: &impl KvStore,
+    host: &str,
+    source: ImportSource,
+    reader: impl BufRead + Send,
+) -> crate::Result<ImportRecord> {
+    match source {
+        ImportSource::Ncdu => import_ncdu(db, host, reader),
+        ImportSource::Du => import_du(db, host, reader),
+    }
+}
+
+// deeper trees are refused, far beyond what PATH_MAX allows
+const NCDU_MAX_DEPTH: usize = 4096;
+// the parse recurses once per level, on a stack of its own that holds
+// `NCDU_MAX_DEPTH` levels
+const NCDU_STACK_SIZE: usize = 64 << 20;
+
+// ncdu nests an array per directory, the recursion limit of serde_json is
+// off and `NcduDir` keeps its own
+pub fn import_ncdu(db: &impl KvStore, host: &str, reader: impl Read + Send) -> crate::Result<ImportRecord> {
+    check_host(host)?;
+    let imported_at = unix();
+    let (original_root, stats) = thread::scope(|scope| {
+        thread::Builder::new()
+            .stack_size(NCDU_STACK_SIZE)
+            .spawn_scoped(scope, || parse_ncdu(host, imported_at, reader))?
+            .join()
+            .map_err(|_| crate::Error::Other("parse ncdu export panicked".to_string()))?
+    })?;
+    save_import(db, host, ImportSource::Ncdu, original_root, imported_at, stats)
+}
+
+fn parse_ncdu(host: &str, ts: u64, reader: impl Read) -> crate::Result<(PathBuf, Vec<DirStat>)> {
+    let mut stats = Vec::new();
+    let mut de = serde_json::Deserializer::from_reader(reader);
+    de.disable_recursion_limit();
+    let root = NcduExport(NcduDir { parent: None, depth: 0, host, ts, stats: &mut stats })
+        .deserialize(&mut de)
+        .and_then(|root| de.end().map(|_| root))
+        .map_err(|e| e.to_string())?;
+    Ok((PathBuf::from(root), stats))
+}
+
+// a file, or the info of a directory as the first item of its array
+#[derive(Deserialize)]
+struct NcduEntry {
+    #[serde(default)]
+    name: String,
+    asize: Option<u64>,
+    dsize: Option<u64>,
+    mtime: Option<i64>,
+    excluded: Option<IgnoredAny>,
+    notreg: Option<IgnoredAny>,
+}
+
+impl NcduEntry {
+    fn blocks(&self) -> u64 {
+        self.dsize.or(self.asize).unwrap_or(0).div_ceil(BLOCK_SIZE)
+    }
+}
+
+const NOT_NCDU: &str = "not an ncdu export, expect [major, minor, metadata, tree]";
+
+// [major, minor, metadata, tree], the name of the root dir
+struct NcduExport<'a>(NcduDir<'a>);
+
+impl<'de> DeserializeSeed<'de> for NcduExport<'_> {
+    type Value = String;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<String, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for NcduExport<'_> {
+    type Value = String;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(NOT_NCDU)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<String, A::Error> {
+        for _ in 0..3 {
+            if seq.next_element::<IgnoredAny>()?.is_none() {
+                return Err(de::Error::custom(NOT_NCDU));
+            }
+        }
+        let Some(root) = seq.next_element_seed(self.0)? else {
+            return Err(de::Error::custom(NOT_NCDU));
+        };
+        while seq.next_element::<IgnoredAny>()?.is_some() {}
+        Ok(root)
+    }
+}
+
+// [info, entries...], where an entry is a file or a dir of its own. pushes
+// the stats of the dir and everything below it, the dir first, and gives
+// back its name
+struct NcduDir<'a> {
+    // none for the root, whose name is a path
+    parent: Option<&'a Path>,
+    depth: usize,
+    host: &'a str,
+    ts: u64,
+    stats: &'a mut Vec<DirStat>,
+}
+
+impl<'de> DeserializeSeed<'de> for NcduDir<'_> {
+    type Value = String;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<String, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for NcduDir<'_> {
+    type Value = String;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an ncdu directory, [info, entries...]")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<String, A::Error> {
+        if self.depth > NCDU_MAX_DEPTH {
+            return Err(de::Error::custom(format!("directories nested deeper than {}", NCDU_MAX_DEPTH)));
+        }
+        let Some(info) = seq.next_element::<NcduEntry>()? else {
+            return Err(de::Error::custom("ncdu directory without info"));
+        };
+        let path = match self.parent {
+            Some(parent) => parent.join(&info.name),
+            None if info.name.is_empty() => return Err(de::Error::custom("ncdu export has no root directory")),
+            None => namespaced_path(self.host, Path::new(&info.name)),
+        };
+
+        let index = self.stats.len();
+        let mut stat = DirStat::new(&path);
+        stat.ts = self.ts;
+        stat.mtime = info.mtime.unwrap_or(-1);
+        stat.blocks = info.blocks();
+        self.stats.push(stat);
+
+        loop {
+            let item = NcduItem(NcduDir {
+                parent: Some(&path),
+                depth: self.depth + 1,
+                host: self.host,
+                ts: self.ts,
+                stats: &mut *self.stats,
+            });
+            match seq.next_element_seed(item)? {
+                None => break,
+                Some(NcduChild::Dir(name)) => {
+                    self.stats[index].subdir_num += 1;
+                    self.stats[index].sub_dirs.push(PathBuf::from(name));
+                }
+                Some(NcduChild::File(entry)) => {
+                    if entry.excluded.is_none() && entry.notreg.is_none() {
+                        self.stats[index].file_num += 1;
+                        self.stats[index].blocks += entry.blocks();
+                    }
+                }
+            }
+        }
+        Ok(info.name)
+    }
+}
+
+enum NcduChild {
+    // the name, its stats are pushed already
+    Dir(String),
+    File(NcduEntry),
+}
+
+struct NcduItem<'a>(NcduDir<'a>);
+
+impl<'de> DeserializeSeed<'de> for NcduItem<'_> {
+    type Value = NcduChild;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<NcduChild, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for NcduItem<'_> {
+    type Value = NcduChild;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an ncdu file or directory")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<NcduChild, A::Error> {
+        NcduEntry::deserialize(MapAccessDeserializer::new(map)).map(NcduChild::File)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<NcduChild, A::Error> {
+        self.0.visit_seq(seq).map(NcduChild::Dir)
+    }
+}
+
+// `du -ak` lists files and directories without telling them apart, every
+// entry with something listed below it is taken as a directory and the rest
+// as files, so empty directories are counted as empty files. The size of a
+// directory includes everything below it, its own files are what is left
+// after taking off its sub directories
+pub fn import_du(db: &impl KvStore, host: &str, reader: impl BufRead) -> crate::Result<ImportRecord> {
+    check_host(host)?;
+    let mut sizes: BTreeMap<PathBuf, u64> = BTreeMap::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let Some((size, path)) = line.split_once('\t') else {
+            return Err(format!("line {}: expect <size>\\t<path>", i + 1).into());
+        };
+        let Ok(size) = size.trim().parse::<u64>() else {
+            return Err(format!("line {}: invalid size {:?}", i + 1, size).into());
+        };
+        sizes.insert(PathBuf::from(path), size);
+    }
+
+    let mut children: HashMap<&Path, Vec<&Path>> = HashMap::new();
+    let mut roots: Vec<&Path> = Vec::new();
+    for path in sizes.keys() {
+        match path.parent().filter(|p| sizes.contains_key(*p)) {
+            Some(parent) => children.entry(parent).or_default().push(path),
+            None => roots.push(path),
+        }
+    }
+    let original_root = match roots.as_slice() {
+        [root] => PathBuf::from(root),
+        [] => return Err("empty du output".into()),
+        _ => return Err("du output covers more than one directory".into()),
+    };
+
+    let imported_at = unix();
+    let mut stats: Vec<DirStat> = Vec::new();
+    for (path, size) in &sizes {
+        let Some(entries) = children.get(path.as_path()) else {
+            // a leaf, it is a file unless it is the root itself
+            if path != &original_root {
+                continue;
+            }
+            let mut stat = DirStat::new(&namespaced_path(host, path));
+            stat.ts = imported_at;
+            stat.blocks = size * 2;
+            stats.push(stat);
+            continue;
+        };
+
+        let mut stat = DirStat::new(&namespaced_path(host, path));
+        stat.ts = imported_at;
+        let mut sub_dirs_size = 0;
+        for entry in entries {
+            if children.contains_key(entry) {
+                sub_dirs_size += sizes[*entry];
+                stat.subdir_num += 1;
+                stat.sub_dirs.push(PathBuf::from(entry.file_name().unwrap_or_default()));
+            } else {
+                stat.file_num += 1;
+            }
+        }
+        // du reports KiB, two 512-byte blocks each
+        stat.blocks = size.saturating_sub(sub_dirs_size) * 2;
+        stats.push(stat);
+    }
+    save_import(db, host, ImportSource::Du, original_root, imported_at, stats)
+}
+
+fn check_host(host: &str) -> crate::Result<()> {
+    if host.is_empty() || host.contains(['/', ':']) {
+        return Err(format!("invalid host name {:?}", host).into());
+    }
+    Ok(())
+}
+
+fn save_import(
+    db: &impl KvStore,
+    host: &str,
+    source: ImportSource,
+    original_root: PathBuf,
+    imported_at: u64,
+    stats: Vec<DirStat>,
+) -> crate::Result<ImportRecord> {
+    let root = namespaced_path(host, &original_root);
+
+    // a new import of the same tree replaces the previous one, all of it
+    // is written as one batch so a failed import leaves the old one intact
+    let mut batch = DirStatBatch::new(db);
+    let mut stale = Vec::new();
+    dirstat::foreach_subtree(db, &root, |path, _| stale.push(path.to_path_buf()));
+    for path in stale {
+        batch.delete(&path);
+    }
+
+    let mut overview = DirScanOverview::new();
+    for stat in &stats {
+        overview.blocks += stat.blocks;
+        overview.files += stat.file_num;
+        overview.dirs += stat.subdir_num;
+        batch.save(&stat.path, stat)?;
+    }
+    let mut extra = Batch::new();
+
+    let mut result = DirScanResult::new();
+    result.scanned = overview.clone();
+    result.ongoing = false;
+    result.root = root.clone();
+    result.started_at = imported_at;
+    result.finished_at = imported_at;
+    scanresult::add_dir_scan_result(&mut extra, &root, &result)?;
+
+    let record = ImportRecord {
+        host: host.to_string(),
+        source,
+        imported_at,
+        root: root.clone(),
+        original_root,
+        overview,
+    };
+    let mut imports: Vec<ImportRecord> = conf::load_list(db, CONF_KEY_IMPORTS)?;
+    imports.retain(|elem| elem.root != root);
+    imports.push(record.clone());
+    extra.set_json(TABLE_CONF, CONF_KEY_IMPORTS, &imports)?;
+    batch.write_with(extra)?;
+    Ok(record)
+}
@@ -0,0 +1,172 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Bound;
+use std::sync::RwLock;
+
+use super::{Batch, BatchOp, KvStore};
+
+type Table = BTreeMap<String, Vec<u8>>;
+
+// entries copied out at a time by `foreach_while`
+const WALK_CHUNK: usize = 256;
+
+// a `KvStore` kept in memory, for tests and for scans that are queried once
+// and thrown away. tables spring into existence on the first write, keys are
+// ordered bytewise like in rocksdb so prefix walks give the same order
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    tables: RwLock<HashMap<String, Table>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+
+    pub fn len(&self, table: &str) -> usize {
+        self.tables.read().unwrap().get(table).map_or(0, |t| t.len())
+    }
+
+    pub fn is_empty(&self, table: &str) -> bool {
+        self.len(table) == 0
+    }
+
+    fn collect_range(&self, table: &str, start: &str, end: &str, limit: u32) -> Vec<(String, Vec<u8>)> {
+        let tables = self.tables.read().unwrap();
+        let Some(table) = tables.get(table) else {
+            return Vec::new();
+        };
+        if start >= end {
+            return Vec::new();
+        }
+        let take = if limit == 0 { usize::MAX } else { limit as usize };
+        table
+            .range::<str, _>((Bound::Included(start), Bound::Excluded(end)))
+            .take(take)
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
+
+    // the next `count` entries with the prefix after the key `after`
+    fn collect_after(&self, table: &str, prefix: &str, after: Option<&str>, count: usize) -> Vec<(String, Vec<u8>)> {
+        let tables = self.tables.read().unwrap();
+        let Some(table) = tables.get(table) else {
+            return Vec::new();
+        };
+        let start = match after {
+            Some(after) => Bound::Excluded(after),
+            None => Bound::Included(prefix),
+        };
+        table
+            .range::<str, _>((start, Bound::Unbounded))
+            .take_while(|(k, _)| k.starts_with(prefix))
+            .take(count)
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
+
+    // the entries with the prefix, copied out so callbacks are free to write
+    fn collect(&self, table: &str, prefix: &str, limit: u32, reverse: bool) -> Vec<(String, Vec<u8>)> {
+        let tables = self.tables.read().unwrap();
+        let Some(table) = tables.get(table) else {
+            return Vec::new();
+        };
+        let range = table
+            .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+            .take_while(|(k, _)| k.starts_with(prefix));
+        let take = if limit == 0 { usize::MAX } else { limit as usize };
+        let copy = |(k, v): (&String, &Vec<u8>)| (k.clone(), v.clone());
+        if reverse {
+            let all: Vec<_> = range.collect();
+            all.into_iter().rev().take(take).map(copy).collect()
+        } else {
+            range.take(take).map(copy).collect()
+        }
+    }
+}
+
+impl KvStore for MemoryStore {
+    fn get_bytes(&self, table: &str, key: impl AsRef<str>) -> Option<impl AsRef<[u8]>> {
+        self.tables.read().unwrap().get(table)?.get(key.as_ref()).cloned()
+    }
+
+    fn set<V>(&self, table: &str, key: impl AsRef<str>, value: V) -> crate::Result<()>
+    where V: AsRef<[u8]> {
+        self.tables
+            .write()
+            .unwrap()
+            .entry(table.to_string())
+            .or_default()
+            .insert(key.as_ref().to_string(), value.as_ref().to_vec());
+        Ok(())
+    }
+
+    fn delete(&self, table: &str, key: impl AsRef<str>) -> crate::Result<()> {
+        if let Some(table) = self.tables.write().unwrap().get_mut(table) {
+            table.remove(key.as_ref());
+        }
+        Ok(())
+    }
+
+    fn write(&self, batch: Batch) -> crate::Result<()> {
+        let mut tables = self.tables.write().unwrap();
+        for op in batch.into_ops() {
+            match op {
+                BatchOp::Set { table, key, value } => {
+                    tables.entry(table).or_default().insert(key, value);
+                }
+                BatchOp::Delete { table, key } => {
+                    if let Some(table) = tables.get_mut(&table) {
+                        table.remove(&key);
+                    }
+                }
+                BatchOp::DeletePrefix { table, prefix } => {
+                    if let Some(table) = tables.get_mut(&table) {
+                        table.retain(|k, _| !k.starts_with(&prefix));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn foreach<F>(&self, table: &str, key_prefix: impl AsRef<str>, limit: u32, mut callback: F)
+    where F: FnMut(&str, &[u8]) {
+        for (k, v) in self.collect(table, key_prefix.as_ref(), limit, false) {
+            callback(&k, &v);
+        }
+    }
+
+    fn foreach_rev<F>(&self, table: &str, key_prefix: impl AsRef<str>, limit: u32, mut callback: F)
+    where F: FnMut(&str, &[u8]) {
+        for (k, v) in self.collect(table, key_prefix.as_ref(), limit, true) {
+            callback(&k, &v);
+        }
+    }
+
+    fn foreach_range<F>(&self, table: &str, start: impl AsRef<str>, end: impl AsRef<str>, limit: u32, mut callback: F)
+    where F: FnMut(&str, &[u8]) {
+        for (k, v) in self.collect_range(table, start.as_ref(), end.as_ref(), limit) {
+            callback(&k, &v);
+        }
+    }
+
+    // copies a chunk at a time, a walk that stops early copies little
+    fn foreach_while<F>(&self, table: &str, key_prefix: impl AsRef<str>, mut callback: F)
+    where F: FnMut(&str, &[u8]) -> bool {
+        let prefix = key_prefix.as_ref();
+        let mut after: Option<String> = None;
+        loop {
+            let chunk = self.collect_after(table, prefix, after.as_deref(), WALK_CHUNK);
+            let done = chunk.len() < WALK_CHUNK;
+            for (k, v) in &chunk {
+                if !callback(k, v) {
+                    return;
+                }
+            }
+            match chunk.into_iter().last() {
+                Some((k, _)) if !done => after = Some(k),
+                _ => return,
+            }
+        }
+    }
+}
@@ -1,12 +1,84 @@
+pub mod memory;
+pub mod store;
+
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum BatchOp {
+    Set { table: String, key: String, value: Vec<u8> },
+    Delete { table: String, key: String },
+    // every key starting with the prefix, all keys for an empty one
+    DeletePrefix { table: String, prefix: String },
+}
+
+// writes that `KvStore::write` applies all together or not at all, in order
+#[derive(Debug, Clone, Default)]
+pub struct Batch {
+    ops: Vec<BatchOp>,
+}
+
+impl Batch {
+    pub fn new() -> Batch {
+        Batch::default()
+    }
+
+    pub fn set(&mut self, table: &str, key: impl AsRef<str>, value: impl AsRef<[u8]>) {
+        self.ops.push(BatchOp::Set {
+            table: table.to_string(),
+            key: key.as_ref().to_string(),
+            value: value.as_ref().to_vec(),
+        });
+    }
+
+    pub fn set_json<T: Serialize>(&mut self, table: &str, key: impl AsRef<str>, value: &T) -> crate::Result<()> {
+        let data = serde_json::to_vec(value).map_err(|e| e.to_string())?;
+        self.set(table, key, data);
+        Ok(())
+    }
+
+    pub fn delete(&mut self, table: &str, key: impl AsRef<str>) {
+        self.ops.push(BatchOp::Delete { table: table.to_string(), key: key.as_ref().to_string() });
+    }
+
+    pub fn delete_prefix(&mut self, table: &str, prefix: impl AsRef<str>) {
+        self.ops.push(BatchOp::DeletePrefix { table: table.to_string(), prefix: prefix.as_ref().to_string() });
+    }
+
+    pub fn append(&mut self, other: Batch) {
+        self.ops.extend(other.ops);
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    pub fn into_ops(self) -> Vec<BatchOp> {
+        self.ops
+    }
+}
+
 pub trait KvStore: Sized {
     fn get_bytes(&self, table: &str, key: impl AsRef<str>) -> Option<impl AsRef<[u8]>>;
 
     fn set<V>(&self, table: &str, key: impl AsRef<str>, value: V) -> crate::Result<()>
     where V: AsRef<[u8]>;
 
+    fn delete(&self, table: &str, key: impl AsRef<str>) -> crate::Result<()>;
+
+    fn write(&self, batch: Batch) -> crate::Result<()>;
+
+    // drops every key starting with the prefix in one go
+    fn delete_prefix(&self, table: &str, prefix: impl AsRef<str>) -> crate::Result<()> {
+        let mut batch = Batch::new();
+        batch.delete_prefix(table, prefix);
+        self.write(batch)
+    }
+
     fn get_string (self: &Self, table: &str, key: impl AsRef<str>) -> String {
         self.get_bytes(table, key).map_or(String::new(), |v| String::from_utf8_lossy(v.as_ref()).to_string())
     }
@@ -20,19 +92,50 @@ pub trait KvStore: Sized {
         self.set(table, key, data.unwrap())
     }
 
-    fn get_as<R>(self: &Self, table: &str, key: impl AsRef<str>) -> Option<R>
+    // none when the key is missing, an error when its value is no `R`, e.g.
+    // written by a version with a different layout
+    fn get_as<R>(self: &Self, table: &str, key: impl AsRef<str>) -> crate::Result<Option<R>>
     where R: DeserializeOwned {
-        if let Some(bytes) = self.get_bytes(table, key) {
-            serde_json::from_slice(bytes.as_ref()).ok()
-        } else {
-            None
-        }
+        let key = key.as_ref();
+        let Some(bytes) = self.get_bytes(table, key) else {
+            return Ok(None);
+        };
+        serde_json::from_slice(bytes.as_ref())
+            .map(Some)
+            .map_err(|e| crate::Error::Decode(format!("{} {:?}: {}", table, key, e)))
     }
 
+    // the callbacks get the keys as text and the values as they were set
     fn foreach<F>(
         self: &Self,
         table: &str,
         key_prefix: impl AsRef<str>,
         limit: u32, callback: F)
-    where F: FnMut(&str, &str);
+    where F: FnMut(&str, &[u8]);
+
+    // same as `foreach` but from the last key with the prefix down to the first
+    fn foreach_rev<F>(
+        self: &Self,
+        table: &str,
+        key_prefix: impl AsRef<str>,
+        limit: u32, callback: F)
+    where F: FnMut(&str, &[u8]);
+
+    // the keys from `start` up to but not including `end`, in order
+    fn foreach_range<F>(
+        self: &Self,
+        table: &str,
+        start: impl AsRef<str>,
+        end: impl AsRef<str>,
+        limit: u32, callback: F)
+    where F: FnMut(&str, &[u8]);
+
+    // same as `foreach` without a limit, the walk stops at the first key the
+    // callback returns false for
+    fn foreach_while<F>(
+        self: &Self,
+        table: &str,
+        key_prefix: impl AsRef<str>,
+        callback: F)
+    where F: FnMut(&str, &[u8]) -> bool;
 }
\ No newline at end of file
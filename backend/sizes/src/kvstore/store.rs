@@ -0,0 +1,66 @@
+use super::memory::MemoryStore;
+use super::{Batch, KvStore};
+use crate::rocksdb::RocksDB;
+#[cfg(feature = "sqlite")]
+use crate::sqlite::SqliteStore;
+
+// one of the store implementations, picked when the database is opened.
+// `KvStore` has generic methods so it can not be used as a trait object,
+// this dispatches to the concrete store instead
+#[derive(Debug)]
+pub enum Store {
+    RocksDB(RocksDB),
+    #[cfg(feature = "sqlite")]
+    Sqlite(SqliteStore),
+    Memory(MemoryStore),
+}
+
+macro_rules! dispatch {
+    ($store:expr, $s:ident => $body:expr) => {
+        match $store {
+            Store::RocksDB($s) => $body,
+            #[cfg(feature = "sqlite")]
+            Store::Sqlite($s) => $body,
+            Store::Memory($s) => $body,
+        }
+    };
+}
+
+impl KvStore for Store {
+    fn get_bytes(&self, table: &str, key: impl AsRef<str>) -> Option<impl AsRef<[u8]>> {
+        dispatch!(self, s => s.get_bytes(table, key).map(|v| v.as_ref().to_vec()))
+    }
+
+    fn set<V>(&self, table: &str, key: impl AsRef<str>, value: V) -> crate::Result<()>
+    where V: AsRef<[u8]> {
+        dispatch!(self, s => s.set(table, key, value))
+    }
+
+    fn delete(&self, table: &str, key: impl AsRef<str>) -> crate::Result<()> {
+        dispatch!(self, s => s.delete(table, key))
+    }
+
+    fn write(&self, batch: Batch) -> crate::Result<()> {
+        dispatch!(self, s => s.write(batch))
+    }
+
+    fn foreach<F>(&self, table: &str, key_prefix: impl AsRef<str>, limit: u32, callback: F)
+    where F: FnMut(&str, &[u8]) {
+        dispatch!(self, s => s.foreach(table, key_prefix, limit, callback))
+    }
+
+    fn foreach_rev<F>(&self, table: &str, key_prefix: impl AsRef<str>, limit: u32, callback: F)
+    where F: FnMut(&str, &[u8]) {
+        dispatch!(self, s => s.foreach_rev(table, key_prefix, limit, callback))
+    }
+
+    fn foreach_range<F>(&self, table: &str, start: impl AsRef<str>, end: impl AsRef<str>, limit: u32, callback: F)
+    where F: FnMut(&str, &[u8]) {
+        dispatch!(self, s => s.foreach_range(table, start, end, limit, callback))
+    }
+
+    fn foreach_while<F>(&self, table: &str, key_prefix: impl AsRef<str>, callback: F)
+    where F: FnMut(&str, &[u8]) -> bool {
+        dispatch!(self, s => s.foreach_while(table, key_prefix, callback))
+    }
+}
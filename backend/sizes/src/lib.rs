@@ -1,29 +1,44 @@
 use crate::cmd::TaskManager;
-use crate::rocksdb::RocksDB;
+use crate::db::DbHandle;
 use std::cell::UnsafeCell;
 use std::env::{self};
-use std::path::PathBuf;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use rocket::form::validate::msg;
-use crate::conf::app_db_path;
 
+pub mod cleanup;
 pub mod cmd;
 pub mod conf;
+pub mod config;
 pub mod db;
+pub mod export;
 pub mod scandir;
 pub mod rocksdb;
+pub mod import;
 pub mod kvstore;
+pub mod reclaim;
 pub mod scan;
+pub mod server;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
 pub mod task;
 
 pub struct Client {
     pub task_manager: TaskManager,
-    pub db: &'static RocksDB
+    pub db: DbHandle
 }
 
 pub async fn init() -> Client {
-    let task_manager = TaskManager::new();
-    let db = db::get_db(app_db_path(), false);
+    init_with(&config::get().db.path).await
+}
+
+pub async fn init_with(db_path: &Path) -> Client {
+    let db = db::get_db(db_path, false);
+    let task_manager = TaskManager::new(db.clone());
+    let retention = &config::get().retention;
+    if db::retention::is_enabled(retention) && retention.interval_hours > 0 {
+        task_manager.schedule_retention(Duration::from_secs(retention.interval_hours * 3600));
+    }
     Client { task_manager, db }
 }
 
@@ -35,6 +50,13 @@ pub fn unix() -> u64 {
         .as_secs()
 }
 
+// 32 bytes of the os random source in hex, for secrets handed out to clients
+pub fn random_token() -> Result<String> {
+    let mut bytes = [0u8; 32];
+    getrandom::fill(&mut bytes).map_err(|e| Error::Other(format!("read the os random source failed, {}", e)))?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
 pub fn home_dir() -> PathBuf {
     let is_windows = env::var("OS")
         .map(|os| os.contains("windows"))
@@ -89,32 +111,92 @@ impl<T> StaticBox<T> {
     }
 }
 
+// the kinds of failure callers may want to tell apart, e.g. to pick an HTTP
+// status, everything else is `Other`
 #[derive(Debug)]
-pub struct Error(String);
+pub enum Error {
+    NotFound(String),
+    Unauthorized(String),
+    InvalidPath(String),
+    Storage(String),
+    // a stored value that does not read back as what it should be
+    Decode(String),
+    Io(String),
+    Busy(String),
+    Cancelled(String),
+    Other(String),
+}
 
 impl Error {
     pub fn new(msg: impl AsRef<str>) -> Error {
-        Error(msg.as_ref().to_string())
+        Error::Other(msg.as_ref().to_string())
+    }
+
+    // a stable name of the kind, for machine readable error responses
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::NotFound(_) => "not_found",
+            Error::Unauthorized(_) => "unauthorized",
+            Error::InvalidPath(_) => "invalid_path",
+            Error::Storage(_) => "storage",
+            Error::Decode(_) => "decode",
+            Error::Io(_) => "io",
+            Error::Busy(_) => "busy",
+            Error::Cancelled(_) => "cancelled",
+            Error::Other(_) => "other",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            Error::NotFound(msg)
+            | Error::Unauthorized(msg)
+            | Error::InvalidPath(msg)
+            | Error::Storage(msg)
+            | Error::Decode(msg)
+            | Error::Io(msg)
+            | Error::Busy(msg)
+            | Error::Cancelled(msg)
+            | Error::Other(msg) => msg,
+        }
     }
 }
 
 impl AsRef<str> for Error {
     fn as_ref(&self) -> &str {
-        &self.0
+        self.message()
     }
 }
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "message {:?}", self.0.as_str())
+        write!(f, "{} {:?}", self.code(), self.message())
     }
 }
 
-type Result<T> = std::result::Result<T, Error>;
+impl std::error::Error for Error {}
+
+pub type Result<T> = std::result::Result<T, Error>;
 
 impl From<::rocksdb::Error> for Error {
     fn from(value: ::rocksdb::Error) -> Self {
-        Self(value.into_string())
+        Error::Storage(value.into_string())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<::rusqlite::Error> for Error {
+    fn from(value: ::rusqlite::Error) -> Self {
+        Error::Storage(value.to_string())
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        match value.kind() {
+            std::io::ErrorKind::NotFound => Error::NotFound(value.to_string()),
+            _ => Error::Io(value.to_string()),
+        }
     }
 }
 
@@ -126,6 +208,6 @@ impl From<&str> for Error {
 
 impl From<String> for Error {
     fn from(value: String) -> Self {
-        Self(value)
+        Error::Other(value)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,478 @@
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::sync::Arc;
+
+use getopts::{Matches, Options};
+use serde::Serialize;
+
+use sizes::cmd;
+use sizes::conf::{self, WatchDirectoryConfiguration};
+use sizes::config::{self, RetentionConfig};
+use sizes::db::dirstat::{get_subtree_overview, DirStat};
+use sizes::db::dirorder::{self, Direction, Orderby, Page};
+use sizes::db::search::{self, MatchMode, MatchTarget, SearchQuery};
+use sizes::db::{self, maintenance, migrate, purge, retention, scanresult, stats, Backend, DbHandle};
+use sizes::export::{self, ExportFormat};
+use sizes::kvstore::memory::MemoryStore;
+use sizes::scan::{DirScanResult, BLOCK_SIZE};
+
+const USAGE: &str = "Usage: sizes [options] <command> [args]
+
+Commands:
+    scan <path>                  scan a directory and save the result
+    stat <path>                  totals of a scanned directory and everything below it
+    top                          directories sorted by the size, number of files or sub dirs they hold
+    find <pattern>               scanned directories whose name matches, a substring by default
+    history <path>               results of the previous scans of a directory
+    watch add <path>             add or update a watched directory
+    watch rm <path>              stop watching a directory
+    watch ls                     list the watched directories
+    purge <path>                 drop the stats and scan history of a directory and everything below it
+    export <path>                write a scanned tree as ncdu, csv or json
+    db stats                     keys, sizes and compression of the tables of the database itself
+    db compact [table]           reclaim the space of deleted data, in one table or all of them
+    db backup <path>             copy the database into a new path, e.g. before an upgrade
+    db backups                   list the backups made
+    db restore <path>            replace all data with a backup
+    db prune                     drop the scan results and stale dirs beyond the [retention] limits
+    migrate <src> <dst>          copy a database into a new one of the other backend";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let mut opts = Options::new();
+    opts.optopt("", "config", "config file, defaults to ~/.config/sizes/config.toml", "FILE");
+    opts.optopt("", "db", "database path, defaults to ~/.config/sizes/app.db", "PATH");
+    opts.optflag("j", "json", "print json instead of tables");
+    opts.optopt("n", "limit", "number of entries to show", "N");
+    opts.optopt("", "offset", "number of entries to skip (top, find)", "N");
+    opts.optopt("", "min", "minimum of the sorted value, blocks by default (top), minimum blocks of a dir and everything below it (find)", "N");
+    opts.optopt("", "orderby", "blocks, files or dirs (top, find)", "FIELD");
+    opts.optflag("", "asc", "smallest first (top, find)");
+    opts.optflag("", "ephemeral", "keep the scan in memory only and print its largest dirs (scan)");
+    opts.optflag("", "glob", "match the pattern as a glob (find)");
+    opts.optflag("", "regex", "match the pattern as a regex (find)");
+    opts.optflag("", "path", "match the full path instead of the name (find)");
+    opts.optflag("", "case", "match case sensitively (find)");
+    opts.optopt("", "since", "finished at or after this unix time (history)", "TS");
+    opts.optopt("", "until", "finished at or before this unix time (history)", "TS");
+    opts.optopt("", "label", "label of the watched directory (watch add)", "LABEL");
+    opts.optopt("", "interval", "refresh interval (watch add)", "INTERVAL");
+    opts.optflag("", "purge", "drop the data of the directory too, unless another watch covers it (watch rm)");
+    opts.optopt("f", "format", "ncdu, csv or json (export)", "FORMAT");
+    opts.optopt("", "depth", "depth limit of the json tree (export)", "N");
+    opts.optopt("o", "output", "write to this file instead of stdout (export)", "FILE");
+    opts.optopt("", "to", "rocksdb or sqlite, the other one of the source by default (migrate)", "BACKEND");
+    opts.optflag("h", "help", "print this help");
+
+    let matches = match opts.parse(&args) {
+        Ok(m) => m,
+        Err(e) => fail(e.to_string()),
+    };
+    if matches.opt_present("h") || matches.free.is_empty() {
+        println!("{}", opts.usage(USAGE));
+        return;
+    }
+
+    let config = match config::load(matches.opt_str("config").as_deref().map(Path::new)) {
+        Ok(config) => config,
+        Err(e) => fail(e.to_string()),
+    };
+    let free: Vec<&str> = matches.free.iter().map(|s| s.as_str()).collect();
+    let db_path = matches.opt_str("db").map(PathBuf::from).unwrap_or(config.db.path.clone());
+    // opened by the commands that need it, an ephemeral scan or a migration
+    // runs while the daemon holds the database
+    let open = || db::registry().open(&db_path, false);
+
+    let res = (|| match free.as_slice() {
+        ["scan", path] if matches.opt_present("ephemeral") => run_ephemeral_scan(path, &matches),
+        ["scan", path] => run_scan(&open()?, path, &matches),
+        ["stat", path] => run_stat(&open()?, path, &matches),
+        ["top"] => run_top(&open()?, &matches),
+        ["find", pattern] => run_find(&open()?, pattern, &matches),
+        ["history", path] => run_history(&open()?, path, &matches),
+        ["watch", "add", path] => run_watch_add(&open()?, path, &matches),
+        ["watch", "rm", path] => run_watch_rm(&open()?, path, &matches),
+        ["watch", "ls"] => run_watch_ls(&open()?, &matches),
+        ["purge", path] => run_purge(&open()?, path, &matches),
+        ["export", path] => run_export(&open()?, path, &matches),
+        ["db", "stats"] => run_db_stats(&open()?, &matches),
+        ["db", "compact"] => maintenance::compact(open()?.as_ref(), None),
+        ["db", "compact", table] => maintenance::compact(open()?.as_ref(), Some(table)),
+        ["db", "backup", path] => run_db_backup(&open()?, path, &matches),
+        ["db", "backups"] => run_db_backups(&open()?, &matches),
+        ["db", "restore", path] => run_db_restore(&open()?, path, &matches),
+        ["db", "prune"] => run_db_prune(&open()?, &config.retention, &matches),
+        // works on two databases of its own instead of the configured one
+        ["migrate", src, dst] => run_migrate(src, dst, &matches),
+        _ => fail(format!("unknown command {:?}\n\n{}", free.join(" "), opts.usage(USAGE))),
+    })();
+    if let Err(e) = res {
+        fail(e.to_string());
+    }
+}
+
+fn fail(msg: String) -> ! {
+    eprintln!("{}", msg);
+    exit(1)
+}
+
+fn opt_num<T: std::str::FromStr>(matches: &Matches, name: &str, default: T) -> sizes::Result<T> {
+    match matches.opt_str(name) {
+        Some(v) => v.parse().map_err(|_| format!("invalid value {:?} for --{}", v, name).into()),
+        None => Ok(default),
+    }
+}
+
+fn print_json<T: Serialize>(value: &T) -> sizes::Result<()> {
+    let s = serde_json::to_string_pretty(value).map_err(|e| e.to_string())?;
+    println!("{}", s);
+    Ok(())
+}
+
+fn human_size(blocks: u64) -> String {
+    human_bytes(blocks * BLOCK_SIZE)
+}
+
+fn human_bytes(bytes: u64) -> String {
+    let mut size = bytes as f64;
+    for unit in ["B", "KiB", "MiB", "GiB", "TiB"] {
+        if size < 1024.0 || unit == "TiB" {
+            return if unit == "B" { format!("{} {}", size, unit) } else { format!("{:.1} {}", size, unit) };
+        }
+        size /= 1024.0;
+    }
+    unreachable!()
+}
+
+// keeps the scan in memory, no database is opened
+fn run_ephemeral_scan(path: &str, matches: &Matches) -> sizes::Result<()> {
+    let path = Path::new(path).canonicalize()?;
+    let runtime = tokio::runtime::Runtime::new()?;
+    let mut progress = DirScanResult::new();
+    let store = Arc::new(MemoryStore::new());
+    runtime.block_on(cmd::scan_dir(store.clone(), &path, &mut progress))?;
+    print_largest(store.as_ref(), &progress, matches)
+}
+
+fn run_scan(db: &DbHandle, path: &str, matches: &Matches) -> sizes::Result<()> {
+    let path = Path::new(path).canonicalize()?;
+    let runtime = tokio::runtime::Runtime::new()?;
+    let mut progress = DirScanResult::new();
+    runtime.block_on(cmd::scan_dir(db.clone(), &path, &mut progress))?;
+
+    if matches.opt_present("json") {
+        return print_json(&progress);
+    }
+    println!(
+        "{}: {} in {} files, {} dirs, {} dirs unchanged, {}s",
+        path.display(),
+        human_size(progress.scanned.blocks),
+        progress.scanned.files,
+        progress.scanned.dirs,
+        progress.cached.dirs,
+        progress.spent
+    );
+    Ok(())
+}
+
+fn print_largest(store: &MemoryStore, progress: &DirScanResult, matches: &Matches) -> sizes::Result<()> {
+    let page = dirorder::list_dirs(store, Orderby::Block, Direction::Desc, 0, 0, opt_num(matches, "limit", 20)?)?;
+    if matches.opt_present("json") {
+        return print_json(&page);
+    }
+    print_dirs(page.items);
+    println!(
+        "{} in {} files, {} dirs, {}s, not saved",
+        human_size(progress.scanned.blocks),
+        progress.scanned.files,
+        progress.scanned.dirs,
+        progress.spent
+    );
+    Ok(())
+}
+
+fn print_dirs(dirs: Vec<DirStat>) {
+    println!("{:>12} {:>10} {:>10}  PATH", "SIZE", "FILES", "DIRS");
+    for dir in dirs {
+        println!(
+            "{:>12} {:>10} {:>10}  {}",
+            human_size(dir.blocks),
+            dir.file_num,
+            dir.subdir_num,
+            dir.path.display()
+        );
+    }
+}
+
+// the path as `scan` saves it, one that is gone from the disk, e.g. to purge
+// what is left of it, is taken relative to the working dir
+fn canonical_path(path: &str) -> sizes::Result<PathBuf> {
+    match Path::new(path).canonicalize() {
+        Ok(path) => Ok(path),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(env::current_dir()?.join(path).components().collect()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn run_stat(db: &DbHandle, path: &str, matches: &Matches) -> sizes::Result<()> {
+    let path = canonical_path(path)?;
+    let overview = get_subtree_overview(db.as_ref(), &path);
+    if matches.opt_present("json") {
+        return print_json(&overview);
+    }
+    println!("{:>12} {:>10} {:>10}  PATH", "SIZE", "FILES", "DIRS");
+    println!("{:>12} {:>10} {:>10}  {}", human_size(overview.blocks), overview.files, overview.dirs, path.display());
+    Ok(())
+}
+
+fn opt_order(matches: &Matches) -> sizes::Result<(Orderby, Direction)> {
+    let orderby: Orderby = match matches.opt_str("orderby") {
+        Some(orderby) => orderby.parse()?,
+        None => Orderby::Block,
+    };
+    let direction = if matches.opt_present("asc") { Direction::Asc } else { Direction::Desc };
+    Ok((orderby, direction))
+}
+
+fn print_page(page: Page<DirStat>, matches: &Matches) -> sizes::Result<()> {
+    if matches.opt_present("json") {
+        return print_json(&page);
+    }
+    let shown = page.items.len();
+    print_dirs(page.items);
+    let more = if page.has_more { ", more follow" } else { "" };
+    let total = page.total.map_or(String::new(), |total| format!(" of {}", total));
+    println!("{}{} dirs, from {}{}", shown, total, page.offset, more);
+    Ok(())
+}
+
+fn run_top(db: &DbHandle, matches: &Matches) -> sizes::Result<()> {
+    let (orderby, direction) = opt_order(matches)?;
+    let page = dirorder::list_dirs(
+        db.as_ref(),
+        orderby,
+        direction,
+        opt_num(matches, "min", 0)?,
+        opt_num(matches, "offset", 0)?,
+        opt_num(matches, "limit", 20)?,
+    )?;
+    print_page(page, matches)
+}
+
+fn run_find(db: &DbHandle, pattern: &str, matches: &Matches) -> sizes::Result<()> {
+    let mode = match (matches.opt_present("glob"), matches.opt_present("regex")) {
+        (true, true) => return Err("--glob and --regex can not be used together".into()),
+        (true, false) => MatchMode::Glob,
+        (false, true) => MatchMode::Regex,
+        (false, false) => MatchMode::Substring,
+    };
+    let (orderby, direction) = opt_order(matches)?;
+    let query = SearchQuery {
+        pattern: pattern.to_string(),
+        mode,
+        target: if matches.opt_present("path") { MatchTarget::Path } else { MatchTarget::Name },
+        case_sensitive: matches.opt_present("case"),
+        min_blocks: match matches.opt_str("min") {
+            Some(_) => Some(opt_num(matches, "min", 0)?),
+            None => None,
+        },
+        orderby,
+        direction,
+        offset: opt_num(matches, "offset", 0)?,
+        limit: opt_num(matches, "limit", 20)?,
+        ..SearchQuery::default()
+    };
+    print_page(search::search(db.as_ref(), &query)?, matches)
+}
+
+// how long ago a unix time was, e.g. `3h`
+fn ago(ts: u64) -> String {
+    let secs = sizes::unix().saturating_sub(ts);
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m", secs / 60),
+        3600..86400 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+fn run_history(db: &DbHandle, path: &str, matches: &Matches) -> sizes::Result<()> {
+    let path = canonical_path(path)?;
+    let limit = opt_num(matches, "limit", 10)?;
+    let results = if matches.opt_present("since") || matches.opt_present("until") {
+        let (from, to) = (opt_num(matches, "since", 0)?, opt_num(matches, "until", u64::MAX)?);
+        scanresult::get_dir_scan_results_between(db.as_ref(), &path, from, to, limit)?
+    } else {
+        scanresult::get_dir_scan_result(db.as_ref(), &path, limit)?
+    };
+    if matches.opt_present("json") {
+        return print_json(&results);
+    }
+    println!("{:>8} {:>12} {:>10} {:>10} {:>10} {:>8}", "AGO", "SIZE", "FILES", "DIRS", "UNCHANGED", "SPENT");
+    for result in results {
+        println!(
+            "{:>8} {:>12} {:>10} {:>10} {:>10} {:>7}s",
+            ago(result.finished_at),
+            human_size(result.scanned.blocks),
+            result.scanned.files,
+            result.scanned.dirs,
+            result.cached.dirs,
+            result.spent
+        );
+    }
+    Ok(())
+}
+
+fn run_watch_add(db: &DbHandle, path: &str, matches: &Matches) -> sizes::Result<()> {
+    let path = Path::new(path).canonicalize()?;
+    let watch = WatchDirectoryConfiguration {
+        refresh_interval: matches.opt_str("interval").unwrap_or("1 Day".to_string()),
+        label: matches.opt_str("label").unwrap_or_default(),
+        path: path.to_string_lossy().to_string(),
+    };
+    conf::add_watch(db.as_ref(), &watch)?;
+    run_watch_ls(db, matches)
+}
+
+fn run_watch_rm(db: &DbHandle, path: &str, matches: &Matches) -> sizes::Result<()> {
+    let watch = WatchDirectoryConfiguration {
+        refresh_interval: String::new(),
+        label: String::new(),
+        path: canonical_path(path)?.to_string_lossy().to_string(),
+    };
+    if matches.opt_present("purge") {
+        let stats = purge::remove_watch_and_data(db.as_ref(), &watch)?;
+        eprintln!("purged {} dirs and {} scan results", stats.dirs, stats.scan_results);
+    } else {
+        conf::remove_watch(db.as_ref(), &watch)?;
+    }
+    run_watch_ls(db, matches)
+}
+
+fn run_purge(db: &DbHandle, path: &str, matches: &Matches) -> sizes::Result<()> {
+    let path = canonical_path(path)?;
+    let stats = purge::purge_subtree(db.as_ref(), &path)?;
+    if matches.opt_present("json") {
+        return print_json(&stats);
+    }
+    println!("purged {} dirs and {} scan results of {}", stats.dirs, stats.scan_results, path.display());
+    Ok(())
+}
+
+fn run_watch_ls(db: &DbHandle, matches: &Matches) -> sizes::Result<()> {
+    let watches = conf::list_watch(db.as_ref());
+    if matches.opt_present("json") {
+        return print_json(&watches);
+    }
+    println!("{:<16} {:<12}  PATH", "LABEL", "INTERVAL");
+    for watch in watches {
+        println!("{:<16} {:<12}  {}", watch.label, watch.refresh_interval, watch.path);
+    }
+    Ok(())
+}
+
+fn run_db_stats(db: &DbHandle, matches: &Matches) -> sizes::Result<()> {
+    let stats = stats::db_stats(db.as_ref())?;
+    if matches.opt_present("json") {
+        return print_json(&stats);
+    }
+    println!("{} {}, schema version {}, {} on disk, {} background errors",
+        stats.backend, stats.path.display(), stats.schema_version, human_bytes(stats.disk_size), stats.background_errors);
+    println!("{:<12} {:>12} {:>12} {:>12}  LEVELS (FILES, COMPRESSION)", "TABLE", "KEYS", "LIVE", "SST");
+    for table in stats.tables {
+        let levels: Vec<String> = table.levels.iter()
+            .map(|l| format!("L{} {} x{:.1}", l.level, l.files, l.compression_ratio))
+            .collect();
+        println!("{:<12} {:>12} {:>12} {:>12}  {}", table.name, table.keys, human_bytes(table.live_data_size),
+            human_bytes(table.sst_files_size), levels.join(", "));
+    }
+    Ok(())
+}
+
+fn run_db_backup(db: &DbHandle, path: &str, matches: &Matches) -> sizes::Result<()> {
+    let record = maintenance::backup(db, &env::current_dir()?.join(path))?;
+    if matches.opt_present("json") {
+        return print_json(&record);
+    }
+    println!("backed up into {}, {}", record.path.display(), human_bytes(record.disk_size));
+    Ok(())
+}
+
+fn run_db_backups(db: &DbHandle, matches: &Matches) -> sizes::Result<()> {
+    let backups = maintenance::list_backups(db.as_ref());
+    if matches.opt_present("json") {
+        return print_json(&backups);
+    }
+    println!("{:<10} {:>12} {:>8} {:>7}  PATH", "AGO", "SIZE", "BACKEND", "SCHEMA");
+    for backup in backups {
+        let missing = if backup.exists { "" } else { " (missing)" };
+        println!("{:<10} {:>12} {:>8} {:>7}  {}{}", ago(backup.created_at), human_bytes(backup.disk_size),
+            format!("{:?}", backup.backend).to_lowercase(), backup.schema_version, backup.path.display(), missing);
+    }
+    Ok(())
+}
+
+fn run_db_restore(db: &DbHandle, path: &str, matches: &Matches) -> sizes::Result<()> {
+    let counts = maintenance::restore(db, &env::current_dir()?.join(path))?;
+    if matches.opt_present("json") {
+        return print_json(&counts);
+    }
+    println!("{:>10}  TABLE", "ENTRIES");
+    for (table, count) in counts {
+        println!("{:>10}  {}", count, table);
+    }
+    Ok(())
+}
+
+fn run_db_prune(db: &DbHandle, policy: &RetentionConfig, matches: &Matches) -> sizes::Result<()> {
+    if !retention::is_enabled(policy) {
+        eprintln!("no limit is set in the [retention] section of the config, nothing to drop");
+    }
+    let report = retention::apply_retention(db.as_ref(), policy, sizes::unix())?;
+    if matches.opt_present("json") {
+        return print_json(&report);
+    }
+    println!("dropped {} scan results and {} dirs", report.scan_results, report.dirs);
+    Ok(())
+}
+
+fn run_migrate(src: &str, dst: &str, matches: &Matches) -> sizes::Result<()> {
+    let (src, dst) = (Path::new(src), Path::new(dst));
+    let to = match matches.opt_str("to") {
+        Some(to) => to.parse()?,
+        None => match Backend::detect(src) {
+            Some(Backend::Sqlite) => Backend::RocksDB,
+            _ => Backend::Sqlite,
+        },
+    };
+    let counts = migrate::migrate(src, dst, to)?;
+    if matches.opt_present("json") {
+        return print_json(&counts);
+    }
+    println!("{:>10}  TABLE", "ENTRIES");
+    for (table, count) in counts {
+        println!("{:>10}  {}", count, table);
+    }
+    Ok(())
+}
+
+fn run_export(db: &DbHandle, path: &str, matches: &Matches) -> sizes::Result<()> {
+    let path = canonical_path(path)?;
+    let format: ExportFormat = matches.opt_str("format").unwrap_or("json".to_string()).parse()?;
+    let depth = match matches.opt_str("depth") {
+        Some(_) => Some(opt_num(matches, "depth", 0)?),
+        None => None,
+    };
+    let out: Box<dyn Write> = match matches.opt_str("output") {
+        Some(file) => Box::new(File::create(file)?),
+        None => Box::new(io::stdout().lock()),
+    };
+    let mut out = BufWriter::new(out);
+    export::export(db.as_ref(), &path, format, depth, &mut out)?;
+    out.flush()?;
+    Ok(())
+}
@@ -0,0 +1,236 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::conf;
+use crate::db::dirstat;
+use crate::home_dir;
+use crate::kvstore::KvStore;
+use crate::scan::{DirScanOverview, BLOCK_SIZE};
+
+// a directory whose content can be regenerated, it either has a well-known
+// name anywhere in the tree (optionally next to a marker file) or sits at a
+// fixed location, a leading `~` in `paths` is the home directory
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct ReclaimRule {
+    pub name: String,
+    #[serde(default)]
+    pub dir_name: Option<String>,
+    #[serde(default)]
+    pub paths: Vec<String>,
+    // at least one of these files has to exist in the parent directory,
+    // checked on the file system since the db only keeps directory names
+    #[serde(default)]
+    pub markers: Vec<String>,
+}
+
+impl ReclaimRule {
+    fn by_name(name: &str, dir_name: &str, markers: &[&str]) -> Self {
+        ReclaimRule {
+            name: name.to_string(),
+            dir_name: Some(dir_name.to_string()),
+            paths: vec![],
+            markers: markers.iter().map(|m| m.to_string()).collect(),
+        }
+    }
+
+    fn by_paths(name: &str, paths: &[&str]) -> Self {
+        ReclaimRule {
+            name: name.to_string(),
+            dir_name: None,
+            paths: paths.iter().map(|p| p.to_string()).collect(),
+            markers: vec![],
+        }
+    }
+
+    fn matches_name(&self, path: &Path) -> bool {
+        let Some(dir_name) = &self.dir_name else {
+            return false;
+        };
+        if path.file_name() != Some(dir_name.as_ref()) {
+            return false;
+        }
+        if self.markers.is_empty() {
+            return true;
+        }
+        let Some(parent) = path.parent() else {
+            return false;
+        };
+        self.markers.iter().any(|m| parent.join(m).exists())
+    }
+}
+
+pub fn builtin_rules() -> Vec<ReclaimRule> {
+    vec![
+        ReclaimRule::by_name("rust-target", "target", &["Cargo.toml"]),
+        ReclaimRule::by_name("node-modules", "node_modules", &[]),
+        ReclaimRule::by_name("python-pycache", "__pycache__", &[]),
+        ReclaimRule::by_paths("pip-cache", &["~/.cache/pip", "~/Library/Caches/pip"]),
+        ReclaimRule::by_paths("cargo-cache", &["~/.cargo/registry", "~/.cargo/git"]),
+        ReclaimRule::by_paths("gradle-cache", &["~/.gradle/caches"]),
+        ReclaimRule::by_paths("maven-repository", &["~/.m2/repository"]),
+        ReclaimRule::by_paths("docker-build-cache", &["~/.docker/buildx", "/var/lib/docker/buildkit"]),
+        ReclaimRule::by_paths("user-cache", &["~/.cache", "~/Library/Caches"]),
+    ]
+}
+
+// built-in rules followed by the ones configured by the user
+pub fn all_rules(db: &impl KvStore) -> Vec<ReclaimRule> {
+    let mut rules = builtin_rules();
+    rules.extend(conf::list_reclaim_rules(db));
+    rules
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReclaimableItem {
+    pub rule: String,
+    pub path: PathBuf,
+    // the directory the reclaimable one belongs to, e.g. the crate of a `target`
+    pub project: PathBuf,
+    pub overview: DirScanOverview,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RuleSummary {
+    pub rule: String,
+    pub count: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProjectSummary {
+    pub project: PathBuf,
+    pub rules: Vec<String>,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReclaimableReport {
+    pub root: PathBuf,
+    pub bytes: u64,
+    pub rules: Vec<RuleSummary>,
+    pub projects: Vec<ProjectSummary>,
+    pub items: Vec<ReclaimableItem>,
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix("~") {
+        Some(rest) => home_dir().join(rest.trim_start_matches('/')),
+        None => PathBuf::from(path),
+    }
+}
+
+struct Match {
+    path: PathBuf,
+    rule: usize,
+    // index of the closest matched ancestor
+    parent: Option<usize>,
+    overview: DirScanOverview,
+}
+
+pub fn analyze(db: &impl KvStore, root: &Path, rules: &[ReclaimRule]) -> ReclaimableReport {
+    let fixed_paths: HashMap<PathBuf, usize> = rules
+        .iter()
+        .enumerate()
+        .flat_map(|(i, rule)| rule.paths.iter().map(move |p| (expand_home(p), i)))
+        .collect();
+
+    let mut candidates: Vec<(PathBuf, usize)> = Vec::new();
+    dirstat::foreach_subtree(db, root, |path, _| {
+        let path = path.to_path_buf();
+        if let Some(i) = fixed_paths.get(&path) {
+            candidates.push((path, *i));
+        } else if let Some(i) = rules.iter().position(|r| r.matches_name(&path)) {
+            candidates.push((path, i));
+        }
+    });
+    // paths compare by component, so every directory is followed by its descendants
+    candidates.sort();
+
+    let mut matches: Vec<Match> = Vec::new();
+    let mut stack: Vec<usize> = Vec::new();
+    for (path, rule) in candidates {
+        while let Some(&top) = stack.last() {
+            if path.starts_with(&matches[top].path) {
+                break;
+            }
+            stack.pop();
+        }
+        let parent = stack.last().copied();
+        // anything under a reclaimable directory goes with it, except the
+        // fixed locations nested in another one, e.g. ~/.cache/pip in ~/.cache,
+        // those are reported on their own and taken out of the outer one
+        if parent.is_some() && rules[rule].dir_name.is_some() {
+            continue;
+        }
+        let overview = dirstat::get_subtree_overview(db, &path);
+        stack.push(matches.len());
+        matches.push(Match { path, rule, parent, overview });
+    }
+
+    for i in 0..matches.len() {
+        let Some(parent) = matches[i].parent else { continue };
+        let nested = matches[i].overview.clone();
+        let outer = &mut matches[parent].overview;
+        outer.blocks = outer.blocks.saturating_sub(nested.blocks);
+        outer.files = outer.files.saturating_sub(nested.files);
+        outer.dirs = outer.dirs.saturating_sub(nested.dirs);
+    }
+
+    let mut items: Vec<ReclaimableItem> = matches
+        .into_iter()
+        .map(|m| {
+            let rule = &rules[m.rule];
+            let project = if rule.dir_name.is_some() {
+                m.path.parent().map(PathBuf::from).unwrap_or(m.path.clone())
+            } else {
+                m.path.clone()
+            };
+            ReclaimableItem {
+                rule: rule.name.clone(),
+                bytes: m.overview.blocks * BLOCK_SIZE,
+                path: m.path,
+                project,
+                overview: m.overview,
+            }
+        })
+        .collect();
+    items.sort_by_key(|item| Reverse(item.bytes));
+
+    let mut by_rule: HashMap<&str, RuleSummary> = HashMap::new();
+    let mut by_project: HashMap<&Path, ProjectSummary> = HashMap::new();
+    for item in &items {
+        let summary = by_rule.entry(item.rule.as_str()).or_insert_with(|| RuleSummary {
+            rule: item.rule.clone(),
+            count: 0,
+            bytes: 0,
+        });
+        summary.count += 1;
+        summary.bytes += item.bytes;
+
+        let summary = by_project.entry(item.project.as_path()).or_insert_with(|| ProjectSummary {
+            project: item.project.clone(),
+            rules: vec![],
+            bytes: 0,
+        });
+        if !summary.rules.contains(&item.rule) {
+            summary.rules.push(item.rule.clone());
+        }
+        summary.bytes += item.bytes;
+    }
+    let mut rule_summaries: Vec<RuleSummary> = by_rule.into_values().collect();
+    rule_summaries.sort_by_key(|summary| Reverse(summary.bytes));
+    let mut project_summaries: Vec<ProjectSummary> = by_project.into_values().collect();
+    project_summaries.sort_by_key(|summary| Reverse(summary.bytes));
+
+    ReclaimableReport {
+        root: PathBuf::from(root),
+        bytes: items.iter().map(|item| item.bytes).sum(),
+        rules: rule_summaries,
+        projects: project_summaries,
+        items,
+    }
+}
@@ -1,9 +1,11 @@
 pub mod property;
 
 use self::property::Property;
-use crate::kvstore::KvStore;
+use crate::kvstore::{Batch, BatchOp, KvStore};
 use crate::rocksdb::property::PropertyPrefix;
-use rocksdb::{BlockBasedOptions, ColumnFamilyDescriptor, DBCompactionStyle, DBPinnableSlice, LogLevel, Options, DB};
+use rocksdb::checkpoint::Checkpoint;
+use rocksdb::{BlockBasedOptions, ColumnFamilyDescriptor, DBCompactionStyle, DBPinnableSlice, Direction, IteratorMode, LogLevel, Options, WriteBatch, DB};
+use std::fmt;
 use std::path::{Path, PathBuf};
 
 const DEFAULT_COLUMN_FAMILY_NAME: &str = "default";
@@ -19,6 +21,9 @@ const DEFAULT_BLOCK_CACHE_SIZE: usize = 64 << 20;
 const HUGE_WRITE_BUFFER_SIZE: usize = DEFAULT_WRITE_BUFFER_SIZE << 2;
 const HUGE_BLOCK_CACHE_SIZE: usize = DEFAULT_BLOCK_CACHE_SIZE << 2;
 
+// levels of the lsm tree, the options here keep the rocksdb default
+pub const NUM_LEVELS: i32 = 7;
+
 pub struct ColumnFamilyConfig {
     pub name: String,
     pub write_buffer_size: usize,
@@ -82,8 +87,23 @@ impl ColumnFamilyConfig {
     pub fn new_huge(name: &str) -> Self {
         Self::new(name, HUGE_WRITE_BUFFER_SIZE, HUGE_BLOCK_CACHE_SIZE, DEFAULT_BLOOM_FILTER_BIT)
     }
+
+    // the std configs scaled from the given default sizes instead of the built in ones
+    pub fn new_scaled(
+        name: &str,
+        config: StdColumnFamilyConfig,
+        write_buffer_size: usize,
+        block_cache_size: usize
+    ) -> Self {
+        match config {
+            StdColumnFamilyConfig::TINY => Self::new(name, write_buffer_size >> 2, block_cache_size >> 2, DEFAULT_BLOOM_FILTER_BIT),
+            StdColumnFamilyConfig::DEFAULT => Self::new(name, write_buffer_size, block_cache_size, DEFAULT_BLOOM_FILTER_BIT),
+            StdColumnFamilyConfig::HUGE => Self::new(name, write_buffer_size << 2, block_cache_size << 2, DEFAULT_BLOOM_FILTER_BIT)
+        }
+    }
 }
 
+#[derive(Debug, Clone, Copy)]
 pub enum StdColumnFamilyConfig {
     TINY,
     DEFAULT,
@@ -109,6 +129,19 @@ fn set_default_options(opt: &mut Options) {
     opt.set_ratelimiter(10<<20, 100000, 10);
 }
 
+// the smallest key greater than every key starting with `prefix`,
+// none when there is no such key, i.e. the prefix is empty or all 0xff
+pub(crate) fn prefix_upper_bound(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut upper = prefix.to_vec();
+    while let Some(last) = upper.pop() {
+        if last < u8::MAX {
+            upper.push(last + 1);
+            return Some(upper);
+        }
+    }
+    None
+}
+
 pub fn is_valid_db(path: &Path) -> bool {
     let res = DB::open_for_read_only(&Options::default(), path, true);
     if res.is_err() {
@@ -123,7 +156,9 @@ pub fn list_cfs(path: &Path) -> Vec<String> {
 
 pub struct RocksDBBuilder {
     path: PathBuf,
-    cfs: Vec<ColumnFamilyConfig>,
+    cfs: Vec<(String, StdColumnFamilyConfig)>,
+    write_buffer_size: usize,
+    block_cache_size: usize,
     truncate: bool
 }
 
@@ -131,6 +166,12 @@ pub struct RocksDB {
     pub(crate) db: DB
 }
 
+impl fmt::Debug for RocksDB {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RocksDB").field("path", &self.db.path()).finish()
+    }
+}
+
 impl KvStore for RocksDB {
     fn get_bytes(&self, table: &str, key: impl AsRef<str>) -> Option<impl AsRef<[u8]>> {
         self.get_cf(table, key)
@@ -141,11 +182,34 @@ impl KvStore for RocksDB {
         self.put_cf(table, key, value)
     }
 
+    fn delete(&self, table: &str, key: impl AsRef<str>) -> crate::Result<()> {
+        self.delete_cf(table, key)
+    }
+
+    fn write(&self, batch: Batch) -> crate::Result<()> {
+        self.write_batch(batch)
+    }
+
     fn foreach<F>(self: &Self, table: &str, key_prefix: impl AsRef<str>, limit: u32, callback: F)
-    where F: FnMut(&str, &str) {
+    where F: FnMut(&str, &[u8]) {
         self.prefix_foreach_cf(table, key_prefix, limit, callback)
     }
 
+    fn foreach_rev<F>(self: &Self, table: &str, key_prefix: impl AsRef<str>, limit: u32, callback: F)
+    where F: FnMut(&str, &[u8]) {
+        self.prefix_foreach_rev_cf(table, key_prefix, limit, callback)
+    }
+
+    fn foreach_range<F>(self: &Self, table: &str, start: impl AsRef<str>, end: impl AsRef<str>, limit: u32, callback: F)
+    where F: FnMut(&str, &[u8]) {
+        self.range_foreach_cf(table, start, end, limit, callback)
+    }
+
+    fn foreach_while<F>(self: &Self, table: &str, key_prefix: impl AsRef<str>, callback: F)
+    where F: FnMut(&str, &[u8]) -> bool {
+        self.prefix_foreach_while_cf(table, key_prefix, callback)
+    }
+
 }
 
 impl RocksDBBuilder {
@@ -153,6 +217,8 @@ impl RocksDBBuilder {
         let mut builder = RocksDBBuilder {
             path: PathBuf::from(path.as_ref()),
             cfs: Vec::new(),
+            write_buffer_size: DEFAULT_WRITE_BUFFER_SIZE,
+            block_cache_size: DEFAULT_BLOCK_CACHE_SIZE,
             truncate: false
         };
         builder.with_column_family(DEFAULT_COLUMN_FAMILY_NAME, StdColumnFamilyConfig::DEFAULT);
@@ -160,11 +226,14 @@ impl RocksDBBuilder {
     }
 
     pub fn with_column_family(&mut self, name: &str, config: StdColumnFamilyConfig) -> &mut RocksDBBuilder {
-        match config {
-            StdColumnFamilyConfig::TINY => self.cfs.push(ColumnFamilyConfig::new_tiny(name)),
-            StdColumnFamilyConfig::DEFAULT => self.cfs.push(ColumnFamilyConfig::new_default(name)),
-            StdColumnFamilyConfig::HUGE => self.cfs.push(ColumnFamilyConfig::new_huge(name))
-        };
+        self.cfs.push((name.to_string(), config));
+        self
+    }
+
+    // sizes of a DEFAULT column family, TINY and HUGE ones are scaled from them
+    pub fn with_cache_sizes(&mut self, write_buffer_size: usize, block_cache_size: usize) -> &mut RocksDBBuilder {
+        self.write_buffer_size = write_buffer_size;
+        self.block_cache_size = block_cache_size;
         self
     }
 
@@ -173,17 +242,22 @@ impl RocksDBBuilder {
         self
     }
 
-    pub fn build(self: &Self) -> RocksDB {
+    pub fn build(self: &Self) -> crate::Result<RocksDB> {
         let db_opts = new_db_option();
-        let cf_descriptors: Vec<ColumnFamilyDescriptor> = self.cfs.iter().map(|x| x.into()).collect();
+        let cf_descriptors: Vec<ColumnFamilyDescriptor> = self.cfs.iter()
+            .map(|(name, config)| ColumnFamilyConfig::new_scaled(name, *config, self.write_buffer_size, self.block_cache_size))
+            .map(|x| (&x).into())
+            .collect();
         if self.truncate && is_valid_db(self.path.as_path()) {
-            DB::destroy(&db_opts, self.path.as_path()).unwrap();
+            DB::destroy(&db_opts, self.path.as_path())?;
         }
 
-        let db = DB::open_cf_descriptors(&db_opts, self.path.as_path(), cf_descriptors).unwrap();
-        RocksDB {
+        // fails e.g. while another process holds the lock of the database
+        let db = DB::open_cf_descriptors(&db_opts, self.path.as_path(), cf_descriptors)
+            .map_err(|e| crate::Error::Storage(format!("open {} failed, {}", self.path.display(), e)))?;
+        Ok(RocksDB {
             db
-        }
+        })
     }
 }
 
@@ -198,13 +272,55 @@ impl RocksDB {
         self.get_property(p.as_str())
     }
 
+    // the same for one column family, empty when rocksdb does not know it
+    pub fn get_property_cf(self: &Self, cf: &str, prop: Property) -> crate::Result<String> {
+        let value = self.db.property_value_cf(self.cf(cf)?, format!("rocksdb.{}", prop).as_str())?;
+        Ok(value.unwrap_or_default())
+    }
+
+    pub fn get_property_cf_with_prefix(self: &Self, cf: &str, prop: PropertyPrefix, prefix: i32) -> crate::Result<String> {
+        self.get_property_cf(cf, format!("{}{}", prop, prefix).as_str())
+    }
+
+    pub fn get_int_property_cf(self: &Self, cf: &str, prop: Property) -> crate::Result<u64> {
+        let value = self.db.property_int_value_cf(self.cf(cf)?, format!("rocksdb.{}", prop).as_str())?;
+        Ok(value.unwrap_or(0))
+    }
+
+    pub fn path(self: &Self) -> &Path {
+        self.db.path()
+    }
+
+    // rewrites all files of the column family, dropping what deletes and
+    // overwrites left behind, e.g. after a purge
+    pub fn compact_cf(self: &Self, cf: &str) -> crate::Result<()> {
+        self.db.compact_range_cf(self.cf(cf)?, None::<&[u8]>, None::<&[u8]>);
+        Ok(())
+    }
+
+    // a consistent copy of the database as it is now at `path`, which must
+    // not exist. files that do not change are hard linked where possible
+    pub fn checkpoint(self: &Self, path: &Path) -> crate::Result<()> {
+        Checkpoint::new(&self.db)?.create_checkpoint(path)?;
+        Ok(())
+    }
+
     pub fn prefix_foreach_cf<F>(self: &Self, cf: &str, key_prefix: impl AsRef<str>, limit: u32, mut callback: F)
-    where F: FnMut(&str, &str)  {
+    where F: FnMut(&str, &[u8])  {
+        let mut count = 0;
+        self.prefix_foreach_while_cf(cf, key_prefix, |k, v| {
+            callback(k, v);
+            count += 1;
+            limit == 0 || count < limit
+        })
+    }
+
+    pub fn prefix_foreach_while_cf<F>(self: &Self, cf: &str, key_prefix: impl AsRef<str>, mut callback: F)
+    where F: FnMut(&str, &[u8]) -> bool  {
         let prefix = key_prefix.as_ref();
         let iter = self.db.prefix_iterator_cf(
             self.db.cf_handle(cf.as_ref()).unwrap(),
             prefix.as_bytes());
-        let mut count = 0;
         for item in iter {
             if item.is_err() {
                 eprintln!("prefix_foreach error: {}", item.unwrap_err());
@@ -214,10 +330,82 @@ impl RocksDB {
             if !prefix.is_empty() && !k.starts_with(prefix.as_bytes()) {
                 break;
             }
+            let go_on = unsafe {
+                callback(
+                    std::str::from_utf8_unchecked(&k),
+                    &v)
+            };
+            if !go_on {
+                break;
+            }
+        }
+    }
+
+    pub fn prefix_foreach_rev_cf<F>(self: &Self, cf: &str, key_prefix: impl AsRef<str>, limit: u32, mut callback: F)
+    where F: FnMut(&str, &[u8])  {
+        let prefix = key_prefix.as_ref();
+        let cf_handle = self.db.cf_handle(cf).unwrap();
+        // start right below the first key after all the keys with the prefix
+        let upper = prefix_upper_bound(prefix.as_bytes());
+        let iter = match &upper {
+            Some(upper) => self.db.iterator_cf(cf_handle, IteratorMode::From(upper, Direction::Reverse)),
+            None => self.db.iterator_cf(cf_handle, IteratorMode::End),
+        };
+        let mut count = 0;
+        for item in iter {
+            let (k, v) = match item {
+                Ok(kv) => kv,
+                Err(e) => {
+                    eprintln!("prefix_foreach_rev error: {}", e);
+                    continue;
+                }
+            };
+            if upper.as_ref().is_some_and(|upper| k.as_ref() >= upper.as_slice()) {
+                continue;
+            }
+            if !k.starts_with(prefix.as_bytes()) {
+                break;
+            }
+            unsafe {
+                callback(
+                    std::str::from_utf8_unchecked(&k),
+                    &v);
+            }
+            count += 1;
+            if limit > 0 && limit == count {
+                break;
+            }
+        }
+    }
+
+    pub fn range_foreach_cf<F>(
+        self: &Self,
+        cf: &str,
+        start: impl AsRef<str>,
+        end: impl AsRef<str>,
+        limit: u32,
+        mut callback: F,
+    ) where F: FnMut(&str, &[u8]) {
+        let end = end.as_ref().as_bytes();
+        let iter = self.db.iterator_cf(
+            self.db.cf_handle(cf).unwrap(),
+            IteratorMode::From(start.as_ref().as_bytes(), Direction::Forward));
+        let mut count = 0;
+        for item in iter {
+            let (k, v) = match item {
+                Ok(kv) => kv,
+                Err(e) => {
+                    eprintln!("range_foreach error: {}", e);
+                    continue;
+                }
+            };
+            if k.as_ref() >= end {
+                break;
+            }
             unsafe {
                 callback(
                     std::str::from_utf8_unchecked(&k),
-                    std::str::from_utf8_unchecked(&v));
+                    &v);
             }
             count += 1;
             if limit > 0 && limit == count {
@@ -239,4 +427,36 @@ impl RocksDB {
             Err(e) => Err(e.into())
         }
     }
+
+    fn write_batch(self: &Self, batch: Batch) -> crate::Result<()> {
+        let mut wb = WriteBatch::default();
+        for op in batch.into_ops() {
+            match op {
+                BatchOp::Set { table, key, value } => wb.put_cf(self.cf(&table)?, key, value),
+                BatchOp::Delete { table, key } => wb.delete_cf(self.cf(&table)?, key),
+                BatchOp::DeletePrefix { table, prefix } => {
+                    // keys are utf-8 and never hold 0xff, so it bounds the empty prefix
+                    let upper = prefix_upper_bound(prefix.as_bytes()).unwrap_or(vec![u8::MAX]);
+                    wb.delete_range_cf(self.cf(&table)?, prefix.as_bytes(), upper.as_slice())
+                }
+            }
+        }
+        match self.db.write(wb) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e.into())
+        }
+    }
+
+    fn cf(self: &Self, cf: &str) -> crate::Result<&rocksdb::ColumnFamily> {
+        self.db
+            .cf_handle(cf)
+            .ok_or_else(|| crate::Error::Storage(format!("no column family handle for {}", cf)))
+    }
+
+    fn delete_cf(self: &Self, cf: &str, key: impl AsRef<str>) -> crate::Result<()> {
+        match self.db.delete_cf(self.db.cf_handle(cf).unwrap(), key.as_ref()) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e.into())
+        }
+    }
 }
\ No newline at end of file
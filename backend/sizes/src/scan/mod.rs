@@ -9,6 +9,9 @@ use crate::db::dirstat::DirStat;
 use crate::kvstore::KvStore;
 use crate::unix;
 
+// st_blocks are counted in 512-byte units regardless of the file system block size
+pub const BLOCK_SIZE: u64 = 512;
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct DirScanOverview {
     pub dirs: u64,
@@ -45,7 +48,15 @@ pub struct DirScanResult {
     pub scanned: DirScanOverview,
     // in unit of second
     pub spent: u64,
-    pub ongoing: bool
+    pub ongoing: bool,
+    // the scanned dir and when the scan ran, in unix seconds. results saved
+    // before these existed get them from their keys
+    #[serde(default)]
+    pub root: PathBuf,
+    #[serde(default)]
+    pub started_at: u64,
+    #[serde(default)]
+    pub finished_at: u64,
 }
 
 impl Display for DirScanResult {
@@ -60,7 +71,10 @@ impl DirScanResult {
             cached: DirScanOverview::new(),
             scanned: DirScanOverview::new(),
             spent: 0,
-            ongoing: true
+            ongoing: true,
+            root: PathBuf::new(),
+            started_at: 0,
+            finished_at: 0,
         };
         obj.cached.is_cached = true;
         obj
@@ -71,16 +85,32 @@ pub fn scan_one_dir(
     db: &impl KvStore,
     path: &Path
 ) -> (DirStat, bool) {
-    let mut dir_stat = dirstat::get_dir_stat(db, path)
-        .unwrap_or(DirStat::new(path));
+    let (dir_stat, is_cached, outdated) = read_one_dir(db, path);
+    if !is_cached || outdated {
+        if let Err(err) = dirstat::save_dir_stat(db, path, &dir_stat) {
+            eprintln!("save dir stat for {:?} failed, {}", path, err);
+        }
+    }
+    (dir_stat, is_cached)
+}
+
+// like `scan_one_dir` but leaves saving a changed stat to the caller, the
+// last flag tells that an unchanged one is stored in an older encoding and
+// should be saved again all the same
+pub fn read_one_dir(
+    db: &impl KvStore,
+    path: &Path
+) -> (DirStat, bool, bool) {
+    let (mut dir_stat, outdated) = dirstat::load_dir_stat(db, path)
+        .unwrap_or((DirStat::new(path), false));
 
     let Ok(dir_meta) = path.metadata() else {
         eprintln!("read dir meta failed on path {:?}", path);
-        return (dir_stat, true);
+        return (dir_stat, true, outdated);
     };
 
     if dir_stat.mtime == dir_meta.st_mtime() {
-        return (dir_stat, true);
+        return (dir_stat, true, outdated);
     }
     let entries = match path.read_dir() {
         Ok(entries) => entries,
@@ -88,7 +118,7 @@ pub fn scan_one_dir(
             if e.kind() != ErrorKind::PermissionDenied {
                 eprintln!("read_dir failed on path {:?} {:?}", path, e);
             }
-            return (dir_stat, true);
+            return (dir_stat, true, outdated);
         }
     };
     dir_stat.sub_dirs.clear();
@@ -112,9 +142,5 @@ pub fn scan_one_dir(
     }
     dir_stat.mtime = dir_meta.st_mtime();
     dir_stat.ts = unix();
-
-    if let Err(err) = dirstat::save_dir_stat(db, path, &dir_stat) {
-        eprintln!("save dir stat for {:?} failed, {}", path, err);
-    }
-    (dir_stat, false)
+    (dir_stat, false, outdated)
 }
\ No newline at end of file
@@ -1,4 +1,3 @@
-use crate::rocksdb::RocksDB;
 use crate::scan;
 use futures::future::BoxFuture;
 use futures::FutureExt;
@@ -9,11 +8,15 @@ use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use tokio::task::JoinSet;
+use crate::db::dirstat::{DirStat, DirStatBatch};
 use crate::kvstore::KvStore;
 use crate::scan::{DirScanOverview, DirScanResult};
 
-pub fn compute_dir_stats_recursive<'a>(
-    db: &'a RocksDB,
+// changed dirs saved per write, one batch instead of a write per dir
+pub const SCAN_BATCH_SIZE: usize = 1000;
+
+pub fn compute_dir_stats_recursive<'a, S: KvStore + Sync>(
+    db: &'a S,
     path: &'a Path,
     scan_result: &'a mut DirScanResult
 ) -> BoxFuture<'a, io::Result<()>> { async move {
@@ -36,14 +39,16 @@ pub fn compute_dir_stats_recursive<'a>(
     Ok(())
 }.boxed()}
 
-async fn process_one_dir(
-    db: &impl KvStore,
+// the overview of the dir, and its stat when it changed, or is stored in an
+// older encoding, and has to be saved
+async fn process_one_dir<S: KvStore>(
+    db: Arc<S>,
     path: PathBuf,
     todos: Arc<RwLock<VecDeque<PathBuf>>>,
-) -> DirScanOverview {
-    let (dir_stat, is_cached) = scan::scan_one_dir(db, path.as_path());
+) -> (DirScanOverview, Option<DirStat>) {
+    let (dir_stat, is_cached, outdated) = scan::read_one_dir(db.as_ref(), path.as_path());
     let mut w1 = todos.write().await;
-    for elem in dir_stat.sub_dirs {
+    for elem in &dir_stat.sub_dirs {
         let mut buf = path.clone();
         buf.push(elem);
         w1.push_back(buf);
@@ -57,16 +62,25 @@ async fn process_one_dir(
     if is_cached {
         overview.is_cached = true;
     }
-    overview
+    (overview, (!is_cached || outdated).then_some(dir_stat))
+}
+
+fn flush_stats<S: KvStore>(batch: DirStatBatch<'_, S>) {
+    let dirs = batch.len();
+    if let Err(err) = batch.write() {
+        eprintln!("save stats of {} dirs failed, {}", dirs, err);
+    }
 }
 
-pub async fn compute_dir_stats_loop_parallel(
-    db: &'static RocksDB,
+pub async fn compute_dir_stats_loop_parallel<S: KvStore + Send + Sync + 'static>(
+    db: Arc<S>,
     root_path: &PathBuf,
+    concurrency: usize,
     progress: &mut DirScanResult,
 ) -> io::Result<()> {
     let todos = Arc::new(RwLock::new(VecDeque::from(vec![root_path.clone()])));
     let mut jobs = JoinSet::new();
+    let mut batch = DirStatBatch::new(db.as_ref());
 
     loop {
         let r = todos.read().await;
@@ -77,8 +91,23 @@ pub async fn compute_dir_stats_loop_parallel(
             break;
         }
 
-        if jobs.len() > 8 || left == 0 {
-            let dir_overview: DirScanOverview = jobs.join_next().await.unwrap().unwrap();
+        if jobs.len() >= concurrency.max(1) || left == 0 {
+            let (dir_overview, changed): (DirScanOverview, Option<DirStat>) = match jobs.join_next().await {
+                Some(Ok(done)) => done,
+                Some(Err(e)) => {
+                    flush_stats(batch);
+                    return Err(io::Error::other(e));
+                }
+                None => continue,
+            };
+            if let Some(stat) = changed {
+                if let Err(err) = batch.save(&stat.path, &stat) {
+                    eprintln!("save dir stat for {:?} failed, {}", stat.path, err);
+                }
+                if batch.len() >= SCAN_BATCH_SIZE {
+                    flush_stats(std::mem::replace(&mut batch, DirStatBatch::new(db.as_ref())));
+                }
+            }
             progress.scanned += &dir_overview;
             if dir_overview.is_cached {
                 progress.cached += &dir_overview;
@@ -93,7 +122,8 @@ pub async fn compute_dir_stats_loop_parallel(
         let path = w.pop_front().unwrap();
         drop(w);
 
-        jobs.spawn(process_one_dir(db, path, todos.clone()));
+        jobs.spawn(process_one_dir(db.clone(), path, todos.clone()));
     }
+    flush_stats(batch);
     Ok(())
 }
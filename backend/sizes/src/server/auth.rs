@@ -0,0 +1,163 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::uri::Origin;
+use rocket::http::{Header, Method};
+use rocket::{Build, Data, Orbit, Request, Response, Rocket};
+
+use super::ServerOptions;
+use crate::config;
+
+// the webview origins of the desktop app, pages served by the server
+// itself are same origin and need no entry
+pub const DEFAULT_ORIGINS: [&str; 3] = ["tauri://localhost", "https://tauri.localhost", "http://tauri.localhost"];
+
+// browsers can not set headers on an `EventSource`, so the token is also
+// accepted as this query parameter
+pub const TOKEN_PARAM: &str = "access_token";
+
+// compared against the decoded segments rocket routes on, so `/sizes//api` or
+// `/sizes/%61pi` are api requests too
+const API_SEGMENTS: [&str; 2] = ["sizes", "api"];
+const UNAUTHORIZED_URI: &str = "/sizes/api/unauthorized";
+
+// the per-install secret every api request has to present as `Authorization: Bearer <token>`
+#[derive(Debug, Clone)]
+pub struct ApiToken(pub String);
+
+impl ApiToken {
+    // compares every byte whatever the first difference, so the time taken
+    // tells nothing about how much of a guess was right
+    pub fn matches(&self, candidate: &str) -> bool {
+        let (expected, candidate) = (self.0.as_bytes(), candidate.as_bytes());
+        if expected.len() != candidate.len() {
+            return false;
+        }
+        expected.iter().zip(candidate).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+    }
+}
+
+// `server.token_file`, or `token` next to the config file
+pub fn token_file(options: &ServerOptions) -> PathBuf {
+    if let Some(path) = &options.token_file {
+        return path.clone();
+    }
+    let config_file = config::get().file.clone().unwrap_or(config::config_file(None));
+    config_file.parent().unwrap_or(Path::new(".")).join("token")
+}
+
+// reads the token of this install, the first start writes a new one
+pub fn load_or_create_token(path: &Path) -> crate::Result<ApiToken> {
+    match fs::read_to_string(path) {
+        Ok(token) if !token.trim().is_empty() => return Ok(ApiToken(token.trim().to_string())),
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let token = crate::random_token()?;
+    fs::write(path, &token)?;
+    restrict_permissions(path)?;
+    eprintln!("created api token in {}", path.display());
+    Ok(ApiToken(token))
+}
+
+pub fn api_token(options: &ServerOptions) -> crate::Result<ApiToken> {
+    load_or_create_token(&token_file(options))
+}
+
+#[cfg(unix)]
+fn restrict_permissions(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+}
+
+#[cfg(not(unix))]
+fn restrict_permissions(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+// checks the token of every api request and answers cross origin requests
+// only for the allowed origins, requests without a valid token are routed
+// to `unauthorized` before any handler runs
+pub struct ApiAuth {
+    token_file: PathBuf,
+    allowed_origins: Vec<String>,
+}
+
+impl ApiAuth {
+    pub fn new(options: &ServerOptions) -> ApiAuth {
+        ApiAuth {
+            token_file: token_file(options),
+            allowed_origins: options.allowed_origins.clone(),
+        }
+    }
+
+    fn is_allowed(&self, origin: &str) -> bool {
+        self.allowed_origins.iter().any(|allowed| allowed == "*" || allowed == origin)
+    }
+}
+
+fn request_token<'r>(req: &'r Request<'_>) -> Option<&'r str> {
+    if let Some(value) = req.headers().get_one("Authorization") {
+        return value.strip_prefix("Bearer ").map(str::trim);
+    }
+    req.query_value::<&str>(TOKEN_PARAM).and_then(|v| v.ok())
+}
+
+#[rocket::async_trait]
+impl Fairing for ApiAuth {
+    fn info(&self) -> Info {
+        Info {
+            name: "api token and cors",
+            kind: Kind::Ignite | Kind::Liftoff | Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> rocket::fairing::Result {
+        match load_or_create_token(&self.token_file) {
+            Ok(token) => Ok(rocket.manage(token)),
+            Err(e) => {
+                eprintln!("load api token from {} failed, {}", self.token_file.display(), e);
+                Err(rocket)
+            }
+        }
+    }
+
+    async fn on_liftoff(&self, _rocket: &Rocket<Orbit>) {
+        println!("api token in {}", self.token_file.display());
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _data: &mut Data<'_>) {
+        // preflight requests never carry credentials
+        let is_api = req.uri().path().segments().take(API_SEGMENTS.len()).eq(API_SEGMENTS);
+        if !is_api || req.method() == Method::Options {
+            return;
+        }
+        let expected = req.rocket().state::<ApiToken>();
+        if let (Some(expected), Some(token)) = (expected, request_token(req)) {
+            if expected.matches(token) {
+                return;
+            }
+        }
+        req.set_method(Method::Get);
+        req.set_uri(Origin::parse(UNAUTHORIZED_URI).unwrap());
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let Some(origin) = req.headers().get_one("Origin") else {
+            return;
+        };
+        if !self.is_allowed(origin) {
+            return;
+        }
+        res.set_header(Header::new("Access-Control-Allow-Origin", origin.to_string()));
+        res.set_header(Header::new("Access-Control-Allow-Methods", "GET, POST, OPTIONS"));
+        res.set_header(Header::new("Access-Control-Allow-Headers", "Authorization, Content-Type"));
+        res.set_header(Header::new("Vary", "Origin"));
+    }
+}
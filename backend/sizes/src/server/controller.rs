@@ -0,0 +1,644 @@
+use rocket::http::{ContentType, Status};
+use rocket::response::{Responder, Response};
+use rocket::response::stream::{Event, EventStream};
+use rocket::serde::json::Json;
+use rocket::tokio::select;
+use rocket::tokio::sync::broadcast::error::RecvError;
+use rocket::tokio::io::{AsyncRead, ReadBuf};
+use rocket::tokio::sync::mpsc;
+use rocket::tokio::task;
+use rocket::data::{Data, ToByteUnit};
+use rocket::{catch, get, options, post, Request, Shutdown, State};
+use serde::Serialize;
+use serde_json::{json, Value};
+use crate::cleanup::{self, AuditRecord, CleanupAction, CleanupTicket};
+use crate::cmd::Command;
+use crate::{conf, config, home_dir, Error};
+use crate::config::AppConfig;
+use crate::conf::WatchDirectoryConfiguration;
+use crate::db::children::{self, ChildEntry};
+use crate::db::dirorder::{self, Direction, Orderby, Page};
+use crate::db::dirstat::{self, get_dir_stat_recursive, DirStat};
+use crate::db::purge::{self, PurgeStats};
+use crate::db::search::{self, MatchMode, MatchTarget, SearchQuery};
+use crate::db::maintenance::{self, BackupRecord};
+use crate::db::retention::{self, RetentionReport};
+use crate::db::stats::{self, DbStats};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::task::{Context, Poll};
+use super::AppState;
+use crate::db::scanresult::{self, get_last_dir_scan_result};
+use crate::export::{self, ExportFormat};
+use crate::import::{self, ImportRecord, ImportSource};
+use crate::reclaim::{self, ReclaimRule, ReclaimableReport};
+use crate::scan::{DirScanOverview, DirScanResult};
+
+// the json body of every failed request, `code` is one of `Error::code`
+#[derive(Debug, Serialize)]
+pub struct ErrorBody {
+    pub code: String,
+    pub message: String,
+    pub details: Option<Value>,
+}
+
+#[derive(Debug)]
+pub struct ApiError {
+    pub error: Error,
+    pub details: Option<Value>,
+}
+
+impl From<Error> for ApiError {
+    fn from(error: Error) -> ApiError {
+        ApiError { error, details: None }
+    }
+}
+
+impl ApiError {
+    pub fn status(&self) -> Status {
+        match self.error {
+            Error::NotFound(_) => Status::NotFound,
+            Error::Unauthorized(_) => Status::Unauthorized,
+            Error::InvalidPath(_) | Error::Other(_) => Status::BadRequest,
+            // both mean the request could not be done in the current state
+            Error::Busy(_) | Error::Cancelled(_) => Status::Conflict,
+            Error::Storage(_) | Error::Decode(_) | Error::Io(_) => Status::InternalServerError,
+        }
+    }
+
+    pub fn body(&self) -> ErrorBody {
+        ErrorBody {
+            code: self.error.code().to_string(),
+            message: self.error.message().to_string(),
+            details: self.details.clone(),
+        }
+    }
+}
+
+pub struct ResultResponder<T>(pub Result<T, ApiError>);
+
+impl<T> ResultResponder<T> {
+    fn err(error: impl Into<Error>) -> ResultResponder<T> {
+        ResultResponder(Err(ApiError::from(error.into())))
+    }
+
+    fn err_with(error: impl Into<Error>, details: Value) -> ResultResponder<T> {
+        ResultResponder(Err(ApiError { error: error.into(), details: Some(details) }))
+    }
+
+    fn of(res: crate::Result<T>) -> ResultResponder<T> {
+        ResultResponder(res.map_err(ApiError::from))
+    }
+}
+
+impl<T> From<T> for ResultResponder<T> {
+    fn from(value: T) -> ResultResponder<T> {
+        ResultResponder(Ok(value))
+    }
+}
+
+impl<'r, T> Responder<'r, 'static> for ResultResponder<T>
+where
+    T: Debug + Serialize,
+{
+    fn respond_to(self, req: &'r Request<'_>) -> rocket::response::Result<'static> {
+        match self.0 {
+            Ok(value) => Json(value).respond_to(req),
+            Err(err) => (err.status(), Json(err.body())).respond_to(req),
+        }
+    }
+}
+
+#[get("/api/scan?<path>")]
+pub async fn scan_dir(app_state: &State<AppState>, path: &str) -> ResultResponder<String> {
+    let path_buf = PathBuf::from(path);
+    if !path_buf.is_absolute() || !path_buf.is_dir() {
+        return ResultResponder::err_with(
+            Error::InvalidPath(format!("{} is not an absolute path of a directory", path)),
+            json!({ "path": path }),
+        );
+    }
+    let task_manager = &app_state.client.task_manager;
+    if task_manager.scan_progress().await.contains_key(&path_buf) {
+        return ResultResponder::err_with(
+            Error::Busy(format!("{} is being scanned", path)),
+            json!({ "path": path }),
+        );
+    }
+    if let Err(err) = task_manager.send(Command::ScanDir(path_buf)).await {
+        return ResultResponder::err(err.to_string());
+    }
+    ResultResponder::from(format!("queued scan dir command for {} successfully", path))
+}
+
+#[post("/api/scan/abort?<path>")]
+pub async fn abort_scan(app_state: &State<AppState>, path: &str) -> ResultResponder<String> {
+    match app_state.client.task_manager.abort(Path::new(path)).await {
+        Ok(_) => ResultResponder::from(format!("aborting the scan of {}", path)),
+        Err(err) => ResultResponder::err_with(err, json!({ "path": path })),
+    }
+}
+
+// scan events as they happen, each named after its status, e.g. `event: completed`
+#[get("/api/events")]
+pub fn task_events(app_state: &State<AppState>, mut shutdown: Shutdown) -> EventStream![] {
+    let mut rx = app_state.client.task_manager.subscribe();
+    EventStream! {
+        loop {
+            let event = select! {
+                msg = rx.recv() => match msg {
+                    Ok(event) => event,
+                    Err(RecvError::Closed) => break,
+                    Err(RecvError::Lagged(_)) => continue,
+                },
+                _ = &mut shutdown => break,
+            };
+            yield Event::json(&event).event(event.status.name());
+        }
+    }
+}
+
+#[get("/api/progress")]
+pub async fn scan_dir_progress(
+    app_state: &State<AppState>
+) -> ResultResponder<HashMap<PathBuf, DirScanResult>> {
+    let ongoings = app_state
+        .client
+        .task_manager
+        .scan_progress()
+        .await;
+
+        ResultResponder::from(ongoings)
+}
+
+// newest first, `from` and `to` bound the finish time in unix seconds
+#[get("/api/result?<path>&<limit>&<from>&<to>")]
+pub fn scan_dir_results(
+    app_state: &State<AppState>,
+    path: &str,
+    limit: Option<u32>,
+    from: Option<u64>,
+    to: Option<u64>,
+) -> ResultResponder<Vec<DirScanResult>> {
+    let db = app_state.client.db.as_ref();
+    let limit = limit.unwrap_or(10);
+    let res = match (from, to) {
+        (None, None) => scanresult::get_dir_scan_result(db, Path::new(path), limit),
+        (from, to) => scanresult::get_dir_scan_results_between(
+            db,
+            Path::new(path),
+            from.unwrap_or(0),
+            to.unwrap_or(u64::MAX),
+            limit,
+        ),
+    };
+    ResultResponder::of(res)
+}
+
+// the last result finished at or before `ts`
+#[get("/api/result/at?<path>&<ts>")]
+pub fn scan_dir_result_at(app_state: &State<AppState>, path: &str, ts: u64) -> ResultResponder<DirScanResult> {
+    match scanresult::get_dir_scan_result_at(app_state.client.db.as_ref(), Path::new(path), ts) {
+        Some(result) => ResultResponder::from(result),
+        None => ResultResponder::err_with(
+            Error::NotFound(format!("no scan of {} finished by {}", path, ts)),
+            json!({ "path": path, "ts": ts }),
+        ),
+    }
+}
+
+#[post("/api/results", data = "<paths>")]
+pub fn dir_results(
+    app_state: &State<AppState>,
+    paths: Json<Vec<&str>>,
+) -> ResultResponder<HashMap<String, DirScanResult>> {
+    let mut m = HashMap::<String, DirScanResult>::new();
+    for path in paths.iter() {
+        let res = get_last_dir_scan_result(app_state.client.db.as_ref(), Path::new(path));
+        let v = res.unwrap_or_else(DirScanResult::new);
+        m.insert(path.to_string(), v);
+    }
+    ResultResponder::from(m)
+}
+
+#[get("/api/watches")]
+pub fn list_watch_dir(
+    app_state: &State<AppState>,
+) -> ResultResponder<Vec<WatchDirectoryConfiguration>> {
+    let watches = conf::list_watch(app_state.client.db.as_ref());
+    ResultResponder::from(watches)
+}
+
+#[post("/api/watches/add", data = "<watch>")]
+pub fn add_watch_dir(
+    app_state: &State<AppState>,
+    watch: Json<WatchDirectoryConfiguration>,
+) -> ResultResponder<Vec<WatchDirectoryConfiguration>> {
+    let db = app_state.client.db.as_ref();
+    if let Err(err) = conf::add_watch(db, &watch.0) {
+        return ResultResponder::err(err);
+    }
+    let watches = conf::list_watch(db);
+    ResultResponder::from(watches)
+}
+
+// with `purge` the scanned data of the dir goes too, see `purge::remove_watch_and_data`
+#[post("/api/watches/delete?<purge>", data = "<watch>")]
+pub async fn remove_watch_dir(
+    app_state: &State<AppState>,
+    watch: Json<WatchDirectoryConfiguration>,
+    purge: Option<bool>,
+) -> ResultResponder<Vec<WatchDirectoryConfiguration>> {
+    let task_manager = &app_state.client.task_manager;
+    let watch = watch.0;
+    let res = match purge {
+        Some(true) => {
+            let path = PathBuf::from(&watch.path);
+            task_manager.run_on_path(&path, move |db| purge::remove_watch_and_data(db, &watch).map(|_| ())).await
+        }
+        _ => task_manager.run_shared(move |db| conf::remove_watch(db, &watch)).await,
+    };
+    if let Err(err) = res {
+        return ResultResponder::err(err);
+    }
+    let watches = conf::list_watch(app_state.client.db.as_ref());
+    ResultResponder::from(watches)
+}
+
+// drops everything known about a dir and its descendants
+#[post("/api/purge?<path>")]
+pub async fn purge_dir(app_state: &State<AppState>, path: &str) -> ResultResponder<PurgeStats> {
+    let path_buf = PathBuf::from(path);
+    if !path_buf.is_absolute() {
+        return ResultResponder::err_with(
+            Error::InvalidPath(format!("{} is not an absolute path", path)),
+            json!({ "path": path }),
+        );
+    }
+    // a scan running inside the subtree would write part of it back
+    let task_manager = &app_state.client.task_manager;
+    let target = path_buf.clone();
+    match task_manager.run_on_path(&path_buf, move |db| purge::purge_subtree(db, &target)).await {
+        Err(err @ Error::Busy(_)) => ResultResponder::err_with(err, json!({ "path": path })),
+        res => ResultResponder::of(res),
+    }
+}
+
+// `min` applies to the value of `orderby`, blocks by default
+#[get("/api/largest?<min>&<limit>&<offset>&<orderby>&<direction>")]
+pub fn get_largest(
+    app_state: &State<AppState>,
+    min: Option<u64>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+    orderby: Option<Orderby>,
+    direction: Option<Direction>,
+) -> ResultResponder<Page<DirStat>> {
+    let db = app_state.client.db.as_ref();
+    ResultResponder::of(dirorder::list_dirs(
+        db,
+        orderby.unwrap_or_default(),
+        direction.unwrap_or_default(),
+        min.unwrap_or(0),
+        offset.unwrap_or(0),
+        limit.unwrap_or(10)
+    ))
+}
+
+// the immediate children of `path` with their recursive sizes, for drilling down
+#[get("/api/children?<path>&<orderby>&<direction>&<offset>&<limit>")]
+pub fn list_children(
+    app_state: &State<AppState>,
+    path: &str,
+    orderby: Option<Orderby>,
+    direction: Option<Direction>,
+    offset: Option<u32>,
+    limit: Option<u32>,
+) -> ResultResponder<Page<ChildEntry>> {
+    ResultResponder::of(children::list_children(
+        app_state.client.db.as_ref(),
+        Path::new(path),
+        orderby.unwrap_or_default(),
+        direction.unwrap_or_default(),
+        offset.unwrap_or(0),
+        limit.unwrap_or(u32::MAX)
+    ))
+}
+
+// dirs whose name or path matches `q`, sizes are the blocks of the files
+// directly in a dir and times are in unix seconds
+#[allow(clippy::too_many_arguments)]
+#[get("/api/search?<q>&<mode>&<target>&<case>&<min>&<max>&<after>&<before>&<orderby>&<direction>&<offset>&<limit>")]
+pub fn search_dirs(
+    app_state: &State<AppState>,
+    q: &str,
+    mode: Option<MatchMode>,
+    target: Option<MatchTarget>,
+    case: Option<bool>,
+    min: Option<u64>,
+    max: Option<u64>,
+    after: Option<i64>,
+    before: Option<i64>,
+    orderby: Option<Orderby>,
+    direction: Option<Direction>,
+    offset: Option<u32>,
+    limit: Option<u32>,
+) -> ResultResponder<Page<DirStat>> {
+    let query = SearchQuery {
+        pattern: q.to_string(),
+        mode: mode.unwrap_or_default(),
+        target: target.unwrap_or_default(),
+        case_sensitive: case.unwrap_or(false),
+        min_blocks: min,
+        max_blocks: max,
+        modified_after: after,
+        modified_before: before,
+        orderby: orderby.unwrap_or_default(),
+        direction: direction.unwrap_or_default(),
+        offset: offset.unwrap_or(0),
+        limit: limit.unwrap_or(0),
+    };
+    ResultResponder::of(search::search(app_state.client.db.as_ref(), &query))
+}
+
+#[get("/api/stat?<path>")]
+pub fn get_dir_stat(app_state: &State<AppState>, path: &str) -> ResultResponder<DirScanOverview> {
+    get_dir_stat_recursive(app_state.client.db.as_ref(), Path::new(path))
+        .unwrap_or(DirScanOverview::new())
+        .into()
+}
+
+#[post("/api/cleanup?<path>&<action>")]
+pub async fn prepare_cleanup(
+    app_state: &State<AppState>,
+    path: &str,
+    action: &str,
+) -> ResultResponder<CleanupTicket> {
+    let action = match CleanupAction::from_str(action) {
+        Ok(action) => action,
+        Err(err) => return ResultResponder::err(err),
+    };
+    let path = PathBuf::from(path);
+    ResultResponder::of(app_state.client.task_manager.run_shared(move |db| cleanup::prepare(db, &path, action)).await)
+}
+
+#[post("/api/cleanup/confirm?<token>")]
+pub async fn confirm_cleanup(app_state: &State<AppState>, token: &str) -> ResultResponder<AuditRecord> {
+    let client = &app_state.client;
+    let ticket = match cleanup::find_ticket(client.db.as_ref(), token) {
+        Ok(ticket) => ticket,
+        Err(err) => return ResultResponder::err(err),
+    };
+    let token = token.to_string();
+    ResultResponder::of(client.task_manager.run_on_path(&ticket.path, move |db| cleanup::confirm(db, &token)).await)
+}
+
+#[get("/api/cleanup/audit?<limit>")]
+pub fn cleanup_audit(app_state: &State<AppState>, limit: Option<u32>) -> ResultResponder<Vec<AuditRecord>> {
+    ResultResponder::from(cleanup::list_audit(app_state.client.db.as_ref(), limit.unwrap_or(100)))
+}
+
+#[get("/api/reclaimable?<path>")]
+pub fn get_reclaimable(app_state: &State<AppState>, path: Option<&str>) -> ResultResponder<ReclaimableReport> {
+    let db = app_state.client.db.as_ref();
+    let root = path.map(PathBuf::from).unwrap_or_else(home_dir);
+    let rules = reclaim::all_rules(db);
+    ResultResponder::from(reclaim::analyze(db, &root, &rules))
+}
+
+#[get("/api/reclaimable/rules")]
+pub fn list_reclaim_rules(app_state: &State<AppState>) -> ResultResponder<Vec<ReclaimRule>> {
+    ResultResponder::from(reclaim::all_rules(app_state.client.db.as_ref()))
+}
+
+#[post("/api/reclaimable/rules/add", data = "<rule>")]
+pub fn add_reclaim_rule(
+    app_state: &State<AppState>,
+    rule: Json<ReclaimRule>,
+) -> ResultResponder<Vec<ReclaimRule>> {
+    let db = app_state.client.db.as_ref();
+    if let Err(err) = conf::add_reclaim_rule(db, &rule.0) {
+        return ResultResponder::err(err);
+    }
+    ResultResponder::from(reclaim::all_rules(db))
+}
+
+#[post("/api/reclaimable/rules/delete", data = "<rule>")]
+pub fn remove_reclaim_rule(
+    app_state: &State<AppState>,
+    rule: Json<ReclaimRule>,
+) -> ResultResponder<Vec<ReclaimRule>> {
+    let db = app_state.client.db.as_ref();
+    if let Err(err) = conf::remove_reclaim_rule(db, &rule.name) {
+        return ResultResponder::err(err);
+    }
+    ResultResponder::from(reclaim::all_rules(db))
+}
+
+// hands the bytes written by a blocking exporter over to the response stream
+struct ChannelWriter(mpsc::Sender<io::Result<Vec<u8>>>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .blocking_send(Ok(buf.to_vec()))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "export stream closed"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// response body fed by a ChannelWriter, an exporter failure ends the body with an error
+// instead of a clean eof so the client never takes a truncated export as complete
+pub struct ExportBody {
+    rx: mpsc::Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl AsyncRead for ExportBody {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        while self.pos >= self.chunk.len() {
+            match self.rx.poll_recv(cx) {
+                Poll::Ready(Some(Ok(chunk))) => {
+                    self.chunk = chunk;
+                    self.pos = 0;
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e)),
+                Poll::Ready(None) => return Poll::Ready(Ok(())),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        let n = buf.remaining().min(self.chunk.len() - self.pos);
+        buf.put_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ExportBody {
+    fn respond_to(self, _: &'r Request<'_>) -> rocket::response::Result<'static> {
+        Response::build().streamed_body(self).ok()
+    }
+}
+
+#[get("/api/export?<path>&<format>&<depth>")]
+pub fn export_dir(
+    app_state: &State<AppState>,
+    path: &str,
+    format: Option<&str>,
+    depth: Option<u32>,
+) -> Result<(ContentType, ExportBody), ResultResponder<String>> {
+    let format = match format.map_or(Ok(ExportFormat::Json), ExportFormat::from_str) {
+        Ok(format) => format,
+        Err(err) => return Err(ResultResponder::err(err)),
+    };
+    let content_type = match format {
+        ExportFormat::Csv => ContentType::CSV,
+        _ => ContentType::JSON,
+    };
+
+    let db = app_state.client.db.clone();
+    let root = PathBuf::from(path);
+    match dirstat::get_dir_stat(db.as_ref(), &root) {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return Err(ResultResponder::err_with(
+                Error::NotFound(format!("{} has not been scanned", path)),
+                json!({ "path": path }),
+            ))
+        }
+        Err(e) => return Err(ResultResponder::err(e)),
+    }
+    let (tx, rx) = mpsc::channel::<io::Result<Vec<u8>>>(16);
+    task::spawn_blocking(move || {
+        let mut out = BufWriter::with_capacity(64 << 10, ChannelWriter(tx));
+        let res = export::export(db.as_ref(), &root, format, depth, &mut out).and_then(|_| Ok(out.flush()?));
+        if let Err(e) = res {
+            eprintln!("export {:?} failed, {}", root, e);
+            let _ = out.get_ref().0.blocking_send(Err(io::Error::other(e.to_string())));
+        }
+    });
+
+    Ok((content_type, ExportBody { rx, chunk: Vec::new(), pos: 0 }))
+}
+
+
+#[post("/api/import?<host>&<format>", data = "<data>")]
+pub async fn import_tree(
+    app_state: &State<AppState>,
+    host: &str,
+    format: &str,
+    data: Data<'_>,
+) -> ResultResponder<ImportRecord> {
+    let source = match ImportSource::from_str(format) {
+        Ok(source) => source,
+        Err(err) => return ResultResponder::err(err),
+    };
+    let bytes = match data.open(1.gibibytes()).into_bytes().await {
+        Ok(bytes) if bytes.is_complete() => bytes.into_inner(),
+        Ok(_) => return ResultResponder::err("import data is too large"),
+        Err(err) => return ResultResponder::err(err),
+    };
+
+    let host = host.to_string();
+    let task_manager = &app_state.client.task_manager;
+    ResultResponder::of(task_manager.run_shared(move |db| import::import(db, &host, source, bytes.as_slice())).await)
+}
+
+#[get("/api/imports")]
+pub fn list_imports(app_state: &State<AppState>) -> ResultResponder<Vec<ImportRecord>> {
+    ResultResponder::from(import::list_imports(app_state.client.db.as_ref()))
+}
+
+// what the index itself costs, key counts and sizes per table
+#[get("/api/db/stats")]
+pub fn db_stats(app_state: &State<AppState>) -> ResultResponder<DbStats> {
+    ResultResponder::of(stats::db_stats(app_state.client.db.as_ref()))
+}
+
+async fn queue(app_state: &State<AppState>, cmd: Command) -> ResultResponder<String> {
+    let name = cmd.name();
+    match app_state.client.task_manager.send(cmd).await {
+        Ok(_) => ResultResponder::from(format!("queued {}, it starts once no scan is running", name)),
+        Err(err) => ResultResponder::err(err.to_string()),
+    }
+}
+
+// of one table, all of them when none is given
+#[post("/api/db/compact?<table>")]
+pub async fn compact_db(app_state: &State<AppState>, table: Option<&str>) -> ResultResponder<String> {
+    if let Err(err) = maintenance::check_table(table) {
+        return ResultResponder::err_with(err, json!({ "table": table }));
+    }
+    queue(app_state, Command::Compact(table.map(str::to_string))).await
+}
+
+#[post("/api/db/backup?<path>")]
+pub async fn backup_db(app_state: &State<AppState>, path: &str) -> ResultResponder<String> {
+    if let Err(err) = maintenance::check_backup_target(Path::new(path)) {
+        return ResultResponder::err_with(err, json!({ "path": path }));
+    }
+    queue(app_state, Command::Backup(PathBuf::from(path))).await
+}
+
+#[get("/api/db/backups")]
+pub fn list_backups(app_state: &State<AppState>) -> ResultResponder<Vec<BackupRecord>> {
+    ResultResponder::from(maintenance::list_backups(app_state.client.db.as_ref()))
+}
+
+// replaces all data of the database with the backup at `path`
+#[post("/api/db/restore?<path>")]
+pub async fn restore_db(app_state: &State<AppState>, path: &str) -> ResultResponder<String> {
+    if let Err(err) = maintenance::check_backup_source(Path::new(path)) {
+        return ResultResponder::err_with(err, json!({ "path": path }));
+    }
+    queue(app_state, Command::Restore(PathBuf::from(path))).await
+}
+
+// applies the `[retention]` limits now instead of at the next scheduled run
+#[post("/api/db/retention")]
+pub async fn apply_retention(app_state: &State<AppState>) -> ResultResponder<String> {
+    queue(app_state, Command::Retention).await
+}
+
+// what the last run removed, none before the first one
+#[get("/api/db/retention")]
+pub fn last_retention(app_state: &State<AppState>) -> ResultResponder<Option<RetentionReport>> {
+    ResultResponder::from(retention::last_report(app_state.client.db.as_ref()))
+}
+
+#[get("/api/config")]
+pub fn get_config() -> ResultResponder<AppConfig> {
+    ResultResponder::from(config::get().clone())
+}
+
+// requests without a valid api token are rerouted here by `auth::ApiAuth`
+#[get("/api/unauthorized")]
+pub fn unauthorized() -> ResultResponder<()> {
+    ResultResponder::err(Error::Unauthorized("missing or invalid api token".to_string()))
+}
+
+// cors preflight, the headers are added by `auth::ApiAuth`
+#[options("/api/<_..>")]
+pub fn preflight() -> Status {
+    Status::NoContent
+}
+
+// json bodies for the errors rocket raises itself, e.g. unknown routes or
+// malformed parameters, and for handlers that panicked
+#[catch(default)]
+pub fn default_catcher(status: Status, _req: &Request) -> (Status, ResultResponder<()>) {
+    let error = match status.code {
+        404 => Error::NotFound("no such api".to_string()),
+        _ => Error::Other(status.reason_lossy().to_string()),
+    };
+    (status, ResultResponder::err(error))
+}
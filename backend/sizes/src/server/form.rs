@@ -0,0 +1,35 @@
+// query parameters parsed with the `FromStr` of the db types, so the db
+// layer stays free of rocket
+use rocket::form::{self, FromFormField, ValueField};
+
+use crate::db::dirorder::{Direction, Orderby};
+use crate::db::search::{MatchMode, MatchTarget};
+
+fn parse_field<'v, T>(field: ValueField<'v>) -> form::Result<'v, T>
+where T: std::str::FromStr<Err = crate::Error> {
+    field.value.parse().map_err(|e: crate::Error| form::Error::validation(e.to_string()).into())
+}
+
+impl<'v> FromFormField<'v> for Orderby {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+        parse_field(field)
+    }
+}
+
+impl<'v> FromFormField<'v> for Direction {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+        parse_field(field)
+    }
+}
+
+impl<'v> FromFormField<'v> for MatchMode {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+        parse_field(field)
+    }
+}
+
+impl<'v> FromFormField<'v> for MatchTarget {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+        parse_field(field)
+    }
+}
@@ -0,0 +1,109 @@
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+use rocket::fs::FileServer;
+use rocket::{catchers, routes, Build, Catcher, Rocket, Route};
+use serde::{Deserialize, Serialize};
+
+use crate::Client;
+
+pub mod auth;
+pub mod controller;
+mod form;
+
+pub struct AppState {
+    pub client: Client,
+}
+
+// unset fields fall back to the Rocket defaults, i.e. Rocket.toml and ROCKET_* env vars
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerOptions {
+    pub address: Option<IpAddr>,
+    pub port: Option<u16>,
+    // the built frontend, served at `/` when present
+    pub static_dir: Option<PathBuf>,
+    // where the api token is kept, `token` next to the config file when unset
+    pub token_file: Option<PathBuf>,
+    // origins of other pages allowed to call the api, `*` allows any
+    pub allowed_origins: Vec<String>,
+}
+
+impl Default for ServerOptions {
+    fn default() -> Self {
+        ServerOptions {
+            address: None,
+            port: None,
+            static_dir: None,
+            token_file: None,
+            allowed_origins: auth::DEFAULT_ORIGINS.iter().map(|o| o.to_string()).collect(),
+        }
+    }
+}
+
+pub fn routes() -> Vec<Route> {
+    use controller::*;
+
+    routes![
+        scan_dir,
+        abort_scan,
+        task_events,
+        scan_dir_progress,
+        scan_dir_results,
+        scan_dir_result_at,
+        list_watch_dir,
+        add_watch_dir,
+        remove_watch_dir,
+        purge_dir,
+        dir_results,
+        get_largest,
+        list_children,
+        search_dirs,
+        get_dir_stat,
+        prepare_cleanup,
+        confirm_cleanup,
+        cleanup_audit,
+        get_reclaimable,
+        list_reclaim_rules,
+        add_reclaim_rule,
+        remove_reclaim_rule,
+        export_dir,
+        import_tree,
+        list_imports,
+        db_stats,
+        compact_db,
+        backup_db,
+        list_backups,
+        restore_db,
+        apply_retention,
+        last_retention,
+        get_config,
+        unauthorized,
+        preflight
+    ]
+}
+
+pub fn catchers() -> Vec<Catcher> {
+    catchers![controller::default_catcher]
+}
+
+pub fn build(client: Client, options: &ServerOptions) -> Rocket<Build> {
+    let mut figment = rocket::Config::figment();
+    if let Some(address) = options.address {
+        figment = figment.merge(("address", address));
+    }
+    if let Some(port) = options.port {
+        figment = figment.merge(("port", port));
+    }
+
+    let rocket_builder = rocket::custom(figment)
+        .manage(AppState { client })
+        .attach(auth::ApiAuth::new(options))
+        .mount("/sizes", routes())
+        .register("/sizes", catchers());
+
+    match &options.static_dir {
+        Some(dir) => rocket_builder.mount("/", FileServer::from(dir)),
+        None => rocket_builder,
+    }
+}
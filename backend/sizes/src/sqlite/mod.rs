@@ -0,0 +1,243 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::kvstore::{Batch, BatchOp, KvStore};
+use crate::rocksdb::prefix_upper_bound;
+
+// rows fetched per round trip while walking a prefix, the connection is not
+// held while the callback runs so it may read and write the store itself
+const PAGE_ROWS: i64 = 1024;
+
+// a `KvStore` in a single sqlite file, every table of the app is a sqlite
+// table `(k BLOB PRIMARY KEY, v BLOB)`. keys are compared as blobs, i.e.
+// bytewise, so prefix walks give the same order as rocksdb
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+    path: PathBuf,
+}
+
+impl fmt::Debug for SqliteStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SqliteStore").field("path", &self.path).finish()
+    }
+}
+
+impl SqliteStore {
+    pub fn open(path: &Path, tables: &[&str], truncate: bool) -> crate::Result<SqliteStore> {
+        if truncate {
+            for suffix in ["", "-wal", "-shm"] {
+                let file = PathBuf::from(format!("{}{}", path.display(), suffix));
+                if file.exists() {
+                    fs::remove_file(file)?;
+                }
+            }
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        for table in tables {
+            conn.execute_batch(&format!(
+                "CREATE TABLE IF NOT EXISTS \"{}\" (k BLOB PRIMARY KEY, v BLOB NOT NULL) WITHOUT ROWID",
+                table
+            ))?;
+        }
+        Ok(SqliteStore { conn: Mutex::new(conn), path: path.to_path_buf() })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // the rows of a table and the bytes of their keys and values
+    pub fn table_size(&self, table: &str) -> crate::Result<(u64, u64)> {
+        let conn = self.conn.lock().unwrap();
+        let sql = format!("SELECT count(*), coalesce(sum(length(k) + length(v)), 0) FROM \"{}\"", table);
+        let (rows, bytes): (i64, i64) = conn.query_row(&sql, params![], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok((rows as u64, bytes as u64))
+    }
+
+    // rebuilds the file without the pages freed by deletes
+    pub fn vacuum(&self) -> crate::Result<()> {
+        self.conn.lock().unwrap().execute_batch("VACUUM")?;
+        Ok(())
+    }
+
+    // a consistent copy of the database in a new file at `path`
+    pub fn vacuum_into(&self, path: &Path) -> crate::Result<()> {
+        let path = path.to_string_lossy().to_string();
+        self.conn.lock().unwrap().execute("VACUUM INTO ?1", params![path.as_str()])?;
+        Ok(())
+    }
+
+    // the database file itself and its write ahead log
+    pub fn files(&self) -> Vec<PathBuf> {
+        ["", "-wal", "-shm"].iter().map(|suffix| PathBuf::from(format!("{}{}", self.path.display(), suffix))).collect()
+    }
+
+    // one page of the keys in `[low, high)`, after `from` when given
+    fn page(
+        &self,
+        table: &str,
+        (low, high): (&[u8], Option<&[u8]>),
+        from: Option<&[u8]>,
+        rows: i64,
+        reverse: bool,
+    ) -> crate::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let (low, high) = match (reverse, from) {
+            (false, Some(from)) => (from, high),
+            (true, Some(from)) => (low, Some(from)),
+            (_, None) => (low, high),
+        };
+        let low_op = if !reverse && from.is_some() { ">" } else { ">=" };
+        let mut sql = format!("SELECT k, v FROM \"{}\" WHERE k {} ?1", table, low_op);
+        if high.is_some() {
+            sql.push_str(" AND k < ?2");
+        }
+        sql.push_str(if reverse { " ORDER BY k DESC" } else { " ORDER BY k" });
+        sql.push_str(&format!(" LIMIT {}", rows));
+
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(&sql)?;
+        let map = |row: &rusqlite::Row| -> rusqlite::Result<(Vec<u8>, Vec<u8>)> { Ok((row.get(0)?, row.get(1)?)) };
+        let rows = match high {
+            Some(high) => stmt.query_map(params![low, high], map)?.collect::<Result<Vec<_>, _>>()?,
+            None => stmt.query_map(params![low], map)?.collect::<Result<Vec<_>, _>>()?,
+        };
+        Ok(rows)
+    }
+
+    // stops at the first row the callback returns false for
+    fn walk<F>(&self, table: &str, range: (&[u8], Option<&[u8]>), limit: u32, reverse: bool, mut callback: F)
+    where F: FnMut(&str, &[u8]) -> bool {
+        let mut left = if limit == 0 { i64::MAX } else { limit as i64 };
+        let mut last: Option<Vec<u8>> = None;
+        while left > 0 {
+            let rows = match self.page(table, range, last.as_deref(), left.min(PAGE_ROWS), reverse) {
+                Ok(rows) => rows,
+                Err(e) => {
+                    eprintln!("sqlite foreach on {} error: {}", table, e);
+                    return;
+                }
+            };
+            let done = (rows.len() as i64) < PAGE_ROWS.min(left);
+            for (k, v) in &rows {
+                if !callback(&String::from_utf8_lossy(k), v) {
+                    return;
+                }
+            }
+            left -= rows.len() as i64;
+            match rows.into_iter().last() {
+                Some((k, _)) if !done => last = Some(k),
+                _ => return,
+            }
+        }
+    }
+
+    fn walk_prefix<F>(&self, table: &str, prefix: &str, limit: u32, reverse: bool, callback: F)
+    where F: FnMut(&str, &[u8]) -> bool {
+        let upper = prefix_upper_bound(prefix.as_bytes());
+        self.walk(table, (prefix.as_bytes(), upper.as_deref()), limit, reverse, callback)
+    }
+}
+
+impl KvStore for SqliteStore {
+    fn get_bytes(&self, table: &str, key: impl AsRef<str>) -> Option<impl AsRef<[u8]>> {
+        let conn = self.conn.lock().unwrap();
+        let sql = format!("SELECT v FROM \"{}\" WHERE k = ?1", table);
+        let res = conn
+            .prepare_cached(&sql)
+            .and_then(|mut stmt| stmt.query_row(params![key.as_ref().as_bytes()], |row| row.get::<_, Vec<u8>>(0)).optional());
+        match res {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("sqlite get on {} error: {}", table, e);
+                None
+            }
+        }
+    }
+
+    fn set<V>(&self, table: &str, key: impl AsRef<str>, value: V) -> crate::Result<()>
+    where V: AsRef<[u8]> {
+        let conn = self.conn.lock().unwrap();
+        let sql = format!("INSERT OR REPLACE INTO \"{}\" (k, v) VALUES (?1, ?2)", table);
+        conn.prepare_cached(&sql)?
+            .execute(params![key.as_ref().as_bytes(), value.as_ref()])?;
+        Ok(())
+    }
+
+    fn delete(&self, table: &str, key: impl AsRef<str>) -> crate::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        let sql = format!("DELETE FROM \"{}\" WHERE k = ?1", table);
+        conn.prepare_cached(&sql)?.execute(params![key.as_ref().as_bytes()])?;
+        Ok(())
+    }
+
+    // one transaction, which is also much faster than a commit per key
+    fn write(&self, batch: Batch) -> crate::Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        for op in batch.into_ops() {
+            match op {
+                BatchOp::Set { table, key, value } => {
+                    let sql = format!("INSERT OR REPLACE INTO \"{}\" (k, v) VALUES (?1, ?2)", table);
+                    tx.prepare_cached(&sql)?.execute(params![key.as_bytes(), value])?;
+                }
+                BatchOp::Delete { table, key } => {
+                    let sql = format!("DELETE FROM \"{}\" WHERE k = ?1", table);
+                    tx.prepare_cached(&sql)?.execute(params![key.as_bytes()])?;
+                }
+                BatchOp::DeletePrefix { table, prefix } => match prefix_upper_bound(prefix.as_bytes()) {
+                    Some(upper) => {
+                        let sql = format!("DELETE FROM \"{}\" WHERE k >= ?1 AND k < ?2", table);
+                        tx.prepare_cached(&sql)?.execute(params![prefix.as_bytes(), upper])?;
+                    }
+                    None => {
+                        let sql = format!("DELETE FROM \"{}\" WHERE k >= ?1", table);
+                        tx.prepare_cached(&sql)?.execute(params![prefix.as_bytes()])?;
+                    }
+                },
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn foreach<F>(&self, table: &str, key_prefix: impl AsRef<str>, limit: u32, mut callback: F)
+    where F: FnMut(&str, &[u8]) {
+        self.walk_prefix(table, key_prefix.as_ref(), limit, false, |k, v| {
+            callback(k, v);
+            true
+        })
+    }
+
+    fn foreach_rev<F>(&self, table: &str, key_prefix: impl AsRef<str>, limit: u32, mut callback: F)
+    where F: FnMut(&str, &[u8]) {
+        self.walk_prefix(table, key_prefix.as_ref(), limit, true, |k, v| {
+            callback(k, v);
+            true
+        })
+    }
+
+    fn foreach_range<F>(&self, table: &str, start: impl AsRef<str>, end: impl AsRef<str>, limit: u32, mut callback: F)
+    where F: FnMut(&str, &[u8]) {
+        let (start, end) = (start.as_ref(), end.as_ref());
+        if start < end {
+            self.walk(table, (start.as_bytes(), Some(end.as_bytes())), limit, false, |k, v| {
+                callback(k, v);
+                true
+            })
+        }
+    }
+
+    fn foreach_while<F>(&self, table: &str, key_prefix: impl AsRef<str>, callback: F)
+    where F: FnMut(&str, &[u8]) -> bool {
+        self.walk_prefix(table, key_prefix.as_ref(), 0, false, callback)
+    }
+}
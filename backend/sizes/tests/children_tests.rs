@@ -0,0 +1,116 @@
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use sizes::db::children::{list_children, ChildKind, OWN_FILES_NAME};
+use sizes::db::dirorder::{Direction, Orderby};
+use sizes::db::dirstat::{save_dir_stat, DirStat};
+use sizes::db::scanresult::save_dir_scan_result;
+use sizes::kvstore::memory::MemoryStore;
+use sizes::scan::DirScanResult;
+
+fn save(db: &MemoryStore, path: &str, blocks: u64, files: u64, sub_dirs: &[&str]) {
+    save_at(db, path, blocks, files, sub_dirs, sizes::unix());
+}
+
+fn save_at(db: &MemoryStore, path: &str, blocks: u64, files: u64, sub_dirs: &[&str], ts: u64) {
+    let mut stat = DirStat::new(Path::new(path));
+    stat.ts = ts;
+    stat.blocks = blocks;
+    stat.file_num = files;
+    stat.subdir_num = sub_dirs.len() as u64;
+    stat.sub_dirs = sub_dirs.iter().map(PathBuf::from).collect();
+    save_dir_stat(db, Path::new(path), &stat).unwrap();
+}
+
+#[test]
+fn test_list_children() {
+    let db = &MemoryStore::new();
+    save(db, "/data", 5, 2, &["a", "b", "c"]);
+    save(db, "/data/a", 10, 1, &["x"]);
+    save(db, "/data/a/x", 100, 4, &[]);
+    save(db, "/data/b", 50, 3, &[]);
+    // a sibling sharing the name prefix is not part of /data/a
+    save(db, "/data/ab", 1000, 1, &[]);
+
+    let page = list_children(db, Path::new("/data"), Orderby::Block, Direction::Desc, 0, u32::MAX).unwrap();
+    assert_eq!(page.total, Some(4));
+    assert!(!page.has_more);
+    let names: Vec<&str> = page.items.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, vec!["a", "b", OWN_FILES_NAME, "c"]);
+
+    let a = &page.items[0];
+    assert_eq!(a.path, PathBuf::from("/data/a"));
+    assert_eq!(a.kind, ChildKind::Dir);
+    assert_eq!((a.blocks, a.files, a.dirs), (110, 5, 1));
+    assert!(a.ts > 0);
+
+    let own = &page.items[2];
+    assert_eq!(own.kind, ChildKind::Files);
+    assert_eq!(own.path, PathBuf::from("/data"));
+    assert_eq!((own.blocks, own.files), (5, 2));
+
+    // listed by the parent but never scanned
+    let c = &page.items[3];
+    assert_eq!((c.blocks, c.ts), (0, 0));
+
+    let page = list_children(db, Path::new("/data"), Orderby::FileNum, Direction::Asc, 1, 2).unwrap();
+    let names: Vec<&str> = page.items.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, vec![OWN_FILES_NAME, "b"]);
+
+    assert!(list_children(db, Path::new("/nowhere"), Orderby::Block, Direction::Desc, 0, 10).is_err());
+}
+
+#[test]
+fn test_list_children_non_utf8() {
+    let db = &MemoryStore::new();
+    let (a, b) = (OsStr::from_bytes(b"\xff"), OsStr::from_bytes(b"\xfe"));
+    let mut stat = DirStat::new(Path::new("/data"));
+    stat.sub_dirs = vec![PathBuf::from(a), PathBuf::from(b)];
+    save_dir_stat(db, Path::new("/data"), &stat).unwrap();
+    let child = Path::new("/data").join(a);
+    let mut stat = DirStat::new(&child);
+    stat.blocks = 7;
+    save_dir_stat(db, &child, &stat).unwrap();
+
+    // both names print the same, each keeps its own path and totals
+    let page = list_children(db, Path::new("/data"), Orderby::Block, Direction::Desc, 0, 10).unwrap();
+    assert_eq!(page.items.len(), 3);
+    assert_eq!(page.items[0].path, child);
+    assert_eq!(page.items[0].blocks, 7);
+    let other = page.items.iter().find(|c| c.path == Path::new("/data").join(b)).unwrap();
+    assert_eq!(other.blocks, 0);
+    assert_eq!(other.name, page.items[0].name);
+}
+
+#[test]
+fn test_list_children_last_scan() {
+    let db = &MemoryStore::new();
+    save_at(db, "/top", 0, 0, &["data"], 100);
+    save_at(db, "/top/data", 1, 1, &["a"], 100);
+    save_at(db, "/top/data/a", 1, 1, &[], 300);
+    let scanned = |path: &str, finished_at: u64| {
+        let mut result = DirScanResult::new();
+        result.finished_at = finished_at;
+        save_dir_scan_result(db, Path::new(path), &result).unwrap();
+    };
+    let times = || {
+        let page = list_children(db, Path::new("/top/data"), Orderby::Block, Direction::Desc, 0, 10).unwrap();
+        let a = page.items.iter().find(|c| c.kind == ChildKind::Dir).unwrap().ts;
+        let own = page.items.iter().find(|c| c.kind == ChildKind::Files).unwrap().ts;
+        (a, own)
+    };
+    // the times the dirs were saved with when no scan found them unchanged since
+    assert_eq!(times(), (300, 100));
+
+    // a scan of an ancestor reaches every dir it still lists, saved again or not
+    scanned("/top", 900);
+    assert_eq!(times(), (900, 900));
+    scanned("/top/data/a", 1000);
+    assert_eq!(times(), (1000, 900));
+
+    // a parent that no longer lists the dir does not pass its scans on
+    save_at(db, "/top", 0, 0, &[], 950);
+    scanned("/top", 2000);
+    assert_eq!(times(), (1000, 100));
+}
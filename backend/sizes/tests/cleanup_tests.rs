@@ -0,0 +1,170 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use common::get_tokio_runtime;
+use sizes::cleanup::{self, format_deletion_date, AuditRecord, CleanupAction, CleanupTicket};
+use sizes::conf::{self, WatchDirectoryConfiguration};
+use sizes::db::dirstat::get_dir_stat;
+use sizes::db::scanresult::save_dir_scan_result;
+use sizes::db::{TABLE_AUDIT_LOG, TABLE_CONF};
+use sizes::kvstore::memory::MemoryStore;
+use sizes::kvstore::KvStore;
+use sizes::scan::{DirScanOverview, DirScanResult};
+use sizes::scandir::compute_dir_stats_recursive;
+use sizes::{home_dir, Error};
+
+mod common;
+
+fn build_tree(root: &Path) {
+    let _ = fs::remove_dir_all(root);
+    fs::create_dir_all(root.join("a/x")).unwrap();
+    fs::create_dir_all(root.join("a/y")).unwrap();
+    fs::create_dir_all(root.join("ab")).unwrap();
+    fs::write(root.join("a/x/f1"), vec![1u8; 8192]).unwrap();
+    fs::write(root.join("ab/f2"), vec![1u8; 8192]).unwrap();
+}
+
+#[test]
+fn test_cleanup_delete_dir() {
+    let root = PathBuf::from("/tmp/sizes-cleanup-delete");
+    build_tree(&root);
+    let db = &MemoryStore::new();
+
+    get_tokio_runtime().block_on(async {
+        let mut scan_result = DirScanResult::new();
+        compute_dir_stats_recursive(db, &root, &mut scan_result).await.unwrap();
+        save_dir_scan_result(db, &root, &scan_result).unwrap();
+    });
+    // only what is inside the scanned root
+    assert!(cleanup::prepare(db, &root, CleanupAction::Delete).is_err());
+
+    let target = root.join("a");
+    let ticket = cleanup::prepare(db, &target, CleanupAction::Delete).unwrap();
+    assert_eq!(ticket.overview.files, 1);
+    assert_eq!(ticket.overview.dirs, 2);
+    assert!(target.exists());
+
+    let record = cleanup::confirm(db, &ticket.token).unwrap();
+    assert!(record.error.is_none());
+    assert!(!target.exists());
+
+    // the subtree is gone from the db, the sibling with the same prefix is not
+    assert!(get_dir_stat(db, &target).unwrap().is_none());
+    assert!(get_dir_stat(db, &target.join("x")).unwrap().is_none());
+    assert!(get_dir_stat(db, &root.join("ab")).unwrap().is_some());
+
+    let parent = get_dir_stat(db, &root).unwrap().unwrap();
+    assert_eq!(parent.subdir_num, 1);
+    assert_eq!(parent.sub_dirs, vec![PathBuf::from("ab")]);
+
+    // a token can only be used once
+    assert!(cleanup::confirm(db, &ticket.token).is_err());
+
+    let audit = cleanup::list_audit(db, 10);
+    assert_eq!(audit.len(), 1);
+    assert_eq!(audit[0].path, target);
+}
+
+#[test]
+fn test_cleanup_trash_file() {
+    let root = PathBuf::from("/tmp/sizes-cleanup-trash");
+    build_tree(&root);
+    std::env::set_var("XDG_DATA_HOME", root.join("share"));
+    let db = &MemoryStore::new();
+    let watch = WatchDirectoryConfiguration {
+        refresh_interval: String::new(),
+        label: "trash".to_string(),
+        path: root.to_string_lossy().to_string(),
+    };
+    conf::add_watch(db, &watch).unwrap();
+
+    let target = root.join("ab/f2");
+    let ticket = cleanup::prepare(db, &target, CleanupAction::Trash).unwrap();
+    let record = cleanup::confirm(db, &ticket.token).unwrap();
+
+    let trashed_to = record.trashed_to.unwrap();
+    assert_eq!(trashed_to, root.join("share/Trash/files/f2"));
+    assert!(trashed_to.exists());
+    assert!(!target.exists());
+
+    let info = fs::read_to_string(root.join("share/Trash/info/f2.trashinfo")).unwrap();
+    assert!(info.starts_with("[Trash Info]\nPath=/tmp/sizes-cleanup-trash/ab/f2\n"));
+}
+
+#[test]
+fn test_cleanup_refuse_paths() {
+    let db = &MemoryStore::new();
+    let mut result = DirScanResult::new();
+    result.finished_at = 1;
+    save_dir_scan_result(db, Path::new("/"), &result).unwrap();
+
+    let home = home_dir();
+    let refused = [
+        PathBuf::from("relative/dir"),
+        PathBuf::from("/"),
+        PathBuf::from("/etc"),
+        PathBuf::from("/usr/lib"),
+        PathBuf::from("/tmp"),
+        PathBuf::from("/tmp/../etc/x"),
+        home.clone(),
+        home.parent().unwrap().to_path_buf(),
+    ];
+    for path in refused {
+        let res = cleanup::prepare(db, &path, CleanupAction::Delete);
+        assert!(matches!(res, Err(Error::InvalidPath(_))), "{} accepted", path.display());
+    }
+
+    // a path that was never scanned
+    let db = &MemoryStore::new();
+    let res = cleanup::prepare(db, Path::new("/tmp/sizes-cleanup-unscanned"), CleanupAction::Delete);
+    assert!(matches!(res, Err(Error::InvalidPath(_))));
+}
+
+#[test]
+fn test_cleanup_drop_expired_tickets() {
+    let root = PathBuf::from("/tmp/sizes-cleanup-expired");
+    build_tree(&root);
+    let db = &MemoryStore::new();
+    save_dir_scan_result(db, &root, &DirScanResult::new()).unwrap();
+    let expired = CleanupTicket {
+        token: "old".to_string(),
+        path: root.join("ab"),
+        action: CleanupAction::Delete,
+        overview: DirScanOverview::new(),
+        expires: 1,
+    };
+    db.set_json(TABLE_CONF, "cleanup,old", &expired).unwrap();
+
+    let ticket = cleanup::prepare(db, &root.join("ab"), CleanupAction::Delete).unwrap();
+    assert_eq!(ticket.token.len(), 64);
+    assert!(db.get_bytes(TABLE_CONF, "cleanup,old").is_none());
+    assert!(matches!(cleanup::confirm(db, "old"), Err(Error::NotFound(_))));
+    assert!(cleanup::find_ticket(db, &ticket.token).is_ok());
+}
+
+#[test]
+fn test_format_deletion_date() {
+    assert_eq!(format_deletion_date(0), "1970-01-01T00:00:00");
+    assert_eq!(format_deletion_date(951782400), "2000-02-29T00:00:00");
+    assert_eq!(format_deletion_date(1760875200), "2025-10-19T12:00:00");
+}
+
+#[test]
+fn test_list_audit_latest_first() {
+    let db = &MemoryStore::new();
+    for ts in [1_700_000_001, 1_700_000_003, 1_700_000_002] {
+        let record = AuditRecord {
+            ts,
+            token: format!("t{}", ts),
+            path: PathBuf::from("/data"),
+            action: CleanupAction::Delete,
+            overview: DirScanOverview::default(),
+            trashed_to: None,
+            error: None,
+        };
+        db.set_json(TABLE_AUDIT_LOG, format!("{},{}", record.ts, record.token), &record).unwrap();
+    }
+    let tss: Vec<u64> = cleanup::list_audit(db, 2).iter().map(|r| r.ts).collect();
+    assert_eq!(tss, vec![1_700_000_003, 1_700_000_002]);
+    assert_eq!(cleanup::list_audit(db, 0).len(), 3);
+}
@@ -0,0 +1,56 @@
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+
+use sizes::config::{config_file, read_config};
+use sizes::home_dir;
+
+#[test]
+fn test_read_config() {
+    let path = Path::new("/tmp/sizes-config-test.toml");
+    fs::write(
+        path,
+        r#"
+[db]
+path = "~/sizes/app.db"
+block_cache_mb = 128
+
+[server]
+address = "0.0.0.0"
+port = 9000
+
+[scan]
+concurrency = 0
+"#,
+    )
+    .unwrap();
+
+    let config = read_config(path).unwrap();
+    assert_eq!(config.file, Some(path.to_path_buf()));
+    assert_eq!(config.db.path, home_dir().join("sizes/app.db"));
+    assert_eq!(config.db.block_cache_mb, 128);
+    assert_eq!(config.db.write_buffer_mb, 32);
+    assert_eq!(config.server.address, Some(IpAddr::V4(Ipv4Addr::UNSPECIFIED)));
+    assert_eq!(config.server.port, Some(9000));
+    assert_eq!(config.server.static_dir, None);
+    assert_eq!(config.scan.concurrency, 1);
+    assert_eq!(config.retention.scan_results, 0);
+}
+
+#[test]
+fn test_read_config_missing_or_broken() {
+    let config = read_config(Path::new("/tmp/sizes-config-missing.toml")).unwrap();
+    assert!(config.file.is_none());
+    assert_eq!(config.scan.concurrency, 8);
+
+    let path = Path::new("/tmp/sizes-config-broken.toml");
+    fs::write(path, "[scan]\nconcurrency = \"many\"\n").unwrap();
+    assert!(read_config(path).is_err());
+}
+
+#[test]
+fn test_config_file_flag_first() {
+    let cli = PathBuf::from("/etc/sizes.toml");
+    assert_eq!(config_file(Some(&cli)), cli);
+    assert!(config_file(None).ends_with("config.toml"));
+}
@@ -2,15 +2,28 @@ use sizes::conf::{add_watch, list_watch, WatchDirectoryConfiguration};
 use sizes::kvstore::KvStore;
 use sizes::rocksdb::{RocksDBBuilder, StdColumnFamilyConfig};
 use std::collections::HashMap;
-use sizes::db::TABLE_CONF;
+use std::path::Path;
+use std::sync::Arc;
+use sizes::db::dirorder::{list_dirs, Direction, Orderby};
+use sizes::db::dirstat::{get_dir_stat, save_dir_stat, DirStat};
+use sizes::db::migrate::copy_store;
+use sizes::db::purge::{purge_subtree, remove_watch_and_data, PurgeStats};
+use sizes::db::scanresult::{get_dir_scan_result, save_dir_scan_result};
+use sizes::db::search::{search, SearchQuery};
+use sizes::db::schema::ensure_schema;
+use sizes::db::stats::db_stats;
+use sizes::db::{self, all_tables, Backend, DbRegistry, TABLE_CONF, TABLE_DIR_STAT};
+use sizes::kvstore::store::Store;
+use sizes::scan::DirScanResult;
+use sizes::kvstore::memory::MemoryStore;
 
 mod common;
 
 #[test]
 fn test_db_get_set_str() {
-    let db = RocksDBBuilder::new("/tmp/test.db")
+    let db = RocksDBBuilder::new("/tmp/test-db-str.db")
         .truncate(true)
-        .build();
+        .build().unwrap();
     db.set("default", "k1", "hello").unwrap();
     let v = db.get_string("default", "k1");
     assert_eq!(v, "hello");
@@ -18,10 +31,10 @@ fn test_db_get_set_str() {
 
 #[test]
 fn test_db_get_set_watch() {
-    let db = &RocksDBBuilder::new("/tmp/test.db")
+    let db = &RocksDBBuilder::new("/tmp/test-db-watch.db")
         .with_column_family(TABLE_CONF, StdColumnFamilyConfig::TINY)
         .truncate(true)
-        .build();
+        .build().unwrap();
 
     let mut watches = list_watch(db);
     let empty: Vec<WatchDirectoryConfiguration> = vec![];
@@ -56,6 +69,31 @@ fn test_db_get_set_watch() {
     assert_eq!(watches, vec![watch_conf_1, watch_conf_3]);
 }
 
+#[test]
+fn test_db_registry() {
+    let registry = DbRegistry::new();
+    let p1 = Path::new("/tmp/test-registry-1.db");
+    let p2 = Path::new("/tmp/test-registry-2.db");
+
+    let db1 = registry.open(p1, true).unwrap();
+    let db2 = registry.open(p2, true).unwrap();
+    assert!(Arc::ptr_eq(&db1, &registry.open(p1, false).unwrap()));
+    assert!(!Arc::ptr_eq(&db1, &db2));
+    assert_eq!(registry.list(), vec![p1.to_path_buf(), p2.to_path_buf()]);
+
+    db1.set(TABLE_CONF, "k1", "db1").unwrap();
+    assert_eq!(db2.get_string(TABLE_CONF, "k1"), "");
+
+    // an open database cannot be truncated under the handles given out
+    assert!(registry.open(p1, true).is_err());
+
+    registry.close(p1);
+    assert!(registry.get(p1).is_none());
+    drop(db1);
+    let db1 = registry.open(p1, false).unwrap();
+    assert_eq!(db1.get_string(TABLE_CONF, "k1"), "db1");
+}
+
 #[test]
 fn serialize_map() {
     let m = &vec![("k1".to_string(), 1), ("k2".to_string(), 2)]
@@ -63,3 +101,100 @@ fn serialize_map() {
         .collect::<HashMap<_, _>>();
     print!("m is {:?}", serde_json::to_value(m).unwrap());
 }
+
+#[test]
+fn test_copy_store() {
+    let src = MemoryStore::new();
+    src.set(TABLE_CONF, "watch,/a", "{}").unwrap();
+    src.set(TABLE_DIR_STAT, "/a", "{\"blocks\":1}").unwrap();
+    src.set(TABLE_DIR_STAT, "/a/b", "{\"blocks\":2}").unwrap();
+    let dst = MemoryStore::new();
+
+    let counts = copy_store(&src, &dst).unwrap();
+    assert!(counts.contains(&(TABLE_DIR_STAT.to_string(), 2)));
+    assert!(counts.contains(&(TABLE_CONF.to_string(), 1)));
+    assert_eq!(dst.get_string(TABLE_DIR_STAT, "/a/b"), "{\"blocks\":2}");
+
+    assert_eq!("sqlite".parse::<Backend>().unwrap(), Backend::Sqlite);
+    assert!("leveldb".parse::<Backend>().is_err());
+    assert_eq!(Backend::detect(Path::new("/tmp/no-such-sizes-db")), None);
+}
+
+fn save_tree(db: &impl KvStore, paths: &[&str]) {
+    for path in paths {
+        let mut stat = DirStat::new(Path::new(path));
+        stat.blocks = path.len() as u64;
+        save_dir_stat(db, Path::new(path), &stat).unwrap();
+        save_dir_scan_result(db, Path::new(path), &DirScanResult::new()).unwrap();
+    }
+}
+
+#[test]
+fn test_purge_subtree() {
+    let mut builder = RocksDBBuilder::new("/tmp/test-db-purge.db");
+    for table in all_tables() {
+        builder.with_column_family(table, StdColumnFamilyConfig::DEFAULT);
+    }
+    let db = &builder.truncate(true).build().unwrap();
+    save_tree(db, &["/data", "/data/a", "/data/a/x", "/data/ab", "/other"]);
+
+    let stats = purge_subtree(db, Path::new("/data/a")).unwrap();
+    assert_eq!(stats, PurgeStats { dirs: 2, scan_results: 2 });
+    assert!(get_dir_stat(db, Path::new("/data/a/x")).unwrap().is_none());
+    // a sibling sharing the name prefix stays
+    assert!(get_dir_stat(db, Path::new("/data/ab")).unwrap().is_some());
+    assert_eq!(get_dir_scan_result(db, Path::new("/data/ab"), 0).unwrap().len(), 1);
+
+    // the indexes forget the purged dirs too
+    let page = list_dirs(db, Orderby::Block, Direction::Desc, 0, 0, 10).unwrap();
+    assert_eq!(page.items.len(), 3);
+    let query = SearchQuery { pattern: "x".to_string(), ..Default::default() };
+    assert!(search(db, &query).unwrap().items.is_empty());
+
+    let stats = purge_subtree(db, Path::new("/")).unwrap();
+    assert_eq!(stats, PurgeStats { dirs: 3, scan_results: 3 });
+    assert_eq!(list_dirs(db, Orderby::Block, Direction::Desc, 0, 0, 10).unwrap().items.len(), 0);
+}
+
+#[test]
+fn test_remove_watch_and_data() {
+    let db = &MemoryStore::new();
+    let watch = |path: &str| WatchDirectoryConfiguration {
+        refresh_interval: "1 Day".to_string(),
+        label: String::new(),
+        path: path.to_string(),
+    };
+    save_tree(db, &["/data", "/data/a", "/srv"]);
+    add_watch(db, &watch("/data")).unwrap();
+    add_watch(db, &watch("/data/a")).unwrap();
+    add_watch(db, &watch("/srv")).unwrap();
+
+    // /data/a is still watched, so /data keeps its data
+    assert_eq!(remove_watch_and_data(db, &watch("/data")).unwrap(), PurgeStats::default());
+    assert!(get_dir_stat(db, Path::new("/data")).unwrap().is_some());
+
+    let stats = remove_watch_and_data(db, &watch("/srv")).unwrap();
+    assert_eq!(stats.dirs, 1);
+    assert!(get_dir_stat(db, Path::new("/srv")).unwrap().is_none());
+    assert_eq!(list_watch(db), vec![watch("/data/a")]);
+}
+
+#[test]
+fn test_db_stats() {
+    let db = Store::Memory(MemoryStore::new());
+    ensure_schema(&db).unwrap();
+    save_tree(&db, &["/data", "/data/a"]);
+    let stats = db_stats(&db).unwrap();
+    assert_eq!(stats.backend, "memory");
+    assert_eq!(stats.tables.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(), all_tables());
+    let dirs = stats.tables.iter().find(|t| t.name == TABLE_DIR_STAT).unwrap();
+    assert_eq!(dirs.keys, 2);
+    assert!(dirs.live_data_size > 0);
+    assert!(stats.schema_version > 0);
+
+    let path = Path::new("/tmp/test-db-stats.db");
+    let db = db::open_store(Backend::RocksDB, path, true).unwrap();
+    let stats = db_stats(&db).unwrap();
+    assert_eq!((stats.backend.as_str(), stats.path.as_path()), ("rocksdb", path));
+    assert_eq!(stats.tables.len(), all_tables().len());
+}
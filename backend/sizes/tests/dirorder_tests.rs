@@ -0,0 +1,130 @@
+use std::path::{Path, PathBuf};
+
+use sizes::db::dirorder::{ensure_index, list_dirs, Direction, Orderby};
+use sizes::db::dirstat::{delete_dir_stat, get_dir_stat, save_dir_stat, DirStat, DirStatBatch};
+use sizes::db::purge::purge_subtree;
+use sizes::db::{TABLE_DIR_ORDER, TABLE_DIR_STAT};
+use sizes::kvstore::memory::MemoryStore;
+use sizes::kvstore::KvStore;
+
+fn stat(path: &str, blocks: u64, files: u64) -> DirStat {
+    let mut stat = DirStat::new(Path::new(path));
+    stat.blocks = blocks;
+    stat.file_num = files;
+    stat
+}
+
+fn paths(stats: &[DirStat]) -> Vec<PathBuf> {
+    stats.iter().map(|s| s.path.clone()).collect()
+}
+
+#[test]
+fn test_list_dirs_sorted_and_paged() {
+    let db = &MemoryStore::new();
+    for s in [stat("/a", 30, 1), stat("/b", 10, 5), stat("/c", 20, 3), stat("/d", 5, 9)] {
+        save_dir_stat(db, &s.path.clone(), &s).unwrap();
+    }
+
+    let page = list_dirs(db, Orderby::Block, Direction::Desc, 0, 0, 2).unwrap();
+    assert_eq!(page.total, Some(4));
+    assert!(page.has_more);
+    assert_eq!(paths(&page.items), vec![PathBuf::from("/a"), PathBuf::from("/c")]);
+
+    let page = list_dirs(db, Orderby::Block, Direction::Desc, 0, 2, 2).unwrap();
+    assert!(!page.has_more);
+    assert_eq!(paths(&page.items), vec![PathBuf::from("/b"), PathBuf::from("/d")]);
+
+    let page = list_dirs(db, Orderby::FileNum, Direction::Asc, 3, 0, 10).unwrap();
+    assert_eq!(page.total, Some(3));
+    assert!(!page.has_more);
+    assert_eq!(paths(&page.items), vec![PathBuf::from("/c"), PathBuf::from("/b"), PathBuf::from("/d")]);
+
+    // the walk stops before the dirs below `min`
+    let page = list_dirs(db, Orderby::Block, Direction::Desc, 10, 1, 1).unwrap();
+    assert_eq!(page.total, Some(3));
+    assert!(page.has_more);
+    assert_eq!(paths(&page.items), vec![PathBuf::from("/c")]);
+    let page = list_dirs(db, Orderby::Block, Direction::Desc, 10, 2, 1).unwrap();
+    assert!(!page.has_more);
+    assert_eq!(paths(&page.items), vec![PathBuf::from("/b")]);
+
+    // updating a stat moves it in the index instead of adding a second entry
+    save_dir_stat(db, Path::new("/d"), &stat("/d", 100, 9)).unwrap();
+    let page = list_dirs(db, Orderby::Block, Direction::Desc, 0, 0, 10).unwrap();
+    assert_eq!(page.total, Some(4));
+    assert_eq!(page.items[0].path, PathBuf::from("/d"));
+
+    delete_dir_stat(db, Path::new("/d")).unwrap();
+    let page = list_dirs(db, Orderby::Block, Direction::Desc, 0, 0, 10).unwrap();
+    assert_eq!(page.total, Some(3));
+    assert_eq!(page.items[0].path, PathBuf::from("/a"));
+
+    save_dir_stat(db, Path::new("/a/x"), &stat("/a/x", 1, 1)).unwrap();
+    assert_eq!(purge_subtree(db, Path::new("/a")).unwrap().dirs, 2);
+    let page = list_dirs(db, Orderby::Block, Direction::Desc, 0, 0, 10).unwrap();
+    assert_eq!(page.total, Some(2));
+}
+
+#[test]
+fn test_dir_stat_batch() {
+    let db = &MemoryStore::new();
+    save_dir_stat(db, Path::new("/a"), &stat("/a", 10, 1)).unwrap();
+
+    let mut batch = DirStatBatch::new(db);
+    batch.save(Path::new("/b"), &stat("/b", 20, 1)).unwrap();
+    // the second save of a path replaces the index entries of the first one
+    batch.save(Path::new("/b"), &stat("/b", 40, 1)).unwrap();
+    batch.delete(Path::new("/a"));
+    batch.delete(Path::new("/never"));
+    assert_eq!(batch.len(), 2);
+    // nothing is visible before the batch is written
+    assert!(get_dir_stat(db, Path::new("/b")).unwrap().is_none());
+    batch.write().unwrap();
+
+    let page = list_dirs(db, Orderby::Block, Direction::Desc, 0, 0, 10).unwrap();
+    assert_eq!(page.total, Some(1));
+    assert_eq!(page.items[0].blocks, 40);
+    // one entry per field, and the count
+    let mut index = Vec::new();
+    db.foreach(TABLE_DIR_ORDER, "", 0, |k, _| index.push(k.to_string()));
+    assert_eq!(index.len(), Orderby::ALL.len() + 1);
+    assert!(get_dir_stat(db, Path::new("/a")).unwrap().is_none());
+}
+
+#[test]
+fn test_ensure_index_on_old_db() {
+    let db = &MemoryStore::new();
+    // written the way stats were saved before there was an index
+    db.set_json(TABLE_DIR_STAT, "/x", &stat("/x", 1, 0)).unwrap();
+    db.set_json(TABLE_DIR_STAT, "/y", &stat("/y", 2, 0)).unwrap();
+    assert_eq!(list_dirs(db, Orderby::Block, Direction::Desc, 0, 0, 10).unwrap().items.len(), 0);
+
+    ensure_index(db).unwrap();
+    let page = list_dirs(db, Orderby::Block, Direction::Desc, 0, 0, 10).unwrap();
+    assert_eq!(page.total, Some(2));
+    assert_eq!(paths(&page.items), vec![PathBuf::from("/y"), PathBuf::from("/x")]);
+}
+
+#[test]
+fn test_foreach_rev() {
+    let db = &MemoryStore::new();
+    for k in ["a,1", "b,1", "b,2", "b,3", "c,1"] {
+        db.set(TABLE_DIR_ORDER, k, "").unwrap();
+    }
+    let mut keys = Vec::new();
+    db.foreach_rev(TABLE_DIR_ORDER, "b,", 0, |k, _| keys.push(k.to_string()));
+    assert_eq!(keys, vec!["b,3", "b,2", "b,1"]);
+
+    keys.clear();
+    db.foreach_rev(TABLE_DIR_ORDER, "", 2, |k, _| keys.push(k.to_string()));
+    assert_eq!(keys, vec!["c,1", "b,3"]);
+}
+
+#[test]
+fn test_parse_order() {
+    assert_eq!("files".parse::<Orderby>().unwrap(), Orderby::FileNum);
+    assert_eq!("SubDirNum".parse::<Orderby>().unwrap(), Orderby::SubDirNum);
+    assert_eq!("ASC".parse::<Direction>().unwrap(), Direction::Asc);
+    assert!("size".parse::<Orderby>().is_err());
+    assert!("up".parse::<Direction>().is_err());
+}
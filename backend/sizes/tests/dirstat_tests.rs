@@ -0,0 +1,97 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use sizes::db::dirorder::{ensure_index, list_dirs, Direction, Orderby};
+use sizes::db::dirstat::{foreach_subtree, get_dir_stat, is_outdated, save_dir_stat, DirStat};
+use sizes::db::key::path_key;
+use sizes::db::migrate::copy_store;
+use sizes::db::TABLE_DIR_STAT;
+use sizes::kvstore::memory::MemoryStore;
+use sizes::kvstore::KvStore;
+use sizes::scan::scan_one_dir;
+
+fn sample(path: &Path) -> DirStat {
+    let mut stat = DirStat::new(path);
+    stat.mtime = -3;
+    stat.subdir_num = 3;
+    stat.file_num = 1_000_000;
+    stat.blocks = u64::MAX;
+    stat.sub_dirs = vec![
+        PathBuf::from("src"),
+        PathBuf::from("caf\u{e9}"),
+        PathBuf::from(OsStr::from_bytes(b"\xff\xfe-latin1")),
+    ];
+    stat
+}
+
+fn stored(db: &MemoryStore, path: &Path) -> Vec<u8> {
+    db.get_bytes(TABLE_DIR_STAT, path_key(path)).unwrap().as_ref().to_vec()
+}
+
+#[test]
+fn test_encode_roundtrip() {
+    let path = Path::new("/data/a");
+    let stat = sample(path);
+    let bytes = stat.encode();
+    let back = DirStat::decode(path, &bytes).unwrap();
+    assert_eq!((back.ts, back.mtime, back.subdir_num, back.file_num, back.blocks), (stat.ts, -3, 3, 1_000_000, u64::MAX));
+    assert_eq!(back.sub_dirs, stat.sub_dirs);
+    assert_eq!(back.path, PathBuf::from(path));
+    assert!(!is_outdated(&bytes));
+
+    let json = serde_json::to_vec(&DirStat::new(path)).unwrap();
+    assert!(DirStat::new(path).encode().len() * 3 < json.len(), "{} vs {}", DirStat::new(path).encode().len(), json.len());
+
+    // cut short or of a version this one does not know
+    assert!(DirStat::decode(path, &bytes[..bytes.len() - 1]).is_err());
+    assert!(DirStat::decode(path, &[9, 1, 2]).is_err());
+    assert!(DirStat::decode(path, &[]).is_err());
+}
+
+#[test]
+fn test_read_json_stats() {
+    let db = &MemoryStore::new();
+    let path = Path::new("/data/a");
+    let mut stat = DirStat::new(path);
+    stat.blocks = 8;
+    stat.sub_dirs = vec![PathBuf::from("x")];
+    db.set_json(TABLE_DIR_STAT, path_key(path), &stat).unwrap();
+    ensure_index(db).unwrap();
+    save_dir_stat(db, Path::new("/data/a/x"), &sample(Path::new("/data/a/x"))).unwrap();
+
+    assert_eq!(get_dir_stat(db, path).unwrap().unwrap().blocks, 8);
+    assert!(is_outdated(&stored(db, path)));
+    let mut seen = Vec::new();
+    foreach_subtree(db, path, |path, stat| seen.push((path.to_path_buf(), stat.sub_dirs.len())));
+    assert_eq!(seen, vec![(PathBuf::from("/data/a"), 1), (PathBuf::from("/data/a/x"), 3)]);
+    let page = list_dirs(db, Orderby::Block, Direction::Asc, 0, 0, 10).unwrap();
+    assert_eq!(page.items.iter().map(|s| s.path.clone()).collect::<Vec<_>>(), vec![PathBuf::from("/data/a"), PathBuf::from("/data/a/x")]);
+}
+
+#[test]
+fn test_scan_upgrades_unchanged_json_stats() {
+    let dir = std::env::temp_dir().join("sizes-test-dirstat-upgrade");
+    fs::create_dir_all(dir.join("sub")).unwrap();
+    let db = &MemoryStore::new();
+    let (stat, is_cached) = scan_one_dir(db, &dir);
+    assert!(!is_cached);
+    db.set_json(TABLE_DIR_STAT, path_key(&dir), &stat).unwrap();
+
+    // the dir did not change, the stat is saved in the binary encoding anyway
+    let (_, is_cached) = scan_one_dir(db, &dir);
+    assert!(is_cached);
+    assert!(!is_outdated(&stored(db, &dir)));
+    assert_eq!(get_dir_stat(db, &dir).unwrap().unwrap().sub_dirs, vec![PathBuf::from("sub")]);
+}
+
+#[test]
+fn test_copy_keeps_binary_values() {
+    let (src, dst) = (&MemoryStore::new(), &MemoryStore::new());
+    let path = Path::new("/data/a");
+    save_dir_stat(src, path, &sample(path)).unwrap();
+    copy_store(src, dst).unwrap();
+    assert_eq!(stored(src, path), stored(dst, path));
+    assert_eq!(get_dir_stat(dst, path).unwrap().unwrap().sub_dirs, sample(path).sub_dirs);
+}
@@ -0,0 +1,80 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use common::get_tokio_runtime;
+use sizes::export::{self, ExportFormat};
+use sizes::kvstore::memory::MemoryStore;
+use sizes::scan::DirScanResult;
+use sizes::scandir::compute_dir_stats_recursive;
+
+mod common;
+
+fn scanned_tree(root: &Path) -> MemoryStore {
+    let _ = fs::remove_dir_all(root);
+    fs::create_dir_all(root.join("a/deep")).unwrap();
+    fs::create_dir_all(root.join("b,c")).unwrap();
+    fs::write(root.join("a/deep/f1"), vec![1u8; 8192]).unwrap();
+    fs::write(root.join("b,c/f2"), vec![1u8; 4096]).unwrap();
+
+    let db = MemoryStore::new();
+    get_tokio_runtime().block_on(async {
+        let mut scan_result = DirScanResult::new();
+        compute_dir_stats_recursive(&db, root, &mut scan_result).await.unwrap();
+    });
+    db
+}
+
+#[test]
+fn test_export_ncdu() {
+    let root = PathBuf::from("/tmp/sizes-export-ncdu");
+    let db = &scanned_tree(&root);
+
+    let mut out = Vec::new();
+    export::export(db, &root, ExportFormat::Ncdu, None, &mut out).unwrap();
+    let value: serde_json::Value = serde_json::from_slice(&out).unwrap();
+
+    assert_eq!(value[0], 1);
+    assert_eq!(value[2]["progname"], "sizes");
+    let tree = &value[3];
+    assert_eq!(tree[0]["name"], "/tmp/sizes-export-ncdu");
+    // the root entry followed by its two sub directories
+    assert_eq!(tree.as_array().unwrap().len(), 3);
+}
+
+#[test]
+fn test_export_csv() {
+    let root = PathBuf::from("/tmp/sizes-export-csv");
+    let db = &scanned_tree(&root);
+
+    let mut out = Vec::new();
+    export::export(db, &root, ExportFormat::Csv, None, &mut out).unwrap();
+    let text = String::from_utf8(out).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+
+    assert_eq!(lines[0], "path,blocks,files,dirs,mtime");
+    assert_eq!(lines.len(), 5);
+    assert!(lines[1].starts_with("/tmp/sizes-export-csv,0,0,2,"));
+    assert!(lines.iter().any(|l| l.starts_with("\"/tmp/sizes-export-csv/b,c\",")));
+}
+
+#[test]
+fn test_export_json_tree_depth() {
+    let root = PathBuf::from("/tmp/sizes-export-json");
+    let db = &scanned_tree(&root);
+
+    let tree = export::build_tree(db, &root, Some(1)).unwrap().unwrap();
+    assert_eq!(tree.files, 2);
+    assert_eq!(tree.dirs, 3);
+    assert_eq!(tree.children.len(), 2);
+
+    let a = tree.children.iter().find(|c| c.name == "a").unwrap();
+    assert!(!a.expanded);
+    assert!(a.children.is_empty());
+    assert_eq!(a.files, 1);
+}
+
+#[test]
+fn test_export_format_from_str() {
+    assert_eq!("CSV".parse::<ExportFormat>().unwrap(), ExportFormat::Csv);
+    assert!("xml".parse::<ExportFormat>().is_err());
+}
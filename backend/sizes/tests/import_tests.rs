@@ -0,0 +1,99 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use common::get_tokio_runtime;
+use sizes::db::dirstat::{get_dir_stat, get_subtree_overview};
+use sizes::db::scanresult::get_last_dir_scan_result;
+use sizes::export::{self, ExportFormat};
+use sizes::import::{self, namespaced_path, ImportSource};
+use sizes::kvstore::memory::MemoryStore;
+use sizes::scan::DirScanResult;
+use sizes::scandir::compute_dir_stats_recursive;
+
+mod common;
+
+#[test]
+fn test_import_du() {
+    let db = &MemoryStore::new();
+    let du = "8\t/srv/data/a/f1\n4\t/srv/data/a/f2\n16\t/srv/data/a\n4\t/srv/data/b/c\n8\t/srv/data/b\n28\t/srv/data\n";
+
+    let record = import::import(db, "server1", ImportSource::Du, du.as_bytes()).unwrap();
+    assert_eq!(record.root, PathBuf::from("server1:/srv/data"));
+    assert_eq!(record.original_root, PathBuf::from("/srv/data"));
+    assert_eq!(record.overview.blocks, 56);
+    assert_eq!(record.overview.files, 3);
+    assert_eq!(record.overview.dirs, 2);
+
+    let root = get_dir_stat(db, &record.root).unwrap().unwrap();
+    assert_eq!(root.subdir_num, 2);
+    assert_eq!(root.blocks, 8);
+    let a = get_dir_stat(db, Path::new("server1:/srv/data/a")).unwrap().unwrap();
+    assert_eq!(a.file_num, 2);
+    assert_eq!(a.blocks, 32);
+
+    assert_eq!(get_subtree_overview(db, &record.root).blocks, 56);
+    assert!(get_last_dir_scan_result(db, &record.root).is_some());
+    assert_eq!(import::list_imports(db).len(), 1);
+
+    // importing again replaces the previous tree
+    let du = "4\t/srv/data/a\n8\t/srv/data\n";
+    let record = import::import(db, "server1", ImportSource::Du, du.as_bytes()).unwrap();
+    assert_eq!(record.overview.blocks, 16);
+    assert!(get_dir_stat(db, Path::new("server1:/srv/data/b")).unwrap().is_none());
+    assert_eq!(import::list_imports(db).len(), 1);
+}
+
+#[test]
+fn test_import_du_rejects_garbage() {
+    let db = &MemoryStore::new();
+    assert!(import::import(db, "server1", ImportSource::Du, "no tab here\n".as_bytes()).is_err());
+    assert!(import::import(db, "a/b", ImportSource::Du, "4\t/x\n".as_bytes()).is_err());
+    assert!(import::import(db, "server1", ImportSource::Ncdu, "{}".as_bytes()).is_err());
+}
+
+#[test]
+fn test_import_ncdu_roundtrip() {
+    let root = PathBuf::from("/tmp/sizes-import-ncdu");
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("a/b")).unwrap();
+    fs::write(root.join("a/f1"), vec![1u8; 8192]).unwrap();
+    fs::write(root.join("a/b/f2"), vec![1u8; 4096]).unwrap();
+
+    let db = &MemoryStore::new();
+    get_tokio_runtime().block_on(async {
+        let mut scan_result = DirScanResult::new();
+        compute_dir_stats_recursive(db, &root, &mut scan_result).await.unwrap();
+    });
+
+    let mut out = Vec::new();
+    export::export(db, &root, ExportFormat::Ncdu, None, &mut out).unwrap();
+    let record = import::import(db, "laptop", ImportSource::Ncdu, out.as_slice()).unwrap();
+
+    assert_eq!(record.root, namespaced_path("laptop", &root));
+    let local = get_subtree_overview(db, &root);
+    assert_eq!(record.overview.blocks, local.blocks);
+    assert_eq!(record.overview.dirs, local.dirs);
+    let b = get_dir_stat(db, &namespaced_path("laptop", &root.join("a/b"))).unwrap().unwrap();
+    assert_eq!(b.blocks, get_dir_stat(db, &root.join("a/b")).unwrap().unwrap().blocks);
+}
+
+fn nested_ncdu(depth: usize) -> String {
+    let mut out = String::from(r#"[1,2,{},[{"name":"/deep"}"#);
+    for _ in 0..depth {
+        out.push_str(r#",[{"name":"d"},{"name":"f","dsize":512}"#);
+    }
+    out.push_str(&"]".repeat(depth + 2));
+    out
+}
+
+#[test]
+fn test_import_deep_ncdu() {
+    let db = &MemoryStore::new();
+    let record = import::import(db, "deep", ImportSource::Ncdu, nested_ncdu(1000).as_bytes()).unwrap();
+    assert_eq!(record.overview.dirs, 1000);
+    assert_eq!(record.overview.files, 1000);
+    let deepest = Path::new("/deep").join("d/".repeat(1000).trim_end_matches('/'));
+    assert!(get_dir_stat(db, &namespaced_path("deep", &deepest)).unwrap().is_some());
+
+    assert!(import::import(db, "deep", ImportSource::Ncdu, nested_ncdu(5000).as_bytes()).is_err());
+}
@@ -0,0 +1,132 @@
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use sizes::db::dirorder::{ensure_index, list_dirs, Direction, Orderby};
+use sizes::db::dirstat::{foreach_subtree, get_dir_stat, get_dir_stat_recursive, save_dir_stat, DirStat};
+use sizes::db::key::{key_path, path_key, scan_result_prefix, subtree_prefix};
+use sizes::db::scanresult::{get_dir_scan_result, get_dir_scan_result_at, save_dir_scan_result};
+use sizes::db::schema::ensure_schema;
+use sizes::db::search::{ensure_name_index, search, SearchQuery};
+use sizes::db::{TABLE_DIR_SCAN_RESULT, TABLE_DIR_STAT};
+use sizes::kvstore::memory::MemoryStore;
+use sizes::kvstore::KvStore;
+use sizes::scan::DirScanResult;
+
+fn save(db: &MemoryStore, path: &Path, blocks: u64) {
+    let mut stat = DirStat::new(path);
+    stat.blocks = blocks;
+    save_dir_stat(db, path, &stat).unwrap();
+}
+
+#[test]
+fn test_key_roundtrip() {
+    let paths = [
+        PathBuf::from("/"),
+        PathBuf::from("/data/a"),
+        PathBuf::from("/data/a,b"),
+        PathBuf::from("host:/data"),
+        PathBuf::from("/caf\u{e9}/\u{1}\u{2}"),
+        PathBuf::from(OsStr::from_bytes(b"/data/\xff\xfe-latin1")),
+    ];
+    for path in &paths {
+        let key = path_key(path);
+        assert!(!key.contains('/'), "{:?}", key);
+        assert_eq!(&key_path(&key), path);
+    }
+    // not utf-8 and lossy the same, still two keys
+    assert_ne!(path_key(Path::new(OsStr::from_bytes(b"/\xff"))), path_key(Path::new(OsStr::from_bytes(b"/\xfe"))));
+}
+
+#[test]
+fn test_descendants_sort_after_their_dir() {
+    let mut keys: Vec<String> = ["/data/a-old", "/data/a/x", "/data/ab", "/data/a", "/data/a/x/y", "/data"]
+        .iter()
+        .map(|p| path_key(Path::new(p)))
+        .collect();
+    keys.sort();
+    let paths: Vec<PathBuf> = keys.iter().map(|k| key_path(k)).collect();
+    let expected: Vec<PathBuf> = ["/data", "/data/a", "/data/a/x", "/data/a/x/y", "/data/a-old", "/data/ab"]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+    assert_eq!(paths, expected);
+
+    let prefix = subtree_prefix(Path::new("/data/a"));
+    assert!(path_key(Path::new("/data/a/x")).starts_with(&prefix));
+    assert!(!path_key(Path::new("/data/a-old")).starts_with(&prefix));
+    assert!(path_key(Path::new("/data")).starts_with(&subtree_prefix(Path::new("/"))));
+}
+
+#[test]
+fn test_siblings_are_not_descendants() {
+    let db = &MemoryStore::new();
+    save(db, Path::new("/data/a"), 1);
+    save(db, Path::new("/data/a/x"), 2);
+    save(db, Path::new("/data/ab"), 100);
+    save(db, Path::new("/data/a-old"), 1000);
+    save(db, Path::new("/data/a,b"), 10000);
+    for path in ["/data/a", "/data/ab", "/data/a,b"] {
+        save_dir_scan_result(db, Path::new(path), &DirScanResult::new()).unwrap();
+    }
+
+    assert_eq!(get_dir_stat_recursive(db, Path::new("/data/a")).unwrap().blocks, 3);
+    assert_eq!(get_dir_scan_result(db, Path::new("/data/a"), 0).unwrap().len(), 1);
+    let mut seen = Vec::new();
+    foreach_subtree(db, Path::new("/"), |path, _| seen.push(path.to_path_buf()));
+    assert_eq!(seen.len(), 5);
+}
+
+#[test]
+fn test_migrate_plain_path_keys() {
+    let db = &MemoryStore::new();
+    let odd = PathBuf::from("/data/a,b");
+    for (path, blocks) in [("/data", 1), ("/data/a", 10), (odd.to_str().unwrap(), 5)] {
+        let mut stat = DirStat::new(Path::new(path));
+        stat.blocks = blocks;
+        db.set_json(TABLE_DIR_STAT, path, &stat).unwrap();
+    }
+    db.set_json(TABLE_DIR_SCAN_RESULT, "/data/a,b,1700000000", &DirScanResult::new()).unwrap();
+    db.set(TABLE_DIR_SCAN_RESULT, "/data,1700000000", "{}").unwrap();
+
+    ensure_schema(db).unwrap();
+    ensure_index(db).unwrap();
+    ensure_name_index(db).unwrap();
+
+    assert_eq!(get_dir_stat(db, Path::new("/data/a")).unwrap().unwrap().blocks, 10);
+    assert_eq!(get_dir_scan_result(db, &odd, 0).unwrap().len(), 1);
+    let page = list_dirs(db, Orderby::Block, Direction::Desc, 0, 0, 10).unwrap();
+    assert_eq!(page.items.iter().map(|s| s.blocks).collect::<Vec<_>>(), vec![10, 5, 1]);
+    let query = SearchQuery { pattern: "a,b".to_string(), ..Default::default() };
+    assert_eq!(search(db, &query).unwrap().items.len(), 1);
+
+    let mut plain = 0;
+    db.foreach(TABLE_DIR_STAT, "/", 0, |_, _| plain += 1);
+    assert_eq!(plain, 0);
+    // done once, a second call leaves new data alone
+    save(db, Path::new("/data/b"), 7);
+    ensure_schema(db).unwrap();
+    assert_eq!(get_dir_stat(db, Path::new("/data/b")).unwrap().unwrap().blocks, 7);
+}
+
+#[test]
+fn test_migrate_oldest_first_results() {
+    let db = &MemoryStore::new();
+    let path = Path::new("/data");
+    for (ts, spent) in [(1_700_000_000u64, 3u64), (1_700_000_100, 4)] {
+        let mut result = DirScanResult::new();
+        result.spent = spent;
+        db.set_json(TABLE_DIR_SCAN_RESULT, format!("{}{}", scan_result_prefix(path), ts), &result).unwrap();
+    }
+    save(db, path, 1);
+
+    ensure_schema(db).unwrap();
+    let results = get_dir_scan_result(db, path, 0).unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].finished_at, 1_700_000_100);
+    assert_eq!(results[0].started_at, 1_700_000_096);
+    assert_eq!(results[0].root, PathBuf::from("/data"));
+    assert_eq!(get_dir_scan_result_at(db, path, 1_700_000_099).unwrap().finished_at, 1_700_000_000);
+    // the stats were keyed the same in both
+    assert_eq!(get_dir_stat(db, path).unwrap().unwrap().blocks, 1);
+}
@@ -0,0 +1,118 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use common::get_tokio_runtime;
+use sizes::cmd::{Command, ProgressStatus, TaskManager};
+use sizes::db::dirorder::{list_dirs, Direction, Orderby};
+use sizes::db::dirstat::{get_dir_stat, save_dir_stat, DirStat};
+use sizes::db::maintenance::{backup, check_table, compact, list_backups, restore};
+use sizes::db::purge::purge_subtree;
+use sizes::db::{open_store, Backend, TABLE_DIR_STAT};
+use sizes::kvstore::memory::MemoryStore;
+use sizes::kvstore::store::Store;
+use sizes::Error;
+
+mod common;
+
+fn save(db: &Store, path: &str, blocks: u64) {
+    let mut stat = DirStat::new(Path::new(path));
+    stat.blocks = blocks;
+    save_dir_stat(db, Path::new(path), &stat).unwrap();
+}
+
+fn fresh(path: &str) -> PathBuf {
+    let path = PathBuf::from(path);
+    let _ = fs::remove_dir_all(&path);
+    path
+}
+
+#[test]
+fn test_backup_and_restore() {
+    let db = &open_store(Backend::RocksDB, Path::new("/tmp/test-maintenance.db"), true).unwrap();
+    save(db, "/data", 1);
+    save(db, "/data/a", 10);
+
+    let target = fresh("/tmp/test-maintenance-backup-1");
+    let record = backup(db, &target).unwrap();
+    assert_eq!(record.backend, Backend::RocksDB);
+    assert!(matches!(backup(db, &target), Err(Error::InvalidPath(_))));
+    assert!(matches!(backup(db, Path::new("relative")), Err(Error::InvalidPath(_))));
+
+    purge_subtree(db, Path::new("/data/a")).unwrap();
+    save(db, "/data/b", 5);
+    compact(db, Some(TABLE_DIR_STAT)).unwrap();
+    assert!(get_dir_stat(db, Path::new("/data/a")).unwrap().is_none());
+
+    let files = |dir: &Path| {
+        let mut names: Vec<_> = fs::read_dir(dir).unwrap().map(|e| e.unwrap().file_name()).collect();
+        names.sort();
+        names
+    };
+    let taken = files(&target);
+    let counts = restore(db, &target).unwrap();
+    // the backup is read through a copy and left as it was taken
+    assert_eq!(files(&target), taken);
+    assert_eq!(counts.iter().find(|(t, _)| t == TABLE_DIR_STAT).unwrap().1, 2);
+    assert_eq!(get_dir_stat(db, Path::new("/data/a")).unwrap().unwrap().blocks, 10);
+    assert!(get_dir_stat(db, Path::new("/data/b")).unwrap().is_none());
+    let page = list_dirs(db, Orderby::Block, Direction::Desc, 0, 0, 10).unwrap();
+    assert_eq!(page.items.iter().map(|s| s.blocks).collect::<Vec<_>>(), vec![10, 1]);
+
+    // the backup was taken before it was recorded, restoring it keeps the record
+    let backups = list_backups(db);
+    assert_eq!(backups.len(), 1);
+    assert_eq!(backups[0].path, target);
+    assert!(backups[0].exists);
+
+    assert!(matches!(restore(db, Path::new("/tmp/test-maintenance-none")), Err(Error::NotFound(_))));
+    // the data saved to put back on failure is gone once the restore is done
+    assert!(!Path::new("/tmp/test-maintenance.db.rollback").exists());
+    assert!(!std::env::temp_dir().join(format!("sizes-restore-{}", std::process::id())).exists());
+
+    // a store in memory keeps its copy in memory
+    let memory = &Store::Memory(MemoryStore::new());
+    save(memory, "/other", 3);
+    restore(memory, &target).unwrap();
+    assert_eq!(get_dir_stat(memory, Path::new("/data/a")).unwrap().unwrap().blocks, 10);
+    assert!(get_dir_stat(memory, Path::new("/other")).unwrap().is_none());
+}
+
+#[test]
+fn test_maintenance_checks() {
+    assert_eq!(check_table(Some(TABLE_DIR_STAT)).unwrap(), vec![TABLE_DIR_STAT]);
+    assert!(check_table(None).unwrap().len() > 1);
+    assert!(matches!(check_table(Some("nope")), Err(Error::NotFound(_))));
+
+    let db = &Store::Memory(MemoryStore::new());
+    compact(db, None).unwrap();
+    assert!(backup(db, &fresh("/tmp/test-maintenance-memory")).is_err());
+}
+
+#[test]
+fn test_queued_backup() {
+    get_tokio_runtime().block_on(async {
+        let db = Arc::new(open_store(Backend::RocksDB, Path::new("/tmp/test-maintenance-task.db"), true).unwrap());
+        save(&db, "/data", 1);
+        let task_manager = TaskManager::new(db.clone());
+        let mut rx = task_manager.subscribe();
+        let target = fresh("/tmp/test-maintenance-backup-2");
+        task_manager.send(Command::Backup(target.clone())).await.unwrap();
+
+        let mut statuses = Vec::new();
+        loop {
+            let event = tokio::time::timeout(Duration::from_secs(10), rx.recv())
+                .await
+                .expect("no backup event in time")
+                .unwrap();
+            assert_eq!((event.task, &event.path), ("backup", &target));
+            statuses.push(event.status);
+            if event.status != ProgressStatus::STARTED {
+                break;
+            }
+        }
+        assert_eq!(statuses, vec![ProgressStatus::STARTED, ProgressStatus::COMPLETED]);
+        assert_eq!(list_backups(db.as_ref()).len(), 1);
+    });
+}
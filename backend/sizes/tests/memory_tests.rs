@@ -0,0 +1,197 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use common::get_tokio_runtime;
+use sizes::cmd;
+use sizes::db::dirorder::{list_dirs, Direction, Orderby};
+use sizes::kvstore::memory::MemoryStore;
+use sizes::kvstore::{Batch, KvStore};
+use sizes::rocksdb::{RocksDBBuilder, StdColumnFamilyConfig};
+use sizes::scan::DirScanResult;
+
+mod common;
+
+fn walk(db: &impl KvStore, prefix: &str, limit: u32, rev: bool) -> Vec<String> {
+    let mut keys = Vec::new();
+    let visit = |k: &str, v: &[u8]| keys.push(format!("{}={}", k, String::from_utf8_lossy(v)));
+    if rev {
+        db.foreach_rev("t", prefix, limit, visit);
+    } else {
+        db.foreach("t", prefix, limit, visit);
+    }
+    keys
+}
+
+fn walk_until(db: &impl KvStore, prefix: &str, stop: usize) -> Vec<String> {
+    let mut keys = Vec::new();
+    db.foreach_while("t", prefix, |k, _| {
+        keys.push(k.to_string());
+        keys.len() < stop
+    });
+    keys
+}
+
+fn range(db: &impl KvStore, start: &str, end: &str, limit: u32) -> Vec<String> {
+    let mut keys = Vec::new();
+    db.foreach_range("t", start, end, limit, |k, _| keys.push(k.to_string()));
+    keys
+}
+
+#[test]
+fn test_same_order_as_rocksdb() {
+    let rocks = RocksDBBuilder::new("/tmp/test-memory-parity.db")
+        .with_column_family("t", StdColumnFamilyConfig::DEFAULT)
+        .truncate(true)
+        .build().unwrap();
+    let memory = MemoryStore::new();
+    for k in ["/a", "/a/b", "/a b", "/a/b/c", "/ab", "/b", "/\u{e9}", "/Z", ""] {
+        rocks.set("t", k, k.len().to_string()).unwrap();
+        memory.set("t", k, k.len().to_string()).unwrap();
+    }
+    rocks.delete("t", "/b").unwrap();
+    memory.delete("t", "/b").unwrap();
+
+    for prefix in ["", "/", "/a", "/a/", "/x"] {
+        for limit in [0, 2] {
+            for rev in [false, true] {
+                assert_eq!(walk(&memory, prefix, limit, rev), walk(&rocks, prefix, limit, rev), "{:?} {} {}", prefix, limit, rev);
+            }
+        }
+    }
+    assert_eq!(memory.get_string("t", "/a/b"), rocks.get_string("t", "/a/b"));
+    assert!(memory.get_bytes("t", "/b").is_none());
+    assert!(memory.get_bytes("missing", "/a").is_none());
+    assert_eq!(memory.len("t"), 8);
+}
+
+#[test]
+fn test_batch_same_as_rocksdb() {
+    let rocks = RocksDBBuilder::new("/tmp/test-memory-batch.db")
+        .with_column_family("t", StdColumnFamilyConfig::DEFAULT)
+        .truncate(true)
+        .build().unwrap();
+    let memory = MemoryStore::new();
+    for k in ["/a", "/b"] {
+        rocks.set("t", k, "old").unwrap();
+        memory.set("t", k, "old").unwrap();
+    }
+
+    // ops apply in order, a later op on the same key wins
+    let mut batch = Batch::new();
+    batch.set("t", "/a", "new");
+    batch.delete("t", "/b");
+    batch.set("t", "/c", "1");
+    batch.set("t", "/c", "2");
+    batch.delete("t", "/missing");
+    assert_eq!(batch.len(), 5);
+    rocks.write(batch.clone()).unwrap();
+    memory.write(batch).unwrap();
+
+    assert_eq!(walk(&memory, "", 0, false), vec!["/a=new", "/c=2"]);
+    assert_eq!(walk(&memory, "", 0, false), walk(&rocks, "", 0, false));
+
+    // a batch naming an unknown table writes nothing at all
+    let mut batch = Batch::new();
+    batch.set("t", "/d", "1");
+    batch.set("missing", "/d", "1");
+    assert!(rocks.write(batch).is_err());
+    assert!(rocks.get_bytes("t", "/d").is_none());
+}
+
+#[test]
+fn test_delete_prefix_same_as_rocksdb() {
+    let rocks = RocksDBBuilder::new("/tmp/test-memory-delete-prefix.db")
+        .with_column_family("t", StdColumnFamilyConfig::DEFAULT)
+        .truncate(true)
+        .build().unwrap();
+    let memory = MemoryStore::new();
+    for k in ["/a", "/a/b", "/a/b/c", "/ab", "/b", "/\u{e9}"] {
+        rocks.set("t", k, "1").unwrap();
+        memory.set("t", k, "1").unwrap();
+    }
+    rocks.delete_prefix("t", "/a/").unwrap();
+    memory.delete_prefix("t", "/a/").unwrap();
+    assert_eq!(walk(&memory, "", 0, false), vec!["/a=1", "/ab=1", "/b=1", "/\u{e9}=1"]);
+    assert_eq!(walk(&memory, "", 0, false), walk(&rocks, "", 0, false));
+
+    // the empty prefix empties the table
+    rocks.delete_prefix("t", "").unwrap();
+    memory.delete_prefix("t", "").unwrap();
+    assert!(walk(&rocks, "", 0, false).is_empty());
+    assert!(memory.is_empty("t"));
+}
+
+#[test]
+fn test_range_same_as_rocksdb() {
+    let rocks = RocksDBBuilder::new("/tmp/test-memory-range.db")
+        .with_column_family("t", StdColumnFamilyConfig::DEFAULT)
+        .truncate(true)
+        .build().unwrap();
+    let memory = MemoryStore::new();
+    for k in ["a", "b", "b1", "c", "d"] {
+        rocks.set("t", k, k).unwrap();
+        memory.set("t", k, k).unwrap();
+    }
+    for (start, end, limit) in [("b", "d", 0), ("", "z", 2), ("b1", "b1", 0), ("d", "a", 0), ("a0", "c0", 0)] {
+        assert_eq!(range(&memory, start, end, limit), range(&rocks, start, end, limit), "{:?} {:?} {}", start, end, limit);
+    }
+    assert_eq!(range(&memory, "b", "d", 0), vec!["b", "b1", "c"]);
+}
+
+#[test]
+fn test_while_same_as_rocksdb() {
+    let rocks = RocksDBBuilder::new("/tmp/test-memory-while.db")
+        .with_column_family("t", StdColumnFamilyConfig::DEFAULT)
+        .truncate(true)
+        .build().unwrap();
+    let memory = MemoryStore::new();
+    // more keys than the memory store copies out at once
+    for i in 0..600 {
+        let k = format!("k{:03}", i);
+        rocks.set("t", &k, "").unwrap();
+        memory.set("t", &k, "").unwrap();
+    }
+    rocks.set("t", "z", "").unwrap();
+    memory.set("t", "z", "").unwrap();
+
+    for stop in [1, 256, 300, 1000] {
+        let keys = walk_until(&memory, "k", stop);
+        assert_eq!(keys, walk_until(&rocks, "k", stop), "{}", stop);
+        assert_eq!(keys.len(), stop.min(600));
+    }
+}
+
+#[test]
+fn test_writes_inside_foreach() {
+    let memory = MemoryStore::new();
+    memory.set("t", "a", "1").unwrap();
+    memory.set("t", "b", "2").unwrap();
+    memory.foreach("t", "", 0, |k, v| memory.set("u", k, v).unwrap());
+    assert_eq!(memory.len("u"), 2);
+}
+
+#[test]
+fn test_ephemeral_scan() {
+    let root = PathBuf::from("/tmp/sizes-memory-scan");
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("big")).unwrap();
+    fs::create_dir_all(root.join("small")).unwrap();
+    fs::write(root.join("big/f"), vec![1u8; 64 * 1024]).unwrap();
+    fs::write(root.join("small/f"), vec![1u8; 10]).unwrap();
+
+    let store = Arc::new(MemoryStore::new());
+    let mut progress = DirScanResult::new();
+    get_tokio_runtime()
+        .block_on(cmd::scan_dir(store.clone(), &root, &mut progress))
+        .unwrap();
+    assert_eq!(progress.scanned.dirs, 2);
+    assert_eq!(progress.scanned.files, 2);
+    assert_eq!(progress.root, root);
+    assert!(progress.started_at > 0 && progress.finished_at >= progress.started_at);
+
+    let page = list_dirs(store.as_ref(), Orderby::Block, Direction::Desc, 0, 0, 1).unwrap();
+    assert_eq!(page.items.len(), 1);
+    assert!(page.has_more);
+    assert_eq!(page.items[0].path, root.join("big"));
+}
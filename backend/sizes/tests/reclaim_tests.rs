@@ -0,0 +1,76 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use common::get_tokio_runtime;
+use sizes::conf::{add_reclaim_rule, list_reclaim_rules, remove_reclaim_rule};
+use sizes::kvstore::memory::MemoryStore;
+use sizes::reclaim::{self, ReclaimRule};
+use sizes::scan::DirScanResult;
+use sizes::scandir::compute_dir_stats_recursive;
+
+mod common;
+
+fn path_rule(name: &str, path: &Path) -> ReclaimRule {
+    ReclaimRule {
+        name: name.to_string(),
+        dir_name: None,
+        paths: vec![path.to_string_lossy().to_string()],
+        markers: vec![],
+    }
+}
+
+#[test]
+fn test_reclaimable_report() {
+    let root = PathBuf::from("/tmp/sizes-reclaim");
+    let _ = fs::remove_dir_all(&root);
+    for dir in ["crate/target/debug", "plain/target", "web/node_modules/a/node_modules", "cache/pip"] {
+        fs::create_dir_all(root.join(dir)).unwrap();
+    }
+    fs::write(root.join("crate/Cargo.toml"), "[package]").unwrap();
+    fs::write(root.join("crate/target/debug/app"), vec![1u8; 8192]).unwrap();
+    fs::write(root.join("plain/target/keep"), vec![1u8; 8192]).unwrap();
+    fs::write(root.join("web/node_modules/a/index.js"), vec![1u8; 4096]).unwrap();
+    fs::write(root.join("web/node_modules/a/node_modules/b.js"), vec![1u8; 4096]).unwrap();
+    fs::write(root.join("cache/blob"), vec![1u8; 4096]).unwrap();
+    fs::write(root.join("cache/pip/wheel"), vec![1u8; 4096]).unwrap();
+
+    let db = &MemoryStore::new();
+    get_tokio_runtime().block_on(async {
+        let mut scan_result = DirScanResult::new();
+        compute_dir_stats_recursive(db, &root, &mut scan_result).await.unwrap();
+    });
+
+    add_reclaim_rule(db, &path_rule("test-cache", &root.join("cache"))).unwrap();
+    add_reclaim_rule(db, &path_rule("test-pip", &root.join("cache/pip"))).unwrap();
+    assert_eq!(list_reclaim_rules(db).len(), 2);
+
+    let report = reclaim::analyze(db, &root, &reclaim::all_rules(db));
+    let mut found: Vec<(String, PathBuf)> = report
+        .items
+        .iter()
+        .map(|item| (item.rule.clone(), item.path.clone()))
+        .collect();
+    found.sort();
+    assert_eq!(
+        found,
+        vec![
+            ("node-modules".to_string(), root.join("web/node_modules")),
+            ("rust-target".to_string(), root.join("crate/target")),
+            ("test-cache".to_string(), root.join("cache")),
+            ("test-pip".to_string(), root.join("cache/pip")),
+        ]
+    );
+
+    // the nested node_modules is part of the outer one
+    let node_modules = report.items.iter().find(|item| item.rule == "node-modules").unwrap();
+    assert_eq!(node_modules.project, root.join("web"));
+    assert_eq!(node_modules.overview.files, 2);
+
+    // pip is taken out of the cache it lives in
+    let cache = report.items.iter().find(|item| item.rule == "test-cache").unwrap();
+    assert_eq!(cache.overview.files, 1);
+    assert_eq!(report.bytes, report.items.iter().map(|item| item.bytes).sum::<u64>());
+
+    remove_reclaim_rule(db, "test-pip").unwrap();
+    assert_eq!(list_reclaim_rules(db).len(), 1);
+}
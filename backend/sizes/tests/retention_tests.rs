@@ -0,0 +1,97 @@
+use std::path::{Path, PathBuf};
+
+use sizes::config::RetentionConfig;
+use sizes::db::dirorder::{list_dirs, Direction, Orderby};
+use sizes::db::dirstat::{get_dir_stat, save_dir_stat, DirStat};
+use sizes::db::retention::{apply_retention, last_report, RetentionReport};
+use sizes::db::scanresult::{get_dir_scan_result, save_dir_scan_result};
+use sizes::kvstore::memory::MemoryStore;
+use sizes::scan::DirScanResult;
+
+const NOW: u64 = 1_700_000_000;
+const DAY: u64 = 24 * 3600;
+
+fn save_result(db: &MemoryStore, path: &str, days_ago: u64) {
+    let mut result = DirScanResult::new();
+    result.root = PathBuf::from(path);
+    result.finished_at = NOW - days_ago * DAY;
+    save_dir_scan_result(db, Path::new(path), &result).unwrap();
+}
+
+fn save_stat(db: &MemoryStore, path: &str, days_ago: u64, sub_dirs: &[&str]) {
+    let mut stat = DirStat::new(Path::new(path));
+    stat.ts = NOW - days_ago * DAY;
+    stat.blocks = 8;
+    stat.sub_dirs = sub_dirs.iter().map(PathBuf::from).collect();
+    save_dir_stat(db, Path::new(path), &stat).unwrap();
+}
+
+fn finished_days_ago(db: &MemoryStore, path: &str) -> Vec<u64> {
+    let results = get_dir_scan_result(db, Path::new(path), 0).unwrap();
+    results.iter().map(|r| (NOW - r.finished_at) / DAY).collect()
+}
+
+fn results_db() -> MemoryStore {
+    let db = MemoryStore::new();
+    for days_ago in [10, 5, 1, 0] {
+        save_result(&db, "/data", days_ago);
+    }
+    save_result(&db, "/data/a", 100);
+    db
+}
+
+#[test]
+fn test_keep_last_results() {
+    let db = &results_db();
+    let policy = RetentionConfig { scan_results: 2, ..Default::default() };
+    let report = apply_retention(db, &policy, NOW).unwrap();
+    assert_eq!(report, RetentionReport { ran_at: NOW, scan_results: 2, dirs: 0 });
+    assert_eq!(finished_days_ago(db, "/data"), vec![0, 1]);
+    assert_eq!(finished_days_ago(db, "/data/a"), vec![100]);
+    assert_eq!(last_report(db), Some(report));
+}
+
+#[test]
+fn test_expire_old_results() {
+    let db = &results_db();
+    let policy = RetentionConfig { max_age_days: 3, ..Default::default() };
+    assert_eq!(apply_retention(db, &policy, NOW).unwrap().scan_results, 2);
+    assert_eq!(finished_days_ago(db, "/data"), vec![0, 1]);
+    // the newest result of a dir stays, however old
+    assert_eq!(finished_days_ago(db, "/data/a"), vec![100]);
+
+    let nothing = RetentionConfig::default();
+    assert_eq!(apply_retention(db, &nothing, NOW).unwrap().scan_results, 0);
+}
+
+#[test]
+fn test_expire_stale_dirs() {
+    let db = &MemoryStore::new();
+    assert_eq!(last_report(db), None);
+    // unchanged since long ago, but reached by yesterday's scan of /data
+    save_result(db, "/data", 1);
+    save_stat(db, "/data", 90, &["a"]);
+    save_stat(db, "/data/a", 90, &["x"]);
+    save_stat(db, "/data/a/x", 90, &[]);
+    save_stat(db, "/data/new", 2, &[]);
+    // no longer listed by /data, so no scan reaches it or below
+    save_stat(db, "/data/gone", 90, &["y"]);
+    save_stat(db, "/data/gone/y", 90, &[]);
+    // a root scanned last long ago
+    save_result(db, "/old", 60);
+    save_stat(db, "/old", 60, &[]);
+
+    let policy = RetentionConfig { stale_dir_days: 30, ..Default::default() };
+    let report = apply_retention(db, &policy, NOW).unwrap();
+    assert_eq!(report.dirs, 3);
+    assert_eq!(report.scan_results, 0);
+    for path in ["/data", "/data/a", "/data/a/x", "/data/new"] {
+        assert!(get_dir_stat(db, Path::new(path)).unwrap().is_some(), "{} expired", path);
+    }
+    for path in ["/data/gone", "/data/gone/y", "/old"] {
+        assert!(get_dir_stat(db, Path::new(path)).unwrap().is_none(), "{} kept", path);
+    }
+    // the order index lost them as well
+    let page = list_dirs(db, Orderby::Block, Direction::Desc, 0, 0, 10).unwrap();
+    assert_eq!(page.items.len(), 4);
+}
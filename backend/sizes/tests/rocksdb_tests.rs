@@ -6,9 +6,9 @@ use sizes::rocksdb::{property, RocksDBBuilder};
 
 #[test]
 fn test_rocksdb_read_write() {
-    let db = RocksDBBuilder::new("/tmp/test.db")
+    let db = RocksDBBuilder::new("/tmp/test-rocksdb-rw.db")
         .truncate(true)
-        .build();
+        .build().unwrap();
 
     let table = DEFAULT_COLUMN_FAMILY_NAME;
     db.set(table, "k1", "this is some value").unwrap();
@@ -20,9 +20,9 @@ fn test_rocksdb_read_write() {
 
 #[test]
 fn test_prefix_read() {
-    let db = &RocksDBBuilder::new("/tmp/test.db")
+    let db = &RocksDBBuilder::new("/tmp/test-rocksdb-prefix.db")
         .truncate(true)
-        .build();
+        .build().unwrap();
     let table = DEFAULT_COLUMN_FAMILY_NAME;
 
 
@@ -36,7 +36,7 @@ fn test_prefix_read() {
 
 #[test]
 fn test_rocksdb_sizes() {
-    let db = RocksDBBuilder::new("/tmp/test.db")
-        .build();
+    let db = RocksDBBuilder::new("/tmp/test-rocksdb-sizes.db")
+        .build().unwrap();
     println!("{}", db.get_property(property::KEstimateNumKeys));
 }
\ No newline at end of file
@@ -12,11 +12,11 @@ fn test_scandir_recurisve() {
     let runtime = get_tokio_runtime();
 
     runtime.block_on(async {
-        let db = get_db(Path::new("/tmp/test.db"), false);
+        let db = get_db(Path::new("/tmp/test-scan.db"), false);
 
         let mut scan_result = DirScanResult::new();
         let res = compute_dir_stats_recursive(
-            &db,
+            db.as_ref(),
             Path::new("/Users/jiangzhaohua/tmp"),
             &mut scan_result
         ).await;
@@ -0,0 +1,65 @@
+use std::path::{Path, PathBuf};
+
+use sizes::db::scanresult::{
+    get_dir_scan_result, get_dir_scan_result_at, get_dir_scan_results_between, get_last_dir_scan_result,
+    save_dir_scan_result,
+};
+use sizes::kvstore::memory::MemoryStore;
+use sizes::scan::DirScanResult;
+
+fn save(db: &MemoryStore, path: &str, finished_at: u64, blocks: u64) {
+    let mut result = DirScanResult::new();
+    result.root = PathBuf::from(path);
+    result.started_at = finished_at - 5;
+    result.finished_at = finished_at;
+    result.scanned.blocks = blocks;
+    result.ongoing = false;
+    save_dir_scan_result(db, Path::new(path), &result).unwrap();
+}
+
+fn finished(results: &[DirScanResult]) -> Vec<u64> {
+    results.iter().map(|r| r.finished_at).collect()
+}
+
+#[test]
+fn test_newest_first() {
+    let db = &MemoryStore::new();
+    // saved out of order, 9 and 10 digit times side by side
+    for ts in [1_700_000_000, 999_999_999, 1_800_000_000, 1_750_000_000] {
+        save(db, "/data", ts, ts / 1000);
+    }
+    save(db, "/data/a", 1_900_000_000, 1);
+
+    let all = get_dir_scan_result(db, Path::new("/data"), 0).unwrap();
+    assert_eq!(finished(&all), vec![1_800_000_000, 1_750_000_000, 1_700_000_000, 999_999_999]);
+    assert_eq!(all[0].root, PathBuf::from("/data"));
+    assert_eq!(all[0].started_at, 1_800_000_000 - 5);
+
+    let last = get_last_dir_scan_result(db, Path::new("/data")).unwrap();
+    assert_eq!(last.finished_at, 1_800_000_000);
+    assert_eq!(finished(&get_dir_scan_result(db, Path::new("/data"), 2).unwrap()), vec![1_800_000_000, 1_750_000_000]);
+}
+
+#[test]
+fn test_time_range_and_at() {
+    let db = &MemoryStore::new();
+    for ts in [100, 200, 300, 400] {
+        save(db, "/data", ts, ts);
+    }
+    save(db, "/data/a", 250, 1);
+    let path = Path::new("/data");
+
+    // both ends inclusive
+    assert_eq!(finished(&get_dir_scan_results_between(db, path, 200, 300, 0).unwrap()), vec![300, 200]);
+    assert_eq!(finished(&get_dir_scan_results_between(db, path, 0, u64::MAX, 0).unwrap()), vec![400, 300, 200, 100]);
+    assert_eq!(finished(&get_dir_scan_results_between(db, path, 150, u64::MAX, 1).unwrap()), vec![400]);
+    assert!(get_dir_scan_results_between(db, path, 301, 399, 0).unwrap().is_empty());
+    assert!(get_dir_scan_results_between(db, path, 300, 200, 0).unwrap().is_empty());
+
+    assert_eq!(get_dir_scan_result_at(db, path, 300).unwrap().finished_at, 300);
+    assert_eq!(get_dir_scan_result_at(db, path, 399).unwrap().finished_at, 300);
+    assert_eq!(get_dir_scan_result_at(db, path, u64::MAX).unwrap().finished_at, 400);
+    assert!(get_dir_scan_result_at(db, path, 99).is_none());
+    // a descendant's results are not the dir's
+    assert!(get_dir_scan_result_at(db, Path::new("/data/a"), 249).is_none());
+}
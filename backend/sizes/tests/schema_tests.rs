@@ -0,0 +1,82 @@
+use std::path::Path;
+
+use sizes::cleanup;
+use sizes::conf::{add_watch, list_watch, WatchDirectoryConfiguration};
+use sizes::db::children::list_children;
+use sizes::db::dirorder::{Direction, Orderby};
+use sizes::db::dirstat::{get_dir_stat, save_dir_stat, DirStat};
+use sizes::db::key::path_key;
+use sizes::db::schema::{ensure_schema, migrations, schema_version};
+use sizes::db::{TABLE_CONF, TABLE_DIR_STAT};
+use sizes::kvstore::memory::MemoryStore;
+use sizes::kvstore::KvStore;
+use sizes::Error;
+
+fn latest() -> u32 {
+    migrations::<MemoryStore>().last().unwrap().version
+}
+
+#[test]
+fn test_migrations_in_order() {
+    let versions: Vec<u32> = migrations::<MemoryStore>().iter().map(|m| m.version).collect();
+    assert_eq!(versions, (1..=versions.len() as u32).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_new_database_is_latest() {
+    let db = &MemoryStore::new();
+    assert_eq!(schema_version(db).unwrap(), 0);
+    assert_eq!(ensure_schema(db).unwrap(), latest());
+    assert_eq!(schema_version(db).unwrap(), latest());
+    assert_eq!(ensure_schema(db).unwrap(), latest());
+}
+
+#[test]
+fn test_migrations_run_again() {
+    let db = &MemoryStore::new();
+    let mut stat = DirStat::new(Path::new("/data"));
+    stat.blocks = 3;
+    save_dir_stat(db, Path::new("/data"), &stat).unwrap();
+    // moved already but without a version, e.g. cut short before it was set
+    assert_eq!(schema_version(db).unwrap(), 0);
+    assert_eq!(ensure_schema(db).unwrap(), latest());
+    assert_eq!(get_dir_stat(db, Path::new("/data")).unwrap().unwrap().blocks, 3);
+}
+
+#[test]
+fn test_refuse_newer_schema() {
+    let db = &MemoryStore::new();
+    db.set(TABLE_CONF, "schema_version", (latest() + 1).to_string()).unwrap();
+    assert!(matches!(ensure_schema(db), Err(Error::Storage(_))));
+    db.set(TABLE_CONF, "schema_version", "x").unwrap();
+    assert!(matches!(ensure_schema(db), Err(Error::Decode(_))));
+}
+
+#[test]
+fn test_decode_failures_are_errors() {
+    let db = &MemoryStore::new();
+    db.set(TABLE_CONF, "watches", r#"[{"path": "/data"}]"#).unwrap();
+    assert!(matches!(db.get_as::<Vec<WatchDirectoryConfiguration>>(TABLE_CONF, "watches"), Err(Error::Decode(_))));
+    assert!(db.get_as::<Vec<WatchDirectoryConfiguration>>(TABLE_CONF, "missing").unwrap().is_none());
+
+    // readers see none, a change leaves the list alone
+    assert!(list_watch(db).is_empty());
+    let watch = WatchDirectoryConfiguration {
+        refresh_interval: "1h".to_string(),
+        label: "data".to_string(),
+        path: "/data".to_string(),
+    };
+    assert!(matches!(add_watch(db, &watch), Err(Error::Decode(_))));
+    assert_eq!(db.get_string(TABLE_CONF, "watches"), r#"[{"path": "/data"}]"#);
+
+    db.set(TABLE_CONF, "cleanup,t", "{}").unwrap();
+    assert!(cleanup::confirm(db, "t").is_err());
+
+    let path = Path::new("/data");
+    db.set(TABLE_DIR_STAT, path_key(path), [1, 0x80]).unwrap();
+    assert!(matches!(get_dir_stat(db, path), Err(Error::Decode(_))));
+    // a dir that does not decode is no dir that was never scanned
+    let listed = list_children(db, path, Orderby::Block, Direction::Desc, 0, 10);
+    assert!(matches!(listed, Err(Error::Decode(_))));
+    assert!(matches!(DirStat::decode(path, &[1, 0x80]), Err(Error::Decode(_))));
+}
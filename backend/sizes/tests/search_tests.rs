@@ -0,0 +1,121 @@
+use std::path::{Path, PathBuf};
+
+use sizes::db::dirorder::{Direction, Orderby};
+use sizes::db::dirstat::{delete_dir_stat, save_dir_stat, DirStat};
+use sizes::db::search::{ensure_name_index, glob_to_regex, search, MatchMode, MatchTarget, SearchQuery};
+use sizes::db::TABLE_DIR_STAT;
+use sizes::kvstore::memory::MemoryStore;
+use sizes::kvstore::KvStore;
+
+fn stat(path: &str, blocks: u64, mtime: i64) -> DirStat {
+    let mut stat = DirStat::new(Path::new(path));
+    stat.blocks = blocks;
+    stat.mtime = mtime;
+    stat
+}
+
+// smallest first, so dirs of the same size come in the order of their names
+fn query(pattern: &str, mode: MatchMode) -> SearchQuery {
+    SearchQuery { pattern: pattern.to_string(), mode, direction: Direction::Asc, ..SearchQuery::default() }
+}
+
+fn found(db: &MemoryStore, query: &SearchQuery) -> Vec<PathBuf> {
+    search(db, query).unwrap().items.into_iter().map(|s| s.path).collect()
+}
+
+fn paths(paths: &[&str]) -> Vec<PathBuf> {
+    paths.iter().map(PathBuf::from).collect()
+}
+
+#[test]
+fn test_search() {
+    let db = &MemoryStore::new();
+    for s in [
+        stat("/home/me/Backups-2023", 100, 1_700_000_000),
+        stat("/home/me/backups-2024", 10, 1_710_000_000),
+        stat("/mnt/old/backups-2023", 1000, 1_600_000_000),
+        stat("/home/me/projects", 50, 1_700_000_000),
+        stat("/home/me/projects/backups", 5, 1_700_000_000),
+    ] {
+        save_dir_stat(db, &s.path.clone(), &s).unwrap();
+    }
+
+    assert_eq!(
+        found(db, &query("2023", MatchMode::Substring)),
+        paths(&["/home/me/Backups-2023", "/mnt/old/backups-2023"])
+    );
+
+    let mut q = query("backups-2023", MatchMode::Substring);
+    q.case_sensitive = true;
+    assert_eq!(found(db, &q), paths(&["/mnt/old/backups-2023"]));
+
+    assert_eq!(
+        found(db, &query("backups-20*", MatchMode::Glob)),
+        paths(&["/home/me/backups-2024", "/home/me/Backups-2023", "/mnt/old/backups-2023"])
+    );
+    // globs match the whole name
+    assert!(found(db, &query("backup", MatchMode::Glob)).is_empty());
+    assert_eq!(found(db, &query("backups-202[!3]", MatchMode::Glob)), paths(&["/home/me/backups-2024"]));
+    assert_eq!(found(db, &query("^backups$", MatchMode::Regex)), paths(&["/home/me/projects/backups"]));
+    assert!(search(db, &query("(unclosed", MatchMode::Regex)).is_err());
+
+    let mut q = query("/home/*/backups*", MatchMode::Glob);
+    q.target = MatchTarget::Path;
+    assert_eq!(found(db, &q), paths(&["/home/me/backups-2024", "/home/me/Backups-2023"]));
+    q.pattern = "/home/**/backups*".to_string();
+    assert_eq!(found(db, &q).len(), 3);
+
+    let mut q = query("backups", MatchMode::Substring);
+    q.min_blocks = Some(50);
+    q.modified_after = Some(1_650_000_000);
+    assert_eq!(found(db, &q), paths(&["/home/me/Backups-2023"]));
+
+    // the bounds are on the whole subtree, projects holds 50 blocks and 5 more below it
+    let mut q = query("projects", MatchMode::Substring);
+    q.min_blocks = Some(55);
+    assert_eq!(found(db, &q), paths(&["/home/me/projects"]));
+    q.max_blocks = Some(54);
+    q.min_blocks = None;
+    assert!(found(db, &q).is_empty());
+
+    // a page of the matches, with the total of all of them
+    let mut q = query("backups", MatchMode::Substring);
+    q.orderby = Orderby::Block;
+    q.direction = Direction::Desc;
+    q.offset = 1;
+    q.limit = 2;
+    let page = search(db, &q).unwrap();
+    assert_eq!(page.total, Some(4));
+    assert!(page.has_more);
+    assert_eq!(page.items.iter().map(|s| s.blocks).collect::<Vec<_>>(), vec![100, 10]);
+
+    // the root has no name of its own
+    save_dir_stat(db, Path::new("/"), &stat("/", 1, 1_700_000_000)).unwrap();
+    let mut q = query("^/$", MatchMode::Regex);
+    q.target = MatchTarget::Path;
+    assert_eq!(found(db, &q), paths(&["/"]));
+    delete_dir_stat(db, Path::new("/")).unwrap();
+    assert!(found(db, &q).is_empty());
+
+    delete_dir_stat(db, Path::new("/mnt/old/backups-2023")).unwrap();
+    assert_eq!(found(db, &query("2023", MatchMode::Substring)), paths(&["/home/me/Backups-2023"]));
+}
+
+#[test]
+fn test_ensure_name_index_on_old_db() {
+    let db = &MemoryStore::new();
+    // written the way stats were saved before there was an index
+    db.set_json(TABLE_DIR_STAT, "/x/photos", &stat("/x/photos", 1, 0)).unwrap();
+    assert!(found(db, &query("photos", MatchMode::Substring)).is_empty());
+
+    ensure_name_index(db).unwrap();
+    assert_eq!(found(db, &query("photos", MatchMode::Substring)), paths(&["/x/photos"]));
+}
+
+#[test]
+fn test_glob_to_regex() {
+    assert_eq!(glob_to_regex("a*.b?"), r"^a[^/]*\.b[^/]$");
+    assert_eq!(glob_to_regex("/home/**/x"), "^/home/.*/x$");
+    assert_eq!(glob_to_regex("[!0-9]x"), "^[^0-9]x$");
+    assert_eq!(glob_to_regex("[unclosed"), r"^\[unclosed$");
+}
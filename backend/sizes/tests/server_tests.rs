@@ -0,0 +1,166 @@
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::asynchronous::Client as LocalClient;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use common::get_tokio_runtime;
+use sizes::db::dirstat::{save_dir_stat, DirStat};
+use sizes::db::key::path_key;
+use sizes::db::TABLE_DIR_STAT;
+use sizes::kvstore::KvStore;
+use sizes::server::auth::{self, ApiToken};
+use sizes::server::{self, AppState, ServerOptions};
+
+mod common;
+
+async fn test_client(db: &str, token_file: &str) -> (LocalClient, ApiToken) {
+    let _ = fs::remove_file(token_file);
+    let options = ServerOptions {
+        token_file: Some(PathBuf::from(token_file)),
+        ..ServerOptions::default()
+    };
+    let client = sizes::init_with(Path::new(db)).await;
+    let client = LocalClient::untracked(server::build(client, &options)).await.unwrap();
+    let token = auth::api_token(&options).unwrap();
+    (client, token)
+}
+
+fn bearer(token: &ApiToken) -> Header<'static> {
+    Header::new("Authorization", format!("Bearer {}", token.0))
+}
+
+async fn get_json(client: &LocalClient, token: &ApiToken, uri: &str) -> (Status, Value) {
+    let response = client.get(uri).header(bearer(token)).dispatch().await;
+    let status = response.status();
+    let body = response.into_json::<Value>().await.unwrap_or(Value::Null);
+    (status, body)
+}
+
+#[test]
+fn test_error_responses() {
+    get_tokio_runtime().block_on(async {
+        let (client, token) = test_client("/tmp/test-server-errors.db", "/tmp/test-server-errors.token").await;
+
+        let (status, body) = get_json(&client, &token, "/sizes/api/scan?path=relative/dir").await;
+        assert_eq!(status, Status::BadRequest);
+        assert_eq!(body["code"], "invalid_path");
+        assert_eq!(body["details"]["path"], "relative/dir");
+
+        let (status, body) = get_json(&client, &token, "/sizes/api/export?path=/never/scanned").await;
+        assert_eq!(status, Status::NotFound);
+        assert_eq!(body["code"], "not_found");
+
+        let (status, body) = get_json(&client, &token, "/sizes/api/export?path=/never/scanned&format=xml").await;
+        assert_eq!(status, Status::BadRequest);
+        assert_eq!(body["code"], "other");
+        assert!(body["message"].as_str().unwrap().contains("xml"));
+
+        let response = client.post("/sizes/api/cleanup/confirm?token=nope").header(bearer(&token)).dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+
+        let (status, body) = get_json(&client, &token, "/sizes/api/no/such/route").await;
+        assert_eq!(status, Status::NotFound);
+        assert_eq!(body["code"], "not_found");
+
+        let response = client
+            .post("/sizes/api/watches/add")
+            .header(ContentType::JSON)
+            .header(bearer(&token))
+            .body(r#"{"label": "no path"}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        let body = response.into_json::<Value>().await.unwrap();
+        assert_eq!(body["code"], "other");
+
+        let (status, body) = get_json(&client, &token, "/sizes/api/watches").await;
+        assert_eq!(status, Status::Ok);
+        assert!(body.is_array());
+    });
+}
+
+#[test]
+fn test_api_token_and_cors() {
+    get_tokio_runtime().block_on(async {
+        let (client, token) = test_client("/tmp/test-server-auth.db", "/tmp/test-server-auth.token").await;
+        assert_eq!(token.0.len(), 64);
+        assert!(token.matches(&token.0));
+        assert!(!token.matches(&token.0[1..]));
+        // the token is kept across starts
+        assert_eq!(fs::read_to_string("/tmp/test-server-auth.token").unwrap(), token.0);
+
+        let response = client.get("/sizes/api/watches").dispatch().await;
+        assert_eq!(response.status(), Status::Unauthorized);
+        let body = response.into_json::<Value>().await.unwrap();
+        assert_eq!(body["code"], "unauthorized");
+
+        let response = client
+            .get("/sizes/api/watches")
+            .header(Header::new("Authorization", "Bearer wrong"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        // the prefix check sees the path the way routing does
+        for uri in ["/sizes//api/config", "/sizes/%61pi/watches", "//sizes/api/watches"] {
+            assert_eq!(client.get(uri).dispatch().await.status(), Status::Unauthorized, "{}", uri);
+        }
+
+        // a rejected request never reaches its handler
+        let response = client.post("/sizes/api/cleanup/confirm?token=nope").dispatch().await;
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        let (status, _) = get_json(&client, &token, "/sizes/api/watches").await;
+        assert_eq!(status, Status::Ok);
+
+        let uri = format!("/sizes/api/watches?{}={}", auth::TOKEN_PARAM, token.0);
+        assert_eq!(client.get(uri).dispatch().await.status(), Status::Ok);
+
+        let response = client
+            .get("/sizes/api/watches")
+            .header(bearer(&token))
+            .header(Header::new("Origin", "tauri://localhost"))
+            .dispatch()
+            .await;
+        assert_eq!(response.headers().get_one("Access-Control-Allow-Origin"), Some("tauri://localhost"));
+
+        let response = client
+            .get("/sizes/api/watches")
+            .header(bearer(&token))
+            .header(Header::new("Origin", "https://evil.example"))
+            .dispatch()
+            .await;
+        assert_eq!(response.headers().get_one("Access-Control-Allow-Origin"), None);
+
+        let response = client
+            .options("/sizes/api/scan")
+            .header(Header::new("Origin", "https://tauri.localhost"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NoContent);
+        assert_eq!(response.headers().get_one("Access-Control-Allow-Origin"), Some("https://tauri.localhost"));
+        assert!(response.headers().get_one("Access-Control-Allow-Headers").unwrap().contains("Authorization"));
+    });
+}
+
+#[test]
+fn test_export_fails_mid_stream() {
+    get_tokio_runtime().block_on(async {
+        let (client, token) = test_client("/tmp/test-server-export.db", "/tmp/test-server-export.token").await;
+        let db = client.rocket().state::<AppState>().unwrap().client.db.as_ref();
+        let mut root = DirStat::new(Path::new("/export"));
+        root.sub_dirs = vec!["bad".into()];
+        save_dir_stat(db, Path::new("/export"), &root).unwrap();
+        let response = client.get("/sizes/api/export?path=/export&format=csv").header(bearer(&token)).dispatch().await;
+        let body = response.into_string().await.unwrap();
+        assert!(body.starts_with("path,blocks,files,dirs,mtime\n/export,"));
+
+        db.set(TABLE_DIR_STAT, path_key(Path::new("/export/bad")), [1, 0x80]).unwrap();
+
+        // the stat of the child only fails once the body is streaming, which must not look complete
+        let response = client.get("/sizes/api/export?path=/export&format=csv").header(bearer(&token)).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        assert!(response.into_bytes().await.is_none());
+    });
+}
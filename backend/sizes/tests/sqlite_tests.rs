@@ -0,0 +1,106 @@
+#![cfg(feature = "sqlite")]
+
+use std::fs;
+use std::path::Path;
+
+use sizes::db::dirorder::{list_dirs, Direction, Orderby};
+use sizes::db::dirstat::{save_dir_stat, DirStat};
+use sizes::db::migrate::migrate;
+use sizes::db::{all_tables, open_store, Backend, TABLE_DIR_STAT};
+use sizes::kvstore::memory::MemoryStore;
+use sizes::kvstore::{Batch, KvStore};
+use sizes::sqlite::SqliteStore;
+
+fn walk(db: &impl KvStore, prefix: &str, limit: u32, rev: bool) -> Vec<String> {
+    let mut keys = Vec::new();
+    let visit = |k: &str, v: &[u8]| keys.push(format!("{}={}", k, String::from_utf8_lossy(v)));
+    if rev {
+        db.foreach_rev("t", prefix, limit, visit);
+    } else {
+        db.foreach("t", prefix, limit, visit);
+    }
+    keys
+}
+
+#[test]
+fn test_same_order_as_memory() {
+    let sqlite = SqliteStore::open(Path::new("/tmp/test-sqlite-parity.sqlite"), &["t"], true).unwrap();
+    let memory = MemoryStore::new();
+    // more keys than one page of a walk
+    for i in 0..2500 {
+        let k = format!("/k/{:05}", i);
+        sqlite.set("t", &k, i.to_string()).unwrap();
+        memory.set("t", &k, i.to_string()).unwrap();
+    }
+    for k in ["/a", "/a/b", "/a b", "/ab", "/\u{e9}", "/Z", ""] {
+        sqlite.set("t", k, k).unwrap();
+        memory.set("t", k, k).unwrap();
+    }
+    sqlite.delete("t", "/ab").unwrap();
+    memory.delete("t", "/ab").unwrap();
+
+    for prefix in ["", "/", "/a", "/k/", "/k/01", "/x"] {
+        for limit in [0, 3, 1500] {
+            for rev in [false, true] {
+                assert_eq!(walk(&sqlite, prefix, limit, rev), walk(&memory, prefix, limit, rev), "{:?} {} {}", prefix, limit, rev);
+            }
+        }
+    }
+    assert_eq!(sqlite.get_string("t", "/a/b"), "/a/b");
+    assert!(sqlite.get_bytes("t", "/ab").is_none());
+
+    // writing while walking does not block on the connection
+    sqlite.foreach("t", "/a", 0, |k, v| sqlite.set("t", format!("{}!", k), v).unwrap());
+    assert_eq!(sqlite.get_string("t", "/a/b!"), "/a/b");
+}
+
+#[test]
+fn test_batch_is_one_transaction() {
+    let sqlite = SqliteStore::open(Path::new("/tmp/test-sqlite-batch.sqlite"), &["t"], true).unwrap();
+    sqlite.set("t", "/a", "old").unwrap();
+
+    let mut batch = Batch::new();
+    batch.set("t", "/a", "new");
+    batch.set("t", "/b", "1");
+    batch.delete("t", "/b");
+    batch.set("t", "/c", "1");
+    sqlite.write(batch).unwrap();
+    assert_eq!(walk(&sqlite, "", 0, false), vec!["/a=new", "/c=1"]);
+
+    // a failing op rolls back the ones before it
+    let mut batch = Batch::new();
+    batch.set("t", "/d", "1");
+    batch.set("missing", "/d", "1");
+    assert!(sqlite.write(batch).is_err());
+    assert!(sqlite.get_bytes("t", "/d").is_none());
+}
+
+#[test]
+fn test_migrate_between_backends() {
+    let rocks_path = Path::new("/tmp/test-migrate-src.db");
+    let sqlite_path = Path::new("/tmp/test-migrate.sqlite");
+    let back_path = Path::new("/tmp/test-migrate-back.db");
+    let _ = fs::remove_file(sqlite_path);
+    let _ = fs::remove_dir_all(back_path);
+    {
+        let src = open_store(Backend::RocksDB, rocks_path, true).unwrap();
+        for (path, blocks) in [("/m/a", 3), ("/m/b", 7), ("/m/c", 5)] {
+            let mut stat = DirStat::new(Path::new(path));
+            stat.blocks = blocks;
+            save_dir_stat(&src, Path::new(path), &stat).unwrap();
+        }
+    }
+    assert_eq!(Backend::detect(rocks_path), Some(Backend::RocksDB));
+
+    let counts = migrate(rocks_path, sqlite_path, Backend::Sqlite).unwrap();
+    assert_eq!(counts.len(), all_tables().len());
+    assert!(counts.contains(&(TABLE_DIR_STAT.to_string(), 3)));
+    assert_eq!(Backend::detect(sqlite_path), Some(Backend::Sqlite));
+    assert!(migrate(rocks_path, sqlite_path, Backend::Sqlite).is_err());
+
+    migrate(sqlite_path, back_path, Backend::RocksDB).unwrap();
+    let back = open_store(Backend::RocksDB, back_path, false).unwrap();
+    let page = list_dirs(&back, Orderby::Block, Direction::Desc, 0, 0, 10).unwrap();
+    let blocks: Vec<u64> = page.items.iter().map(|s| s.blocks).collect();
+    assert_eq!(blocks, vec![7, 5, 3]);
+}
@@ -0,0 +1,45 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use common::get_tokio_runtime;
+use sizes::cmd::{Command, ProgressStatus, TaskManager};
+use sizes::db::DbRegistry;
+
+mod common;
+
+#[test]
+fn test_scan_events() {
+    let root = PathBuf::from("/tmp/sizes-task-events");
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("a/b")).unwrap();
+    fs::write(root.join("a/f1"), vec![1u8; 4096]).unwrap();
+
+    get_tokio_runtime().block_on(async {
+        let db = DbRegistry::new().open(Path::new("/tmp/test-task-events.db"), true).unwrap();
+        let task_manager = TaskManager::new(db);
+        let mut rx = task_manager.subscribe();
+        task_manager.send(Command::ScanDir(root.clone())).await.unwrap();
+
+        let mut statuses = Vec::new();
+        let last = loop {
+            let event = tokio::time::timeout(Duration::from_secs(10), rx.recv())
+                .await
+                .expect("no scan event in time")
+                .unwrap();
+            assert_eq!(event.path, root);
+            statuses.push(event.status);
+            if event.status != ProgressStatus::STARTED && event.status != ProgressStatus::RUNNING {
+                break event;
+            }
+        };
+
+        assert_eq!(statuses.first(), Some(&ProgressStatus::STARTED));
+        assert_eq!(last.status, ProgressStatus::COMPLETED);
+        assert!(!last.result.ongoing);
+        assert_eq!(last.result.scanned.dirs, 2);
+        assert_eq!(last.result.scanned.files, 1);
+
+        assert!(task_manager.abort(&root).await.is_err());
+    });
+}
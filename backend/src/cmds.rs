@@ -1,4 +1,12 @@
+use sizes::server::auth::ApiToken;
+
 #[derive(Clone, serde::Serialize)]
 pub(crate) struct Notification {
     pub message: String,
 }
+
+// the webview sends it as `Authorization: Bearer <token>` on every api call
+#[tauri::command]
+pub(crate) fn api_token(token: tauri::State<'_, ApiToken>) -> String {
+    token.0.clone()
+}
@@ -1,28 +1,17 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use rocket::routes;
-use std::env;
 use std::error::Error;
-use rocket::fs::FileServer;
-use tauri::{App, AppHandle};
+use std::path::PathBuf;
+use tauri::{App, Manager};
 
-use crate::controller::{
-    add_watch_dir, dir_results, get_dir_stat, get_largest, list_watch_dir, remove_watch_dir,
-    scan_dir, scan_dir_progress, scan_dir_results
-};
-use sizes::Client;
+use sizes::{config, server};
 
 mod cmds;
-mod controller;
-
-struct AppState {
-    client: Client,
-    handle: AppHandle,
-}
 
 fn main() {
     tauri::Builder::default()
+        .invoke_handler(tauri::generate_handler![cmds::api_token])
         .setup(setup)
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -30,36 +19,20 @@ fn main() {
 
 fn setup(app: &mut App) -> Result<(), Box<dyn Error>> {
     let handle = app.handle().clone();
+    let mut options = config::get().server.clone();
+    if tauri::is_dev() && options.static_dir.is_none() {
+        options.static_dir = Some(PathBuf::from("../frontend/composeApp/build/dist/wasmJs/developmentExecutable/"));
+    }
+    // created here so the webview can ask for it before the server is up
+    app.manage(server::auth::api_token(&options)?);
+
     tauri::async_runtime::spawn(async move {
         let client = sizes::init().await;
-        let rocket_builder = rocket::build()
-            .manage(AppState { client, handle })
-            .mount(
-                "/sizes",
-                routes![
-                    scan_dir,
-                    scan_dir_progress,
-                    scan_dir_results,
-                    list_watch_dir,
-                    add_watch_dir,
-                    remove_watch_dir,
-                    dir_results,
-                    get_largest,
-                    get_dir_stat
-                ]
-            );
-
-        if tauri::is_dev() {
-            rocket_builder.mount("/", FileServer::from("../frontend/composeApp/build/dist/wasmJs/developmentExecutable/"))
-                .launch()
-                .await
-                .unwrap();
-        } else {
-            rocket_builder
-                .launch()
-                .await
-                .unwrap();
-        }
+        server::build(client, &options)
+            .manage(handle)
+            .launch()
+            .await
+            .unwrap();
     });
     println!("setup finished");
     Ok(())